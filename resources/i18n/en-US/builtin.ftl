@@ -8,3 +8,22 @@ Save = Save
 Reconnect = Reconnect
 Disconnect = Disconnect
 Connect = Connect
+SaveTemplate = Save Template
+Repeat = Repeat
+Probe = Probe
+Stop = Stop
+Request = Request
+Scan = Scan
+Clear = Clear
+Confirm = Confirm
+Cancel = Cancel
+Run = Run
+AddRule = Add Rule
+Bridge = Bridge
+Start = Start
+Unlock = Unlock
+SetMasterPassword = Set
+Export = Export
+Import = Import
+AddEnvironment = Add
+Copy = Copy
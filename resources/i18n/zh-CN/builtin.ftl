@@ -7,3 +7,22 @@ Save = 保存
 Reconnect = 重连
 Disconnect = 断开
 Connect = 连接
+SaveTemplate = 保存模板
+Repeat = 重复发布
+Probe = 延迟探测
+Stop = 停止
+Request = 请求
+Scan = 扫描
+Clear = 清除
+Confirm = 确认
+Cancel = 取消
+Run = 运行
+AddRule = 添加规则
+Bridge = 桥接
+Start = 启动
+Unlock = 解锁
+SetMasterPassword = 设置
+Export = 导出
+Import = 导入
+AddEnvironment = 添加
+Copy = 复制
@@ -0,0 +1,526 @@
+//! Loopback-only HTTP/JSON api to drive the running client, started when `FOR_MQTT_HTTP_PORT` is set.
+//!
+//! - `GET  /brokers`
+//! - `POST /brokers/{id}/connect`, `POST /brokers/{id}/disconnect`
+//! - `POST /brokers/{id}/subscribe`   `{"topic": "a/#", "qos": 1}`
+//! - `POST /brokers/{id}/unsubscribe` `{"topic": "a/#"}`
+//! - `POST /brokers/{id}/publish`     `{"topic": "a/b", "msg": "hello", "qos": 1, "retain": false}`
+//! - `GET  /messages`: server-sent events of the received messages
+//!
+//! `{id}` is the `id` or the `uuid` of `GET /brokers`; the `id` changes between the starts, the `uuid` doesn't.
+//!
+//! Every request carries `Authorization: Bearer <token>`, the token is generated at every start
+//! and written to `http-token` in the data dir. The `Host` must be `127.0.0.1:<port>` or
+//! `localhost:<port>`, and the body of `POST` must be `Content-Type: application/json`,
+//! so that the web pages opened by the user can't drive the client.
+//!
+//! The requests are translated into `AppEvent`s; reading the state is done in an idle callback of druid.
+//! They are served by `WORKERS` threads, the streams of `GET /messages` by a thread each,
+//! at most `MAX_STREAMS`: the others are refused with 503. A stream that can't keep up
+//! with the messages is closed.
+use crate::data::common::{PublicInput, QoS, SubscribeFilter, SubscribeInput, SubscribeMsg};
+use crate::data::hierarchy::AppData;
+use crate::data::AppEvent;
+use crate::session::{SessionEvent, SessionObserver};
+use crate::ui::formatter::parse_to_qos;
+use anyhow::{anyhow, bail, Result};
+use crossbeam_channel::{bounded, Sender};
+use druid::im::vector;
+use druid::ExtEventSink;
+use log::{debug, error, info};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tiny_http::{Header, Method, Request, Response, Server};
+use uuid::Uuid;
+
+pub const HTTP_PORT_ENV: &str = "FOR_MQTT_HTTP_PORT";
+pub const TOKEN_FILE: &str = "http-token";
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+/// the threads serving the requests, except the streams of `GET /messages`
+const WORKERS: usize = 4;
+/// the requests waiting for a worker
+const QUEUE_SIZE: usize = 64;
+/// the streams of `GET /messages` at the same time
+const MAX_STREAMS: usize = 8;
+/// the messages waiting to be written to a stream
+const STREAM_QUEUE: usize = 1024;
+
+/// what every request is checked against
+struct Access {
+    port: u16,
+    token: String,
+}
+
+impl Access {
+    /// the status and the error if the request is refused
+    fn check(&self, method: &Method, headers: &[Header]) -> Option<(u16, &'static str)> {
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|x| x.field.equiv(name))
+                .map(|x| x.value.as_str())
+        };
+        let local = [
+            format!("127.0.0.1:{}", self.port),
+            format!("localhost:{}", self.port),
+        ];
+        if !header("Host").map_or(false, |x| local.iter().any(|local| local == x)) {
+            return Some((403, "invalid host"));
+        }
+        if let Some(origin) = header("Origin") {
+            if !local.iter().any(|x| origin == format!("http://{}", x)) {
+                return Some((403, "invalid origin"));
+            }
+        }
+        let authorized = header("Authorization")
+            .and_then(|x| x.strip_prefix("Bearer "))
+            .map_or(false, |x| {
+                constant_time_eq(x.as_bytes(), self.token.as_bytes())
+            });
+        if !authorized {
+            return Some((401, "unauthorized"));
+        }
+        if *method == Method::Post {
+            let json = header("Content-Type").map_or(false, |x| {
+                x.split(';')
+                    .next()
+                    .map_or(false, |x| x.trim().eq_ignore_ascii_case("application/json"))
+            });
+            if !json {
+                return Some((415, "content type should be application/json"));
+            }
+        }
+        None
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |rs, (x, y)| rs | (x ^ y)) == 0
+}
+
+/// forward the received messages to the streams of `GET /messages`, see `STREAM_QUEUE`
+#[derive(Default)]
+pub struct MsgHub {
+    streams: Mutex<Vec<Sender<(usize, SubscribeMsg)>>>,
+}
+
+impl SessionObserver for MsgHub {
+    fn notify(&self, event: SessionEvent) {
+        let SessionEvent::Publish(id, msg) = event else {
+            return;
+        };
+        // the stream is closed when the receiver is dropped or full
+        self.streams
+            .lock()
+            .unwrap()
+            .retain(|x| x.try_send((id, msg.clone())).is_ok());
+    }
+}
+
+#[derive(Deserialize)]
+struct SubscribeBody {
+    topic: String,
+    #[serde(default)]
+    qos: u8,
+}
+
+#[derive(Deserialize)]
+struct UnsubscribeBody {
+    topic: String,
+}
+
+#[derive(Deserialize)]
+struct PublishBody {
+    topic: String,
+    msg: String,
+    #[serde(default)]
+    qos: u8,
+    #[serde(default)]
+    retain: bool,
+}
+
+/// the token is written to `TOKEN_FILE` in the data dir
+pub fn start(
+    port: u16,
+    data_dir: &Path,
+    tx: Sender<AppEvent>,
+    event_sink: ExtEventSink,
+    hub: Arc<MsgHub>,
+) -> Result<()> {
+    let access = Arc::new(Access {
+        port,
+        token: Uuid::new_v4().simple().to_string(),
+    });
+    let token_path = data_dir.join(TOKEN_FILE);
+    write_token(&token_path, &access.token)?;
+    let server = Server::http(("127.0.0.1", port)).map_err(|e| anyhow!("{:?}", e))?;
+    info!(
+        "http api listens on 127.0.0.1:{}, the token is in {:?}",
+        port, token_path
+    );
+    let (requests, queue) = bounded::<Request>(QUEUE_SIZE);
+    for index in 0..WORKERS {
+        let (queue, tx, event_sink, hub, access) = (
+            queue.clone(),
+            tx.clone(),
+            event_sink.clone(),
+            hub.clone(),
+            access.clone(),
+        );
+        thread::Builder::new()
+            .name(format!("http-api-{}", index))
+            .spawn(move || {
+                for request in queue.iter() {
+                    handle(
+                        request,
+                        &access,
+                        tx.clone(),
+                        event_sink.clone(),
+                        hub.clone(),
+                    );
+                }
+            })?;
+    }
+    let streams = Arc::new(AtomicUsize::new(0));
+    thread::Builder::new()
+        .name("http-api".to_string())
+        .spawn(move || {
+            for request in server.incoming_requests() {
+                if !is_stream(&request) {
+                    if let Err(e) = requests.try_send(request) {
+                        respond(e.into_inner(), 503, json!({"error": "too many requests"}));
+                    }
+                    continue;
+                }
+                if streams.fetch_add(1, Ordering::SeqCst) >= MAX_STREAMS {
+                    streams.fetch_sub(1, Ordering::SeqCst);
+                    respond(request, 503, json!({"error": "too many streams"}));
+                    continue;
+                }
+                let (tx, event_sink, hub, access, streams) = (
+                    tx.clone(),
+                    event_sink.clone(),
+                    hub.clone(),
+                    access.clone(),
+                    streams.clone(),
+                );
+                // `GET /messages` holds the thread until the client goes away
+                thread::spawn(move || {
+                    handle(request, &access, tx, event_sink, hub);
+                    streams.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        })?;
+    Ok(())
+}
+
+fn is_stream(request: &Request) -> bool {
+    *request.method() == Method::Get && request.url().trim_matches('/') == "messages"
+}
+
+/// readable by the user only
+fn write_token(path: &Path, token: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let _ = fs::remove_file(path);
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(token.as_bytes())?;
+    Ok(())
+}
+
+fn handle(
+    mut request: Request,
+    access: &Access,
+    tx: Sender<AppEvent>,
+    event_sink: ExtEventSink,
+    hub: Arc<MsgHub>,
+) {
+    debug!("{} {}", request.method(), request.url());
+    if let Some((status, e)) = access.check(request.method(), request.headers()) {
+        respond(request, status, json!({ "error": e }));
+        return;
+    }
+    let method = request.method().clone();
+    let url = request.url().to_string();
+    let paths: Vec<&str> = url.trim_matches('/').split('/').collect();
+    if is_stream(&request) {
+        stream_msgs(request, hub);
+        return;
+    }
+    let mut body = String::new();
+    if let Err(e) = request.as_reader().read_to_string(&mut body) {
+        error!("{:?}", e);
+        return;
+    }
+    let rs = match (method, paths.as_slice()) {
+        (Method::Get, ["brokers"]) => brokers(&event_sink),
+        (Method::Post, ["brokers", id, action]) => match broker_id(&event_sink, id) {
+            Ok(Some(id)) => broker_action(&tx, &event_sink, id, action, &body),
+            Ok(None) => {
+                let e = format!("can't find broker: {}", id);
+                respond(request, 404, json!({ "error": e }));
+                return;
+            }
+            Err(e) => Err(e),
+        },
+        _ => {
+            respond(request, 404, json!({"error": "not found"}));
+            return;
+        }
+    };
+    match rs {
+        Ok(val) => respond(request, 200, val),
+        Err(e) => respond(request, 400, json!({ "error": e.to_string() })),
+    }
+}
+
+fn brokers(event_sink: &ExtEventSink) -> Result<Value> {
+    query(event_sink, |data| {
+        let brokers: Vec<Value> = data
+            .brokers
+            .iter()
+            .map(|x| {
+                json!({
+                    "id": x.id,
+                    "uuid": x.uuid.to_string(),
+                    "name": x.name.as_str(),
+                    "addr": x.addr.as_str(),
+                    "port": x.port,
+                    "client_id": x.client_id.as_str(),
+                    "connected": data.tab_statuses.get(&x.id).map_or(false, |x| x.connected),
+                })
+            })
+            .collect();
+        Value::from(brokers)
+    })
+}
+
+/// the id of the broker by its id or uuid, none if not found
+fn broker_id(event_sink: &ExtEventSink, id: &str) -> Result<Option<usize>> {
+    let index = id.parse::<usize>().ok();
+    let uuid = Uuid::parse_str(id).ok();
+    if index.is_none() && uuid.is_none() {
+        bail!("invalid broker id: {}", id);
+    }
+    query(event_sink, move |data| {
+        data.brokers
+            .iter()
+            .find(|x| Some(x.id) == index || Some(x.uuid) == uuid)
+            .map(|x| x.id)
+    })
+}
+
+fn broker_action(
+    tx: &Sender<AppEvent>,
+    event_sink: &ExtEventSink,
+    id: usize,
+    action: &str,
+    body: &str,
+) -> Result<Value> {
+    match action {
+        "connect" => {
+            let found = query(event_sink, move |data| {
+                let found = data.find_broker(id).is_some();
+                if found {
+                    data.db_click_broker(id);
+                }
+                found
+            })?;
+            if !found {
+                bail!("can't find broker: {}", id);
+            }
+        }
+        "disconnect" => tx.send(AppEvent::Disconnect(id))?,
+        "subscribe" => {
+            let body: SubscribeBody = serde_json::from_str(body)?;
+            let filter = SubscribeFilter {
+                topic: body.topic.into(),
+                qos: to_qos(body.qos)?,
+                ..SubscribeFilter::default()
+            };
+            let input = SubscribeInput {
+                broker_id: id,
+                filters: vector![filter],
+            };
+            tx.send(AppEvent::Subscribe(input, id))?;
+        }
+        "unsubscribe" => {
+            let body: UnsubscribeBody = serde_json::from_str(body)?;
+            let topic = body.topic.clone();
+            let subscribe_ids = query(event_sink, move |data| {
+                data.subscribe_topics
+                    .get(&id)
+                    .map(|x| {
+                        x.iter()
+                            .filter(|x| x.topic.as_str() == topic.as_str())
+                            .map(|x| x.id)
+                            .collect::<Vec<_>>()
+                    })
+                    .unwrap_or_default()
+            })?;
+            if subscribe_ids.is_empty() {
+                bail!("not subscribed: {}", body.topic);
+            }
+            for subscribe_id in subscribe_ids {
+                tx.send(AppEvent::ToUnSubscribe {
+                    broker_id: id,
+                    id: subscribe_id,
+                })?;
+            }
+        }
+        "publish" => {
+            let body: PublishBody = serde_json::from_str(body)?;
+            let input = PublicInput {
+                topic: body.topic.into(),
+                msg: body.msg.into(),
+                qos: to_qos(body.qos)?,
+                retain: body.retain,
+                ..PublicInput::default()
+            };
+            tx.send(AppEvent::Public(input, id))?;
+        }
+        _ => bail!("unknown action: {}", action),
+    }
+    Ok(json!({"result": "accepted"}))
+}
+
+fn stream_msgs(request: Request, hub: Arc<MsgHub>) {
+    let (tx, rx) = bounded(STREAM_QUEUE);
+    hub.streams.lock().unwrap().push(tx);
+    let mut writer = request.into_writer();
+    let head = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n";
+    if writer.write_all(head.as_bytes()).is_err() || writer.flush().is_err() {
+        return;
+    }
+    for (id, msg) in rx.iter() {
+        let data = json!({
+            "broker_id": id,
+            "topic": msg.topic.as_str(),
+            "qos": msg.qos.to_string(),
+            "retain": msg.retain,
+            "msg": msg.msg.as_str(),
+        });
+        if write!(writer, "data: {}\n\n", data).is_err() || writer.flush().is_err() {
+            debug!("stream of messages closed");
+            return;
+        }
+    }
+}
+
+/// read `AppData` in the ui thread
+fn query<T: Send + 'static>(
+    event_sink: &ExtEventSink,
+    f: impl FnOnce(&mut AppData) -> T + Send + 'static,
+) -> Result<T> {
+    let (tx, rx) = bounded(1);
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        let _ = tx.send(f(data));
+    });
+    Ok(rx.recv_timeout(QUERY_TIMEOUT)?)
+}
+
+fn to_qos(qos: u8) -> Result<QoS> {
+    Ok(parse_to_qos(qos.to_string().as_str())?)
+}
+
+fn respond(request: Request, status: u16, val: Value) {
+    let header: Header = "Content-Type: application/json".parse().unwrap();
+    let response = Response::from_string(val.to_string())
+        .with_status_code(status)
+        .with_header(header);
+    if let Err(e) = request.respond(response) {
+        error!("{:?}", e);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::api::{Access, MsgHub};
+    use crate::data::common::SubscribeMsg;
+    use crate::session::{SessionEvent, SessionObserver};
+    use tiny_http::{Header, Method};
+
+    #[test]
+    fn test_access() {
+        let access = Access {
+            port: 8080,
+            token: "secret".to_string(),
+        };
+        let headers =
+            |lines: &[&str]| -> Vec<Header> { lines.iter().map(|x| x.parse().unwrap()).collect() };
+        let ok = [
+            "Host: 127.0.0.1:8080",
+            "Authorization: Bearer secret",
+            "Content-Type: application/json; charset=utf-8",
+        ];
+        assert_eq!(access.check(&Method::Post, &headers(&ok)), None);
+        assert_eq!(access.check(&Method::Get, &headers(&ok[..2])), None);
+        // dns rebinding
+        let rebinding = ["Host: evil.example:8080", ok[1]];
+        assert_eq!(
+            access.check(&Method::Get, &headers(&rebinding)).unwrap().0,
+            403
+        );
+        let cross_origin = [ok[0], ok[1], ok[2], "Origin: http://evil.example"];
+        assert_eq!(
+            access
+                .check(&Method::Post, &headers(&cross_origin))
+                .unwrap()
+                .0,
+            403
+        );
+        let wrong_token = [ok[0], "Authorization: Bearer secreT", ok[2]];
+        assert_eq!(
+            access
+                .check(&Method::Post, &headers(&wrong_token))
+                .unwrap()
+                .0,
+            401
+        );
+        assert_eq!(
+            access.check(&Method::Get, &headers(&ok[..1])).unwrap().0,
+            401
+        );
+        // a simple form post of a web page
+        let form = [ok[0], ok[1], "Content-Type: text/plain"];
+        assert_eq!(access.check(&Method::Post, &headers(&form)).unwrap().0, 415);
+    }
+
+    #[test]
+    fn test_msg_hub() {
+        let hub = MsgHub::default();
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let (closed_tx, closed_rx) = crossbeam_channel::unbounded();
+        hub.streams.lock().unwrap().push(tx);
+        hub.streams.lock().unwrap().push(closed_tx);
+        drop(closed_rx);
+        hub.notify(SessionEvent::UnSubAck(1, 2));
+        hub.notify(SessionEvent::Publish(
+            1,
+            SubscribeMsg::sample("a/b", "hello"),
+        ));
+        let (id, msg) = rx.try_recv().unwrap();
+        assert_eq!((id, msg.msg.as_str()), (1, "hello"));
+        assert!(rx.try_recv().is_err());
+        assert_eq!(hub.streams.lock().unwrap().len(), 1);
+
+        // the slow stream is dropped once its queue is full
+        let (slow_tx, _slow_rx) = crossbeam_channel::bounded(1);
+        hub.streams.lock().unwrap().push(slow_tx);
+        for _ in 0..2 {
+            hub.notify(SessionEvent::Publish(1, msg.clone()));
+        }
+        assert_eq!(hub.streams.lock().unwrap().len(), 1);
+    }
+}
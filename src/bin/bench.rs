@@ -0,0 +1,25 @@
+use anyhow::Result;
+use for_mqtt::mqtt::bench::{run_bench, BenchConfig};
+use log::LevelFilter::{Debug, Info};
+use std::fs;
+
+/// usage: bench [config.json] [report.json]
+///
+/// without config, publish to the local broker 127.0.0.1:1883
+#[tokio::main]
+async fn main() -> Result<()> {
+    let _logger = custom_utils::logger::logger_feature("for-mqtt-bench", Debug, Info)
+        .module("rumqttc", Info)
+        .build();
+    let mut args = std::env::args().skip(1);
+    let config: BenchConfig = match args.next() {
+        Some(path) => serde_json::from_slice(&fs::read(path)?)?,
+        None => BenchConfig::default(),
+    };
+    let report = run_bench(config).await?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    if let Some(path) = args.next() {
+        report.export(path)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,19 @@
+use anyhow::Result;
+use for_mqtt::cli::{run, Args, USAGE};
+use log::LevelFilter::{Info, Warn};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let _logger = custom_utils::logger::logger_feature("for-mqtt-cli", Warn, Info)
+        .module("sled", Info)
+        .module("rumqttc", Info)
+        .build();
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+    run(args).await
+}
@@ -2,14 +2,18 @@
 #![windows_subsystem = "windows"]
 use druid::theme::WINDOW_BACKGROUND_COLOR;
 use druid::{AppLauncher, Color, Env, LocalizedString, PlatformError, WindowDesc};
+use for_mqtt::api::{MsgHub, HTTP_PORT_ENV};
 use for_mqtt::data::hierarchy::AppData;
 use for_mqtt::logic::deal_event;
+use for_mqtt::session::{Observers, Session, SessionObserver};
 use for_mqtt::ui::common::WHITE;
 use for_mqtt::ui::init_layout;
 use for_mqtt::util::custom_logger::CustomWriter;
+use for_mqtt::util::data_dir;
 use for_mqtt::util::db::ArcDb;
-use log::error;
 use log::LevelFilter::{Debug, Info};
+use log::{error, info};
+use std::sync::Arc;
 use std::thread;
 
 fn main() -> Result<(), PlatformError> {
@@ -21,18 +25,35 @@ fn main() -> Result<(), PlatformError> {
         .build();
 
     let win = WindowDesc::new(init_layout(tx.clone())).title(LocalizedString::new("app-names")); //.menu(menu);
-    let mut db = ArcDb::init_db(tx.clone())?;
-    let data = db.read_app_data()?;
+    let dir = data_dir::resolve_args(std::env::args().skip(1))?;
+    info!("data dir: {:?}", dir);
+    let db = ArcDb::init_db(&dir)?;
+    let data = AppData::load(db.clone(), tx.clone())?;
 
     let launcher =
         AppLauncher::with_window(win).configure_env(|_env: &mut Env, _data: &AppData| {
             // env.set(WINDOW_BACKGROUND_COLOR, WHITE);
         });
     let event_sink = launcher.get_external_handle();
+    let mut observers: Vec<Arc<dyn SessionObserver>> = vec![Arc::new(tx.clone())];
+    if let Ok(port) = std::env::var(HTTP_PORT_ENV) {
+        let hub = Arc::new(MsgHub::default());
+        match port.parse() {
+            Ok(port) => {
+                match for_mqtt::api::start(port, &dir, tx.clone(), event_sink.clone(), hub.clone())
+                {
+                    Ok(_) => observers.push(hub),
+                    Err(e) => error!("{:?}", e),
+                }
+            }
+            Err(e) => error!("invalid {}: {:?}", HTTP_PORT_ENV, e),
+        }
+    }
+    let session = Session::new(db, Arc::new(Observers(observers)));
     thread::Builder::new()
         .name("logic-worker".to_string())
         .spawn(move || {
-            if let Err(e) = deal_event(event_sink, rx, tx) {
+            if let Err(e) = deal_event(event_sink, rx, tx, session) {
                 error!("{:?}", e);
             }
         })
@@ -0,0 +1,262 @@
+//! Forward the messages between connections of the session, e.g. to test a migration.
+//!
+//! The messages of the source matching the filters are published to the target with the topic
+//! rewritten: `strip_prefix` is removed, then `add_prefix` is added. With `both`, the messages of
+//! the target matching the rewritten filters are published back with the reverse rewrite.
+//! A source can be bridged to several targets, every pair of them is a route.
+//!
+//! The publishes of the bridges carry the user property `BRIDGE_PROPERTY`, so the copy received
+//! by any connection of the session isn't forwarded again, while the same message published
+//! by the others is.
+use crate::mqtt::data::MqttPublicInput;
+use crate::session::types::{QoS, SubscribeMsg};
+use crate::util::general_id;
+use std::collections::HashMap;
+
+/// user property marking the publishes of the bridges, the value is `<session>/<source>`
+pub const BRIDGE_PROPERTY: &str = "for-mqtt-bridge";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BridgeRoute {
+    pub source: usize,
+    pub target: usize,
+    pub filters: Vec<String>,
+    pub strip_prefix: String,
+    pub add_prefix: String,
+    /// the qos of the forwarded messages is not more than it
+    pub max_qos: QoS,
+    pub both: bool,
+}
+
+#[derive(Debug)]
+pub enum Routed {
+    Forward {
+        bridge: usize,
+        target: usize,
+        /// from the target back to the source
+        backward: bool,
+        input: MqttPublicInput,
+    },
+    /// the message was published by the bridge
+    Echo { bridge: usize },
+}
+
+#[derive(Debug)]
+pub struct Bridges {
+    /// by (source, target)
+    routes: HashMap<(usize, usize), BridgeRoute>,
+    /// tells the publishes of this session from the ones of another instance
+    session: String,
+}
+
+impl Default for Bridges {
+    fn default() -> Self {
+        Self {
+            routes: HashMap::new(),
+            session: general_id(),
+        }
+    }
+}
+
+impl BridgeRoute {
+    pub fn rewrite(&self, topic: &str) -> String {
+        let topic = topic
+            .strip_prefix(self.strip_prefix.as_str())
+            .unwrap_or(topic);
+        format!("{}{}", self.add_prefix, topic)
+    }
+
+    pub fn rewrite_back(&self, topic: &str) -> Option<String> {
+        let topic = topic.strip_prefix(self.add_prefix.as_str())?;
+        Some(format!("{}{}", self.strip_prefix, topic))
+    }
+
+    /// filters to subscribe on the target when `both`
+    pub fn target_filters(&self) -> Vec<String> {
+        self.filters.iter().map(|x| self.rewrite(x)).collect()
+    }
+
+    fn qos(&self, qos: &QoS) -> QoS {
+        if (qos.clone() as u8) < (self.max_qos.clone() as u8) {
+            qos.clone()
+        } else {
+            self.max_qos.clone()
+        }
+    }
+
+    fn input(&self, topic: String, msg: &SubscribeMsg, marker: &str) -> MqttPublicInput {
+        MqttPublicInput {
+            topic,
+            msg: msg.msg.as_ref().clone(),
+            qos: self.qos(&msg.qos).into(),
+            retain: msg.retain,
+            user_properties: vec![(BRIDGE_PROPERTY.to_string(), marker.to_string())],
+        }
+    }
+}
+
+impl Bridges {
+    /// replace the route between the same source and target
+    pub fn insert(&mut self, route: BridgeRoute) -> Option<BridgeRoute> {
+        self.routes.insert((route.source, route.target), route)
+    }
+
+    /// remove the routes from the source to every target
+    pub fn remove(&mut self, source: usize) -> Vec<BridgeRoute> {
+        let keys: Vec<(usize, usize)> = self
+            .routes
+            .keys()
+            .filter(|(x, _)| *x == source)
+            .cloned()
+            .collect();
+        keys.iter().filter_map(|x| self.routes.remove(x)).collect()
+    }
+
+    /// sources of the bridges from or to the broker
+    pub fn bridges_of(&self, broker: usize) -> Vec<usize> {
+        let mut sources: Vec<usize> = self
+            .routes
+            .values()
+            .filter(|x| x.source == broker || x.target == broker)
+            .map(|x| x.source)
+            .collect();
+        sources.sort();
+        sources.dedup();
+        sources
+    }
+
+    pub fn route(&self, broker: usize, msg: &SubscribeMsg) -> Vec<Routed> {
+        if let Some(bridge) = self.marked(msg) {
+            return vec![Routed::Echo { bridge }];
+        }
+        let mut routed = Vec::new();
+        for route in self.routes.values() {
+            let topic = msg.topic.as_str();
+            let marker = format!("{}/{}", self.session, route.source);
+            if route.source == broker && route.filters.iter().any(|x| matches(topic, x)) {
+                routed.push(Routed::Forward {
+                    bridge: route.source,
+                    target: route.target,
+                    backward: false,
+                    input: route.input(route.rewrite(topic), msg, marker.as_str()),
+                });
+            } else if route.both
+                && route.target == broker
+                && route.target_filters().iter().any(|x| matches(topic, x))
+            {
+                if let Some(back) = route.rewrite_back(topic) {
+                    routed.push(Routed::Forward {
+                        bridge: route.source,
+                        target: route.source,
+                        backward: true,
+                        input: route.input(back, msg, marker.as_str()),
+                    });
+                }
+            }
+        }
+        routed
+    }
+
+    /// the source of the bridge of this session publishing the message
+    fn marked(&self, msg: &SubscribeMsg) -> Option<usize> {
+        let (session, source) = msg.bridge.split_once('/')?;
+        if session != self.session.as_str() {
+            return None;
+        }
+        source.parse().ok()
+    }
+}
+
+fn matches(topic: &str, filter: &str) -> bool {
+    rumqttc::v5::mqttbytes::matches(topic, filter)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bridge::{BridgeRoute, Bridges, Routed, BRIDGE_PROPERTY};
+    use crate::session::types::{QoS, SubscribeMsg};
+    use std::sync::Arc;
+
+    fn msg(topic: &str, qos: QoS) -> SubscribeMsg {
+        marked(topic, qos, "")
+    }
+
+    fn marked(topic: &str, qos: QoS, bridge: &str) -> SubscribeMsg {
+        SubscribeMsg {
+            qos,
+            bridge: Arc::new(bridge.to_string()),
+            ..SubscribeMsg::sample(topic, "hello")
+        }
+    }
+
+    #[test]
+    fn test_bridge() {
+        let route = BridgeRoute {
+            source: 0,
+            target: 1,
+            filters: vec!["site/#".to_string()],
+            strip_prefix: "site/".to_string(),
+            add_prefix: "lab/".to_string(),
+            max_qos: QoS::AtLeastOnce,
+            both: true,
+        };
+        assert_eq!(route.target_filters(), vec!["lab/#".to_string()]);
+        assert_eq!(route.rewrite_back("lab/a"), Some("site/a".to_string()));
+        assert_eq!(route.rewrite_back("other/a"), None);
+        let mut bridges = Bridges::default();
+        bridges.insert(route.clone());
+
+        let routed = bridges.route(0, &msg("site/a", QoS::ExactlyOnce));
+        let [Routed::Forward {
+            target: 1,
+            backward: false,
+            input,
+            ..
+        }] = routed.as_slice()
+        else {
+            panic!("{:?}", routed);
+        };
+        assert_eq!(input.topic, "lab/a");
+        assert_eq!(QoS::from(input.qos), QoS::AtLeastOnce);
+        let (key, marker) = &input.user_properties[0];
+        assert_eq!(key, BRIDGE_PROPERTY);
+        // the copy received by another subscription of the target
+        let routed = bridges.route(1, &marked("lab/a", QoS::AtLeastOnce, marker));
+        assert!(matches!(routed.as_slice(), [Routed::Echo { bridge: 0 }]));
+        // the same message published by the others, or by the bridge of another session
+        assert_eq!(bridges.route(1, &msg("lab/a", QoS::AtLeastOnce)).len(), 1);
+        assert_eq!(
+            bridges
+                .route(1, &marked("lab/a", QoS::AtLeastOnce, "other/0"))
+                .len(),
+            1
+        );
+
+        let routed = bridges.route(1, &msg("lab/b", QoS::AtMostOnce));
+        let [Routed::Forward {
+            target: 0,
+            backward: true,
+            input,
+            ..
+        }] = routed.as_slice()
+        else {
+            panic!("{:?}", routed);
+        };
+        assert_eq!(input.topic, "site/b");
+        assert!(bridges
+            .route(0, &msg("other/a", QoS::AtMostOnce))
+            .is_empty());
+
+        // another target of the same source
+        bridges.insert(BridgeRoute {
+            target: 2,
+            both: false,
+            ..route
+        });
+        assert_eq!(bridges.route(0, &msg("site/a", QoS::AtMostOnce)).len(), 2);
+        assert_eq!(bridges.bridges_of(1), vec![0]);
+        assert_eq!(bridges.bridges_of(2), vec![0]);
+        assert_eq!(bridges.remove(0).len(), 2);
+        assert!(bridges.route(1, &msg("lab/b", QoS::AtMostOnce)).is_empty());
+    }
+}
@@ -0,0 +1,380 @@
+//! `for-mqtt-cli`: reuse the brokers and subscribe history saved by the gui.
+//! The commands read a snapshot of the db and never change it, except `import`.
+//! `pub` and `sub` read the db through a `Session`, `export` and `import` load the profiles
+//! as the gui does.
+//! The db is locked by the gui while it's running, so close the gui before importing.
+use crate::data::common::{QoS, SubscribeMsg};
+use crate::data::hierarchy::AppData;
+use crate::mqtt::data::{MqttPublicInput, MqttSubscribeInput};
+use crate::profile::{self, Conflict, Format};
+use crate::session::types::{ConnectOptions, SavedBroker};
+use crate::session::{Session, SessionEvent, SessionObserver};
+use crate::ui::formatter::parse_to_qos;
+use crate::util::data_dir::{self, DATA_DIR_ARG, PORTABLE_ARG};
+use crate::util::db::ArcDb;
+use anyhow::{bail, Result};
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::time::{timeout, timeout_at, Instant};
+use uuid::Uuid;
+
+pub const USAGE: &str = r#"usage: for-mqtt-cli <command> [options] [--data-dir <dir>] [--portable]
+
+commands:
+    list-brokers
+    pub     -b <broker> -t <topic> -m <msg> [-q <qos>] [-r]
+    sub     -b <broker> [-t <filter>]... [--his] [-q <qos>] [-f text|json|raw]
+            [-c <count>] [-w <seconds>]
+    export  [-b <broker>]... [-o <file>] [--secrets]
+    import  -i <file> [--format bundle|mqttx|explorer] [--on-conflict rename|skip|replace]
+
+<broker> is the name, the id or the uuid of a saved broker.
+--data-dir and --portable locate the db as the gui, or the env FOR_MQTT_DATA_DIR.
+The saved passwords encrypted by the master password are unlocked by the env
+FOR_MQTT_MASTER_PASSWORD.
+sub: --his subscribes the topics of the subscribe history too,
+     -c exits after <count> messages, -w exits after <seconds>.
+export: all the brokers if no -b, the passwords are excluded without --secrets.
+import: the format is detected if no --format, the names in use are renamed by default."#;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+pub const MASTER_PASSWORD_ENV: &str = "FOR_MQTT_MASTER_PASSWORD";
+
+/// options of the command: `-x value` and flags without value, e.g. `-r`
+#[derive(Debug, Default)]
+pub struct Args {
+    pub command: String,
+    options: HashMap<String, Vec<String>>,
+}
+
+impl Args {
+    const FLAGS: [&'static str; 4] = ["-r", "--his", "--secrets", PORTABLE_ARG];
+
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        let Some(command) = args.next() else {
+            bail!("lack of command");
+        };
+        let mut options: HashMap<String, Vec<String>> = HashMap::new();
+        while let Some(key) = args.next() {
+            if !key.starts_with('-') {
+                bail!("unexpected argument: {}", key);
+            }
+            let values = options.entry(key.clone()).or_default();
+            if Self::FLAGS.contains(&key.as_str()) {
+                continue;
+            }
+            let Some(value) = args.next() else {
+                bail!("lack of the value of {}", key);
+            };
+            values.push(value);
+        }
+        Ok(Self { command, options })
+    }
+    pub fn flag(&self, key: &str) -> bool {
+        self.options.contains_key(key)
+    }
+    pub fn value(&self, key: &str) -> Option<&str> {
+        self.options
+            .get(key)
+            .and_then(|x| x.last())
+            .map(|x| x.as_str())
+    }
+    pub fn values(&self, key: &str) -> Vec<String> {
+        self.options.get(key).cloned().unwrap_or_default()
+    }
+    fn must(&self, key: &str) -> Result<&str> {
+        let Some(value) = self.value(key) else {
+            bail!("lack of {}", key);
+        };
+        Ok(value)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Raw,
+}
+
+impl OutputFormat {
+    pub fn parse(input: &str) -> Result<Self> {
+        Ok(match input {
+            "text" => Self::Text,
+            "json" => Self::Json,
+            "raw" => Self::Raw,
+            _ => bail!("unknown format: {}", input),
+        })
+    }
+    pub fn format(&self, msg: &SubscribeMsg) -> String {
+        match self {
+            Self::Text => format!("{} [qos {}] {}", msg.topic, msg.qos.to_string(), msg.msg),
+            Self::Json => json!({
+                "topic": msg.topic.as_str(),
+                "qos": msg.qos.to_string(),
+                "retain": msg.retain,
+                "payload": msg.msg.as_str(),
+            })
+            .to_string(),
+            Self::Raw => msg.msg.as_ref().clone(),
+        }
+    }
+}
+
+struct ChannelObserver(UnboundedSender<SessionEvent>);
+
+impl SessionObserver for ChannelObserver {
+    fn notify(&self, event: SessionEvent) {
+        let _ = self.0.send(event);
+    }
+}
+
+pub async fn run(args: Args) -> Result<()> {
+    let dir = data_dir::resolve(args.value(DATA_DIR_ARG), args.flag(PORTABLE_ARG))?;
+    // the legacy db is left to the gui
+    let db = if args.command == "import" {
+        ArcDb::open_path(&data_dir::db_path(&dir))?
+    } else {
+        ArcDb::open_snapshot(&dir)?
+    };
+    if db.is_locked() {
+        if let Ok(master) = std::env::var(MASTER_PASSWORD_ENV) {
+            db.unlock(&master)?;
+        }
+    }
+    let (tx, rx) = unbounded_channel();
+    let mut session = Session::new(db.clone(), Arc::new(ChannelObserver(tx)));
+    match args.command.as_str() {
+        "list-brokers" => list_brokers(&session),
+        "pub" => publish(&mut session, rx, &args).await,
+        "sub" => subscribe(&mut session, rx, &args).await,
+        // the profiles are the format of the gui
+        "export" | "import" => {
+            let (tx, _rx) = crossbeam_channel::unbounded();
+            let mut data = AppData::load(db, tx)?;
+            if args.command == "export" {
+                export(&data, &args)
+            } else {
+                import(&mut data, &args)
+            }
+        }
+        command => bail!("unknown command: {}\n{}", command, USAGE),
+    }
+}
+
+fn list_brokers(session: &Session) -> Result<()> {
+    for broker in session.saved_brokers()? {
+        println!(
+            "{}\t{}\t{}\t{}:{}\t{}",
+            broker.id, broker.uuid, broker.name, broker.addr, broker.port, broker.client_id
+        );
+    }
+    Ok(())
+}
+
+fn export(data: &AppData, args: &Args) -> Result<()> {
+    let ids = args
+        .values("-b")
+        .iter()
+        .map(|name| {
+            let ids: Vec<(usize, Uuid, &str)> = data
+                .brokers
+                .iter()
+                .map(|x| (x.id, x.uuid, x.name.as_str()))
+                .collect();
+            find_by_name(&ids, name)
+        })
+        .collect::<Result<Vec<usize>>>()?;
+    let bundle = data.export_profiles(&ids, args.flag("--secrets"));
+    let json = serde_json::to_string_pretty(&bundle)?;
+    match args.value("-o") {
+        Some(path) => fs::write(path, json)?,
+        None => println!("{}", json),
+    }
+    Ok(())
+}
+
+fn import(data: &mut AppData, args: &Args) -> Result<()> {
+    let content = fs::read_to_string(args.must("-i")?)?;
+    let format = args.value("--format").map(Format::parse).transpose()?;
+    let conflict = Conflict::parse(args.value("--on-conflict").unwrap_or("rename"))?;
+    let summary = data.import_profiles(profile::parse(&content, format)?, conflict)?;
+    println!("{}", summary);
+    Ok(())
+}
+
+/// the id of the broker by its name, uuid or id
+fn find_by_name(ids: &[(usize, Uuid, &str)], name: &str) -> Result<usize> {
+    let found = ids
+        .iter()
+        .find(|x| x.2 == name || x.1.to_string() == name)
+        .or_else(|| {
+            name.parse::<usize>()
+                .ok()
+                .and_then(|id| ids.iter().find(|x| x.0 == id))
+        });
+    let Some((id, _, _)) = found.copied() else {
+        bail!("can't find broker: {}", name);
+    };
+    Ok(id)
+}
+
+/// the broker to connect, with the variables of its environment substituted
+fn find_broker(session: &Session, args: &Args) -> Result<SavedBroker> {
+    let name = args.must("-b")?;
+    let brokers = session.saved_brokers()?;
+    let ids: Vec<(usize, Uuid, &str)> = brokers
+        .iter()
+        .map(|x| (x.id, x.uuid, x.name.as_str()))
+        .collect();
+    let id = find_by_name(&ids, name)?;
+    let Some(broker) = brokers.iter().find(|x| x.id == id) else {
+        bail!("can't find broker: {}", name);
+    };
+    if broker.use_credentials && broker.password.is_empty() {
+        if !broker.save_password {
+            bail!("the password of {} isn't saved", name);
+        } else if session.db().is_locked() {
+            bail!("the saved password is locked, set {}", MASTER_PASSWORD_ENV);
+        }
+    }
+    session.resolved_broker(broker)
+}
+
+async fn connect(
+    session: &mut Session,
+    rx: &mut UnboundedReceiver<SessionEvent>,
+    broker: &SavedBroker,
+) -> Result<()> {
+    session.connect(&ConnectOptions::from(broker)).await?;
+    loop {
+        match timeout(CONNECT_TIMEOUT, rx.recv()).await {
+            Ok(Some(SessionEvent::ConnAckSuccess(_))) => return Ok(()),
+            Ok(Some(SessionEvent::ConnAckFail(_, msg))) => bail!("fail to connect: {}", msg),
+            Ok(Some(SessionEvent::Closed(..)) | None) => bail!("connection closed"),
+            Ok(Some(_)) => continue,
+            Err(_) => bail!("connect timeout"),
+        }
+    }
+}
+
+/// wait for the PUBACK of qos 1 or the PUBCOMP of qos 2; qos 0 returns once the publish is sent
+async fn publish(
+    session: &mut Session,
+    mut rx: UnboundedReceiver<SessionEvent>,
+    args: &Args,
+) -> Result<()> {
+    let broker = find_broker(session, args)?;
+    let qos = parse_to_qos(args.value("-q").unwrap_or("0"))?;
+    let input = MqttPublicInput {
+        topic: args.must("-t")?.to_string(),
+        msg: args.must("-m")?.to_string(),
+        qos: qos.clone().into(),
+        retain: args.flag("-r"),
+        user_properties: Vec::new(),
+    };
+    connect(session, &mut rx, &broker).await?;
+    let pkid = session.publish(broker.id, input).await?;
+    let ack = match qos {
+        QoS::AtMostOnce => None,
+        QoS::AtLeastOnce => Some("puback"),
+        QoS::ExactlyOnce => Some("pubcomp"),
+    };
+    if let Some(ack) = ack {
+        loop {
+            match timeout(CONNECT_TIMEOUT, rx.recv()).await {
+                Ok(Some(SessionEvent::PubAck(_, x))) if ack == "puback" && x.pkid == pkid => break,
+                Ok(Some(SessionEvent::PubComp(_, x))) if ack == "pubcomp" && x == pkid => break,
+                Ok(Some(SessionEvent::Closed(..)) | None) => bail!("connection closed"),
+                Ok(Some(_)) => continue,
+                Err(_) => bail!("wait for {} timeout", ack),
+            }
+        }
+    }
+    session.disconnect(broker.id).await?;
+    Ok(())
+}
+
+async fn subscribe(
+    session: &mut Session,
+    mut rx: UnboundedReceiver<SessionEvent>,
+    args: &Args,
+) -> Result<()> {
+    let broker = find_broker(session, args)?;
+    let qos = parse_to_qos(args.value("-q").unwrap_or("0"))?;
+    let mut inputs: Vec<MqttSubscribeInput> = args
+        .values("-t")
+        .into_iter()
+        .map(|topic| MqttSubscribeInput {
+            topic,
+            qos: qos.clone().into(),
+        })
+        .collect();
+    if args.flag("--his") {
+        inputs.extend(session.subscribe_history(&broker)?);
+    }
+    if inputs.is_empty() {
+        bail!("no topic to subscribe: -t or --his");
+    }
+    let format = OutputFormat::parse(args.value("-f").unwrap_or("text"))?;
+    let count: Option<usize> = args.value("-c").map(|x| x.parse()).transpose()?;
+    let deadline = args
+        .value("-w")
+        .map(|x| {
+            x.parse()
+                .map(|secs| Instant::now() + Duration::from_secs(secs))
+        })
+        .transpose()?;
+
+    connect(session, &mut rx, &broker).await?;
+    for input in inputs {
+        session.subscribe(broker.id, input).await?;
+    }
+    let mut received = 0;
+    while count.map_or(true, |count| received < count) {
+        let event = match deadline {
+            Some(deadline) => match timeout_at(deadline, rx.recv()).await {
+                Ok(event) => event,
+                Err(_) => break,
+            },
+            None => rx.recv().await,
+        };
+        match event {
+            Some(SessionEvent::Publish(_, msg)) => {
+                println!("{}", format.format(&msg));
+                received += 1;
+            }
+            Some(SessionEvent::Closed(..)) | None => bail!("connection closed"),
+            Some(_) => {}
+        }
+    }
+    session.disconnect(broker.id).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cli::{Args, OutputFormat};
+
+    #[test]
+    fn test_args() {
+        let args = "sub -b local -t a/# -t b/+ --his -c 3"
+            .split_whitespace()
+            .map(|x| x.to_string());
+        let args = Args::parse(args).unwrap();
+        assert_eq!(args.command, "sub");
+        assert_eq!(args.value("-b"), Some("local"));
+        assert_eq!(args.values("-t"), vec!["a/#", "b/+"]);
+        assert!(args.flag("--his"));
+        assert!(!args.flag("-r"));
+        assert_eq!(args.value("-c"), Some("3"));
+
+        let args = "pub -t".split_whitespace().map(|x| x.to_string());
+        assert!(Args::parse(args).is_err());
+        assert!(OutputFormat::parse("xml").is_err());
+    }
+}
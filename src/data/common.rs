@@ -2,15 +2,18 @@ mod impls;
 
 use crate::data::db::BrokerDB;
 use crate::data::{AString, AppEvent};
+pub use crate::session::types::{QoS, SubscribeMsg, TimelineEvent, TimelineKind};
 use crossbeam_channel::Sender;
+use druid::im::Vector;
 use druid::{Data, Lens};
 use log::debug;
 use serde::{Deserialize, Serialize};
 use std::sync::atomic::{AtomicU32, Ordering};
+use uuid::Uuid;
 
 static U32: AtomicU32 = AtomicU32::new(0);
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Data)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Data)]
 pub struct Id(u32);
 
 impl Default for Id {
@@ -21,15 +24,23 @@ impl Default for Id {
 
 #[derive(Data, Clone, Debug, Lens)]
 pub struct SubscribeTopic {
+    #[data(ignore)]
+    pub id: Id,
     pub pkid: u16,
     #[data(ignore)]
     pub topic: AString,
     #[data(ignore)]
     pub qos: QoS,
+    #[data(ignore)]
+    pub no_local: bool,
+    #[data(ignore)]
+    pub retain_as_published: bool,
+    #[data(ignore)]
+    pub retain_handling: RetainHandling,
     #[data(eq)]
     pub status: SubscribeStatus,
 }
-#[derive(Debug, Clone, Eq, Lens, Deserialize, Serialize, Data)]
+#[derive(Debug, Clone, Eq, Lens, Deserialize, Serialize, Data, Default)]
 pub struct SubscribeHis {
     #[serde(skip)]
     pub(crate) id: Id,
@@ -39,6 +50,40 @@ pub struct SubscribeHis {
     pub(crate) selected: bool,
     pub(crate) topic: AString,
     pub(crate) qos: QoS,
+    /// shown instead of the topic if not empty
+    #[serde(default)]
+    pub(crate) alias: AString,
+    #[serde(default)]
+    pub(crate) description: AString,
+    #[serde(default)]
+    pub(crate) no_local: bool,
+    #[serde(default)]
+    pub(crate) retain_as_published: bool,
+    #[serde(default)]
+    pub(crate) retain_handling: RetainHandling,
+    /// listed before the others
+    #[serde(default)]
+    pub(crate) pinned: bool,
+    #[serde(default)]
+    pub(crate) use_count: u32,
+    /// millis of the last success subscribe
+    #[serde(default)]
+    pub(crate) last_used: u64,
+}
+
+#[derive(Data, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SubscribeHisSort {
+    Recent,
+    Frequent,
+}
+
+/// the search, the sort and the note of the selected subscribe history
+#[derive(Data, Clone, Debug, Lens, Default)]
+pub struct SubscribeHisInput {
+    pub(crate) search: AString,
+    pub(crate) sort: SubscribeHisSort,
+    pub(crate) alias: AString,
+    pub(crate) description: AString,
 }
 
 #[derive(Debug, Data, Clone, Eq, PartialEq)]
@@ -54,6 +99,12 @@ impl Msg {
         }
         false
     }
+    pub fn is_highlight(&self) -> bool {
+        if let Msg::Subscribe(msg) = self {
+            return msg.highlight;
+        }
+        false
+    }
     pub fn is_sucess(&self) -> bool {
         if let Msg::Public(msg) = self {
             if msg.status == PublicStatus::Success {
@@ -87,21 +138,113 @@ pub struct PublicInput {
     pub msg: AString,
     pub qos: QoS,
     pub retain: bool,
+    /// expand the placeholders of topic and msg, see `util::template`
+    pub expand: bool,
+    /// name of the template to save as
+    pub template: AString,
+    /// milliseconds between two publishes of repeat mode
+    pub interval: u64,
+    /// 0: repeat until stopped
+    pub times: u64,
 }
 
-#[derive(Data, Clone, Debug, Eq, PartialEq)]
-pub struct SubscribeMsg {
-    pub pkid: u16,
+/// an active repeat publish of a connection
+#[derive(Debug, Data, Clone, Eq, PartialEq, Lens)]
+pub struct PublicSchedule {
+    pub(crate) id: Id,
+    pub(crate) topic: AString,
+    pub(crate) interval: u64,
+    pub(crate) times: u64,
+    pub(crate) sent: u64,
+}
+
+/// topic and msg may contain placeholders, see `util::template`
+#[derive(Debug, Clone, Eq, PartialEq, Lens, Deserialize, Serialize, Data)]
+pub struct PublicTemplate {
+    #[serde(skip)]
+    pub(crate) id: Id,
+    #[serde(skip)]
+    pub(crate) broker_id: usize,
+    pub(crate) name: AString,
+    pub(crate) topic: AString,
+    pub(crate) msg: AString,
+    pub(crate) qos: QoS,
+    pub(crate) retain: bool,
+    /// the placeholders are expanded when published
+    #[serde(default)]
+    pub(crate) expand: bool,
+}
+
+pub const PUBLIC_HIS_MAX: usize = 100;
+pub const TIMELINE_MAX: usize = 500;
+
+/// a message published by hand, the identical ones are merged
+#[derive(Debug, Clone, Eq, Lens, Deserialize, Serialize, Data)]
+pub struct PublicHis {
+    #[serde(skip)]
+    pub(crate) id: Id,
+    #[serde(skip)]
+    pub(crate) broker_id: usize,
+    pub(crate) topic: AString,
+    pub(crate) msg: AString,
+    pub(crate) qos: QoS,
+    pub(crate) retain: bool,
+    #[serde(default)]
+    pub(crate) expand: bool,
+    /// kept when the history is full
+    #[serde(default)]
+    pub(crate) favourite: bool,
+    /// millis of the last publish
+    #[serde(default)]
+    pub(crate) last_used: u64,
+}
+
+/// request of request/response: published with response topic and correlation data
+#[derive(Debug, Data, Clone, Eq, PartialEq, Lens)]
+pub struct RequestInput {
     pub topic: AString,
     pub msg: AString,
     pub qos: QoS,
+    /// subscribed automatically for the replies
+    pub response_topic: AString,
+    /// seconds to wait for the reply
+    pub timeout: u64,
+}
+
+#[derive(Debug, Data, Clone, Eq, PartialEq, Lens)]
+pub struct RequestRecord {
+    pub(crate) correlation: AString,
+    pub(crate) topic: AString,
+    pub(crate) msg: AString,
+    pub(crate) status: RequestStatus,
+    pub(crate) reply: AString,
+    // milliseconds
+    pub(crate) sent_at: u64,
+    pub(crate) latency: u64,
+}
+
+#[derive(Debug, Data, Clone, Eq, PartialEq)]
+pub enum RequestStatus {
+    Waiting,
+    Replied,
+    Timeout,
 }
 
 #[derive(Data, Debug, Clone, Eq, PartialEq, Lens)]
 pub struct SubscribeInput {
     pub broker_id: usize,
+    pub(crate) filters: Vector<SubscribeFilter>,
+}
+
+/// One topic filter of a SUBSCRIBE packet, with its own qos and subscription options
+#[derive(Data, Debug, Clone, Eq, PartialEq, Lens, Default)]
+pub struct SubscribeFilter {
+    pub(crate) id: Id,
     pub(crate) topic: AString,
     pub(crate) qos: QoS,
+    pub(crate) no_local: bool,
+    pub(crate) retain_as_published: bool,
+    pub(crate) retain_handling: RetainHandling,
 }
 #[derive(Data, Debug, Clone, Eq, PartialEq)]
 pub enum SubscribeStatus {
@@ -111,11 +254,170 @@ pub enum SubscribeStatus {
     UnSubscribeIng,
 }
 
+/// retained messages collected by subscribing to the filter for a while
+#[derive(Debug, Clone, Data, Lens, Default)]
+pub struct RetainScan {
+    pub(crate) filter: AString,
+    pub(crate) scanning: bool,
+    /// the selected topics are previewed, waiting for the confirmation to clear
+    pub(crate) confirming: bool,
+    pub(crate) msgs: Vector<RetainMsg>,
+    /// the messages of the last scan, to keep when an unchanged message was first seen
+    pub(crate) last: Vector<RetainMsg>,
+}
+
+pub const RETAIN_PREVIEW_MAX: usize = 48;
+
+#[derive(Debug, Clone, Data, Lens, PartialEq, Eq)]
+pub struct RetainMsg {
+    pub(crate) topic: AString,
+    /// bytes of the payload
+    pub(crate) size: usize,
+    /// the head of the payload
+    pub(crate) preview: AString,
+    /// hash of the payload
+    pub(crate) hash: u64,
+    /// the broker doesn't tell when a retained message was published,
+    /// so the age is counted from the first scan seeing the same payload
+    pub(crate) first_seen: u64,
+    /// remaining seconds of message expiry interval, 0 if none
+    pub(crate) expiry: u32,
+    pub(crate) selected: bool,
+}
+
+/// the bridge from the broker to another connected one, see `bridge`
+#[derive(Debug, Clone, Data, Lens)]
+pub struct Bridge {
+    /// names or ids of the target brokers, separated by `,`
+    pub(crate) target: AString,
+    /// separated by `,`
+    pub(crate) filters: AString,
+    pub(crate) strip_prefix: AString,
+    pub(crate) add_prefix: AString,
+    pub(crate) max_qos: QoS,
+    pub(crate) both: bool,
+    pub(crate) running: bool,
+    pub(crate) forwarded: u64,
+    pub(crate) backwarded: u64,
+    /// the messages published by the bridge and received again
+    pub(crate) dropped: u64,
+}
+
+/// the embedded broker, see `local_broker`
+#[derive(Debug, Clone, Data, Lens)]
+pub struct LocalBroker {
+    pub(crate) port: u16,
+    /// listen on all the interfaces instead of the loopback
+    pub(crate) lan: bool,
+    pub(crate) running: bool,
+    pub(crate) clients: Vector<AString>,
+    pub(crate) retains: Vector<RetainMsg>,
+}
+
+/// a group of the broker list, the brokers without a folder are listed without the header
+#[derive(Debug, Clone, Data, Lens)]
+pub struct BrokerFolder {
+    pub(crate) name: AString,
+    pub(crate) collapsed: bool,
+    /// empty if collapsed
+    pub(crate) brokers: Vector<Broker>,
+    pub(crate) count: usize,
+}
+
+/// the colour label of the broker in the broker list
+#[derive(Debug, Data, Clone, Copy, Eq, PartialEq, Deserialize, Serialize)]
+pub enum BrokerColor {
+    None,
+    Red,
+    Orange,
+    Green,
+    Blue,
+    Purple,
+}
+
+/// the variables of a stage, referenced by `${NAME}` in the brokers, see `util::env`
+#[derive(Debug, Clone, Data, Lens, Default, Deserialize, Serialize)]
+pub struct Environment {
+    #[serde(skip)]
+    pub(crate) id: Id,
+    pub(crate) name: AString,
+    /// `NAME=value` per line
+    pub(crate) vars: AString,
+}
+
+/// the master password of the saved credentials, see `Vault`
+#[derive(Debug, Clone, Data, Lens, Default)]
+pub struct VaultInput {
+    pub(crate) master: AString,
+    pub(crate) enabled: bool,
+    pub(crate) locked: bool,
+}
+
+/// the file to export the profiles to or import from, see `profile`
+#[derive(Debug, Clone, Data, Lens, Default)]
+pub struct ProfileInput {
+    pub(crate) path: AString,
+    /// export the passwords
+    pub(crate) secrets: bool,
+}
+
+/// see `rule`
+#[derive(Debug, Clone, Data, Lens, Default, Deserialize, Serialize)]
+pub struct Rule {
+    #[serde(skip)]
+    pub(crate) id: Id,
+    pub(crate) filter: AString,
+    /// empty, jsonpath starting with `$` or regex of the payload
+    pub(crate) condition: AString,
+    pub(crate) highlight: bool,
+    pub(crate) notify: bool,
+    /// publish when fired if not empty
+    pub(crate) topic: AString,
+    pub(crate) msg: AString,
+    pub(crate) qos: QoS,
+    pub(crate) retain: bool,
+    pub(crate) enabled: bool,
+    #[serde(skip)]
+    pub(crate) fired: u64,
+}
+
+/// rhai script of the broker, see `script`
+#[derive(Debug, Clone, Data, Lens, Default, Deserialize, Serialize)]
+pub struct Script {
+    pub(crate) code: AString,
+    /// load the script once connected
+    pub(crate) enabled: bool,
+    #[serde(skip)]
+    pub(crate) running: bool,
+    #[serde(skip)]
+    pub(crate) console: Vector<AString>,
+}
+
+/// round-trip latency of the messages published to a private topic and received back
+#[derive(Debug, Clone, Data, Lens)]
+pub struct Probe {
+    pub(crate) running: bool,
+    // milliseconds
+    pub(crate) interval: u64,
+    pub(crate) sent: u64,
+    pub(crate) received: u64,
+    pub(crate) samples: Vector<ProbeSample>,
+}
+
+#[derive(Debug, Clone, Data, PartialEq)]
+pub struct ProbeSample {
+    pub(crate) seq: u64,
+    // milliseconds
+    pub(crate) latency: f64,
+}
+
 #[derive(Debug, Clone, Data)]
 pub struct TabStatus {
     pub(crate) id: usize,
     pub(crate) try_connect: bool,
     pub(crate) connected: bool,
+    /// the password isn't saved, prompt for it to connect
+    pub(crate) ask_password: bool,
 }
 
 #[derive(Data, Clone, Copy, Eq, PartialEq, Debug, Hash)]
@@ -125,29 +427,34 @@ pub enum TabKind {
 }
 #[derive(Debug, Data, Clone, Eq, PartialEq, Deserialize, Serialize)]
 #[repr(u8)]
-pub enum QoS {
-    AtMostOnce = 0,
-    AtLeastOnce = 1,
-    ExactlyOnce = 2,
+pub enum RetainHandling {
+    SendAtSubscribe = 0,
+    SendAtNewSubscribe = 1,
+    NotSend = 2,
 }
-impl Default for QoS {
+impl Default for RetainHandling {
     fn default() -> Self {
-        QoS::AtMostOnce
+        RetainHandling::SendAtSubscribe
     }
 }
-impl ToString for QoS {
+impl ToString for RetainHandling {
     fn to_string(&self) -> String {
         match self {
-            QoS::AtMostOnce => "0".to_string(),
-            QoS::AtLeastOnce => "1".to_string(),
-            QoS::ExactlyOnce => "2".to_string(),
+            RetainHandling::SendAtSubscribe => "0".to_string(),
+            RetainHandling::SendAtNewSubscribe => "1".to_string(),
+            RetainHandling::NotSend => "2".to_string(),
         }
     }
 }
 
 #[derive(Debug, Clone, Data, Lens)]
 pub struct Broker {
+    /// the index in the session, not persisted
     pub id: usize,
+    /// the stable identity, the key in the db
+    #[data(ignore)]
+    #[lens(ignore)]
+    pub uuid: Uuid,
     pub client_id: AString,
     pub name: AString,
     pub addr: AString,
@@ -156,6 +463,15 @@ pub struct Broker {
     pub use_credentials: bool,
     pub user_name: AString,
     pub password: AString,
+    /// prompted on connect if not saved
+    pub save_password: bool,
+    /// the group in the broker list, e.g. dev/staging/prod
+    pub folder: AString,
+    /// separated by comma
+    pub tags: AString,
+    pub color: BrokerColor,
+    /// the name of the environment of the variables, see `Environment`
+    pub env: AString,
     #[data(ignore)]
     #[lens(ignore)]
     pub stored: bool,
@@ -170,6 +486,7 @@ impl Broker {
     pub fn clone_to_db(&self) -> BrokerDB {
         BrokerDB {
             id: self.id,
+            uuid: self.uuid,
             client_id: self.client_id.clone(),
             name: self.name.clone(),
             addr: self.addr.clone(),
@@ -178,6 +495,11 @@ impl Broker {
             use_credentials: self.use_credentials,
             user_name: self.user_name.clone(),
             password: self.password.clone(),
+            save_password: self.save_password,
+            folder: self.folder.clone(),
+            tags: self.tags.clone(),
+            color: self.color,
+            env: self.env.clone(),
         }
     }
 }
@@ -187,3 +509,13 @@ impl PartialEq for SubscribeHis {
         self.broker_id == other.broker_id && self.topic == other.topic && self.qos == other.qos
     }
 }
+impl PartialEq for PublicHis {
+    fn eq(&self, other: &Self) -> bool {
+        self.broker_id == other.broker_id
+            && self.topic == other.topic
+            && self.msg == other.msg
+            && self.qos == other.qos
+            && self.retain == other.retain
+            && self.expand == other.expand
+    }
+}
@@ -1,25 +1,45 @@
 use crate::data::common::{
-    Id, Msg, PublicInput, PublicMsg, PublicStatus, QoS, SubscribeHis, SubscribeInput, SubscribeMsg,
-    SubscribeStatus, SubscribeTopic,
+    Bridge, Broker, BrokerColor, Id, LocalBroker, Msg, Probe, ProbeSample, PublicHis, PublicInput,
+    PublicMsg, PublicSchedule, PublicStatus, PublicTemplate, QoS, RequestInput, RequestRecord,
+    RequestStatus, RetainHandling, RetainMsg, RetainScan, Script, SubscribeFilter, SubscribeHis,
+    SubscribeHisSort, SubscribeInput, SubscribeMsg, SubscribeStatus, SubscribeTopic, TimelineEvent,
+    TimelineKind, RETAIN_PREVIEW_MAX,
 };
 use crate::data::AString;
+use crate::mqtt::data::{
+    MqttPublicInput, MqttRequestInput, MqttSubscribeInput, MqttSubscribeManyInput,
+};
+use crate::session::types::ConnectOptions;
+use druid::im::{vector, Vector};
 use druid::Data;
-use std::sync::Arc;
+use rumqttc::v5::mqttbytes::{Filter, RetainForwardRule};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::time::Instant;
 
 impl SubscribeTopic {
-    pub fn from(val: SubscribeInput, pkid: u16) -> Self {
+    pub fn from(val: SubscribeFilter, pkid: u16) -> Self {
         Self {
+            id: Id::default(),
             pkid,
             topic: val.topic.clone(),
             qos: val.qos,
+            no_local: val.no_local,
+            retain_as_published: val.retain_as_published,
+            retain_handling: val.retain_handling,
             status: SubscribeStatus::SubscribeIng,
         }
     }
     pub fn from_his(val: SubscribeHis, pkid: u16) -> Self {
         Self {
+            id: Id::default(),
             pkid,
             topic: val.topic.clone(),
             qos: val.qos,
+            no_local: val.no_local,
+            retain_as_published: val.retain_as_published,
+            retain_handling: val.retain_handling,
             status: SubscribeStatus::SubscribeIng,
         }
     }
@@ -44,14 +64,337 @@ impl PublicMsg {
     }
 }
 
-impl From<SubscribeInput> for SubscribeHis {
-    fn from(val: SubscribeInput) -> Self {
+impl SubscribeHis {
+    pub fn from_topic(broker_id: usize, val: &SubscribeTopic) -> Self {
         Self {
             id: Id::default(),
-            broker_id: val.broker_id,
+            broker_id,
             selected: false,
             topic: val.topic.clone(),
             qos: val.qos.clone(),
+            no_local: val.no_local,
+            retain_as_published: val.retain_as_published,
+            retain_handling: val.retain_handling.clone(),
+            ..Self::default()
+        }
+    }
+    /// the alias, or the topic
+    pub fn title(&self) -> AString {
+        if self.alias.is_empty() {
+            self.topic.clone()
+        } else {
+            self.alias.clone()
+        }
+    }
+    /// the topic, the alias or the description contains the query, ignoring case
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return true;
+        }
+        let contains = |x: &str| x.to_lowercase().contains(&query);
+        contains(&self.topic) || contains(&self.alias) || contains(&self.description)
+    }
+    /// subscribe again with the options saved
+    pub fn to_input(&self) -> SubscribeInput {
+        SubscribeInput {
+            broker_id: self.broker_id,
+            filters: vector![SubscribeFilter {
+                id: Id::default(),
+                topic: self.topic.clone(),
+                qos: self.qos.clone(),
+                no_local: self.no_local,
+                retain_as_published: self.retain_as_published,
+                retain_handling: self.retain_handling.clone(),
+            }],
+        }
+    }
+}
+
+impl Default for SubscribeHisSort {
+    fn default() -> Self {
+        Self::Recent
+    }
+}
+
+impl PublicTemplate {
+    pub fn from_input(broker_id: usize, val: &PublicInput) -> Self {
+        Self {
+            id: Id::default(),
+            broker_id,
+            name: val.template.clone(),
+            topic: val.topic.clone(),
+            msg: val.msg.clone(),
+            qos: val.qos.clone(),
+            retain: val.retain,
+            expand: val.expand,
+        }
+    }
+    pub fn to_input(&self) -> PublicInput {
+        PublicInput {
+            topic: self.topic.clone(),
+            msg: self.msg.clone(),
+            qos: self.qos.clone(),
+            retain: self.retain,
+            expand: self.expand,
+            template: self.name.clone(),
+            ..PublicInput::default()
+        }
+    }
+}
+
+impl PublicHis {
+    pub fn from_input(broker_id: usize, val: &PublicInput) -> Self {
+        Self {
+            id: Id::default(),
+            broker_id,
+            topic: val.topic.clone(),
+            msg: val.msg.clone(),
+            qos: val.qos.clone(),
+            retain: val.retain,
+            expand: val.expand,
+            favourite: false,
+            last_used: 0,
+        }
+    }
+    pub fn to_input(&self) -> PublicInput {
+        PublicInput {
+            topic: self.topic.clone(),
+            msg: self.msg.clone(),
+            qos: self.qos.clone(),
+            retain: self.retain,
+            expand: self.expand,
+            ..PublicInput::default()
+        }
+    }
+}
+
+impl PublicSchedule {
+    pub fn from(id: Id, val: &PublicInput) -> Self {
+        Self {
+            id,
+            topic: val.topic.clone(),
+            interval: val.interval,
+            times: val.times,
+            sent: 0,
+        }
+    }
+}
+
+pub const PROBE_SAMPLES_MAX: usize = 120;
+
+impl Default for Probe {
+    fn default() -> Self {
+        Self {
+            running: false,
+            interval: 1000,
+            sent: 0,
+            received: 0,
+            samples: Vector::new(),
+        }
+    }
+}
+
+impl Probe {
+    pub fn push(&mut self, sample: ProbeSample) {
+        self.received += 1;
+        self.samples.push_back(sample);
+        if self.samples.len() > PROBE_SAMPLES_MAX {
+            self.samples.pop_front();
+        }
+    }
+    pub fn last(&self) -> f64 {
+        self.samples.last().map(|x| x.latency).unwrap_or_default()
+    }
+    pub fn avg(&self) -> f64 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+        self.samples.iter().map(|x| x.latency).sum::<f64>() / self.samples.len() as f64
+    }
+    /// mean of the differences between consecutive latencies
+    pub fn jitter(&self) -> f64 {
+        if self.samples.len() < 2 {
+            return 0.0;
+        }
+        let diffs: f64 = self
+            .samples
+            .iter()
+            .zip(self.samples.iter().skip(1))
+            .map(|(pre, next)| (next.latency - pre.latency).abs())
+            .sum();
+        diffs / (self.samples.len() - 1) as f64
+    }
+}
+
+impl ProbeSample {
+    /// payload of probe: `{seq}`, the sending instant is kept locally
+    pub fn payload(seq: u64) -> String {
+        seq.to_string()
+    }
+    /// none if invalid, unknown or echoed twice
+    pub fn parse(payload: &str, sent: &mut HashMap<u64, Instant>) -> Option<Self> {
+        let seq: u64 = payload.parse().ok()?;
+        let instant = sent.remove(&seq)?;
+        Some(Self {
+            seq,
+            latency: instant.elapsed().as_secs_f64() * 1000.0,
+        })
+    }
+}
+
+impl RetainMsg {
+    pub fn from(msg: &SubscribeMsg, now: u64) -> Self {
+        let mut hasher = DefaultHasher::new();
+        msg.msg.hash(&mut hasher);
+        let preview: String = msg
+            .msg
+            .chars()
+            .take(RETAIN_PREVIEW_MAX)
+            .map(|x| if x.is_control() { ' ' } else { x })
+            .collect();
+        Self {
+            topic: msg.topic.clone(),
+            size: msg.size,
+            preview: preview.into(),
+            hash: hasher.finish(),
+            first_seen: now,
+            expiry: msg.expiry,
+            selected: true,
+        }
+    }
+    /// e.g. `42s`, `5m`, `3h`, `2d`
+    pub fn age(&self, now: u64) -> String {
+        let secs = now.saturating_sub(self.first_seen) / 1000;
+        match secs {
+            0..=59 => format!("{}s", secs),
+            60..=3599 => format!("{}m", secs / 60),
+            3600..=86399 => format!("{}h", secs / 3600),
+            _ => format!("{}d", secs / 86400),
+        }
+    }
+}
+
+impl RetainScan {
+    pub fn selected_topics(&self) -> Vec<String> {
+        self.msgs
+            .iter()
+            .filter(|x| x.selected)
+            .map(|x| x.topic.as_ref().clone())
+            .collect()
+    }
+}
+
+impl Default for Bridge {
+    fn default() -> Self {
+        Self {
+            target: Default::default(),
+            filters: Default::default(),
+            strip_prefix: Default::default(),
+            add_prefix: Default::default(),
+            max_qos: QoS::ExactlyOnce,
+            both: false,
+            running: false,
+            forwarded: 0,
+            backwarded: 0,
+            dropped: 0,
+        }
+    }
+}
+
+impl Default for LocalBroker {
+    fn default() -> Self {
+        Self {
+            port: 1883,
+            lan: false,
+            running: false,
+            clients: Vector::new(),
+            retains: Vector::new(),
+        }
+    }
+}
+
+impl Default for BrokerColor {
+    fn default() -> Self {
+        Self::None
+    }
+}
+
+impl BrokerColor {
+    pub const ALL: [Self; 6] = [
+        Self::None,
+        Self::Red,
+        Self::Orange,
+        Self::Green,
+        Self::Blue,
+        Self::Purple,
+    ];
+}
+
+impl Broker {
+    pub fn tags(&self) -> impl Iterator<Item = &str> {
+        self.tags
+            .split(',')
+            .map(|x| x.trim())
+            .filter(|x| !x.is_empty())
+    }
+    /// the name, the address, the folder or a tag contains the query, ignoring case
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return true;
+        }
+        let contains = |x: &str| x.to_lowercase().contains(&query);
+        contains(&self.name)
+            || contains(&self.addr)
+            || contains(&self.folder)
+            || self.tags().any(contains)
+    }
+}
+
+pub const SCRIPT_CONSOLE_MAX: usize = 500;
+
+impl Script {
+    pub fn output(&mut self, line: String) {
+        self.console.push_back(line.into());
+        if self.console.len() > SCRIPT_CONSOLE_MAX {
+            self.console.pop_front();
+        }
+    }
+}
+
+impl RequestInput {
+    pub fn init(response_topic: String) -> Self {
+        Self {
+            topic: Default::default(),
+            msg: Default::default(),
+            qos: QoS::AtLeastOnce,
+            response_topic: response_topic.into(),
+            timeout: 5,
+        }
+    }
+}
+
+impl RequestRecord {
+    pub fn from(input: &RequestInput, correlation: String, sent_at: u64) -> Self {
+        Self {
+            correlation: correlation.into(),
+            topic: input.topic.clone(),
+            msg: input.msg.clone(),
+            status: RequestStatus::Waiting,
+            reply: Default::default(),
+            sent_at,
+            latency: 0,
+        }
+    }
+}
+
+impl ToString for RequestStatus {
+    fn to_string(&self) -> String {
+        match self {
+            RequestStatus::Waiting => "waiting".to_string(),
+            RequestStatus::Replied => "replied".to_string(),
+            RequestStatus::Timeout => "timeout".to_string(),
         }
     }
 }
@@ -71,8 +414,22 @@ impl SubscribeInput {
     pub fn init(broker_id: usize) -> Self {
         Self {
             broker_id,
-            topic: Arc::new("".to_string()),
-            qos: QoS::AtMostOnce,
+            filters: vector![SubscribeFilter::default()],
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.filters.iter().all(|x| x.topic.is_empty())
+    }
+    /// drop the rows without topic, so that the filters sent keep the same order as the rows displayed
+    pub fn without_empty(&self) -> Self {
+        Self {
+            broker_id: self.broker_id,
+            filters: self
+                .filters
+                .iter()
+                .filter(|x| !x.topic.is_empty())
+                .cloned()
+                .collect(),
         }
     }
 }
@@ -97,21 +454,141 @@ impl Msg {
     }
 }
 
-impl From<rumqttc::v5::mqttbytes::QoS> for QoS {
-    fn from(qos: rumqttc::v5::mqttbytes::QoS) -> Self {
-        match qos {
-            rumqttc::v5::mqttbytes::QoS::AtLeastOnce => Self::AtLeastOnce,
-            rumqttc::v5::mqttbytes::QoS::AtMostOnce => Self::AtMostOnce,
-            rumqttc::v5::mqttbytes::QoS::ExactlyOnce => Self::ExactlyOnce,
+impl From<RetainHandling> for RetainForwardRule {
+    fn from(val: RetainHandling) -> Self {
+        match val {
+            RetainHandling::SendAtSubscribe => Self::OnEverySubscribe,
+            RetainHandling::SendAtNewSubscribe => Self::OnNewSubscribe,
+            RetainHandling::NotSend => Self::Never,
         }
     }
 }
-impl From<QoS> for rumqttc::v5::mqttbytes::QoS {
-    fn from(qos: QoS) -> Self {
-        match qos {
-            QoS::AtLeastOnce => Self::AtLeastOnce,
-            QoS::AtMostOnce => Self::AtMostOnce,
-            QoS::ExactlyOnce => Self::ExactlyOnce,
+
+#[cfg(test)]
+mod test {
+    use crate::data::common::{Probe, ProbeSample};
+    use std::collections::HashMap;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_probe_sample() {
+        let mut sent = HashMap::new();
+        let instant = Instant::now();
+        sent.insert(1, instant);
+        sent.insert(2, instant - Duration::from_millis(20));
+        let sample = ProbeSample::parse(&ProbeSample::payload(2), &mut sent).unwrap();
+        assert_eq!(sample.seq, 2);
+        assert!(sample.latency >= 20.0);
+        // sub millisecond latency is kept
+        let sample = ProbeSample::parse("1", &mut sent).unwrap();
+        assert!(sample.latency > 0.0 && sample.latency < 20.0);
+        assert!(sent.is_empty());
+        assert!(ProbeSample::parse("1", &mut sent).is_none());
+        assert!(ProbeSample::parse("1:1700000000000", &mut sent).is_none());
+    }
+
+    #[test]
+    fn test_probe_jitter() {
+        let mut probe = Probe::default();
+        assert_eq!(probe.jitter(), 0.0);
+        for (seq, latency) in [10.0, 14.0, 12.0, 12.0].into_iter().enumerate() {
+            probe.push(ProbeSample {
+                seq: seq as u64,
+                latency,
+            });
+        }
+        assert_eq!(probe.jitter(), 2.0);
+        assert_eq!(probe.avg(), 12.0);
+        assert_eq!(probe.last(), 12.0);
+        assert_eq!(probe.received, 4);
+    }
+}
+
+// the types of the session core don't derive `Data`, see `session::types`
+impl Data for QoS {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+impl Data for TimelineKind {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+impl Data for TimelineEvent {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+impl Data for SubscribeMsg {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+impl From<&Broker> for ConnectOptions {
+    fn from(val: &Broker) -> Self {
+        Self {
+            id: val.id,
+            client_id: val.client_id.as_ref().clone(),
+            addr: val.addr.as_ref().clone(),
+            port: val.port,
+            params: val.params.as_ref().clone(),
+            credentials: val.use_credentials.then(|| {
+                (
+                    val.user_name.as_ref().clone(),
+                    val.password.as_ref().clone(),
+                )
+            }),
+        }
+    }
+}
+
+impl From<PublicInput> for MqttPublicInput {
+    fn from(val: PublicInput) -> Self {
+        Self {
+            topic: val.topic.as_ref().clone(),
+            msg: val.msg.as_ref().clone(),
+            qos: rumqttc::v5::mqttbytes::QoS::AtLeastOnce,
+            retain: val.retain,
+            user_properties: Vec::new(),
+        }
+    }
+}
+impl MqttRequestInput {
+    pub fn from(val: &RequestInput, correlation: String) -> Self {
+        Self {
+            topic: val.topic.as_ref().clone(),
+            msg: val.msg.as_ref().clone(),
+            qos: val.qos.clone().into(),
+            response_topic: val.response_topic.as_ref().clone(),
+            correlation,
+        }
+    }
+}
+impl From<SubscribeInput> for MqttSubscribeManyInput {
+    fn from(val: SubscribeInput) -> Self {
+        Self {
+            filters: val.filters.into_iter().map(|x| x.into()).collect(),
+        }
+    }
+}
+impl From<SubscribeFilter> for Filter {
+    fn from(val: SubscribeFilter) -> Self {
+        Self {
+            path: val.topic.as_ref().clone(),
+            qos: val.qos.into(),
+            nolocal: val.no_local,
+            preserve_retain: val.retain_as_published,
+            retain_forward_rule: val.retain_handling.into(),
+        }
+    }
+}
+impl From<SubscribeHis> for MqttSubscribeInput {
+    fn from(val: SubscribeHis) -> Self {
+        Self {
+            topic: val.topic.as_ref().clone(),
+            qos: val.qos.into(),
         }
     }
 }
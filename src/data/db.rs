@@ -1,26 +1,8 @@
-use crate::data::common::Broker;
+use crate::data::common::{Broker, BrokerColor};
 use crate::data::{AString, AppEvent};
-use anyhow::Result;
 use crossbeam_channel::Sender;
 use serde::{Deserialize, Serialize};
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum DbKey {
-    Broker(usize),
-    SubscribeHis(usize),
-}
-
-impl DbKey {
-    pub fn broker_key(id: usize) -> Self {
-        Self::Broker(id)
-    }
-    pub fn subscribe_his_key(id: usize) -> Self {
-        Self::SubscribeHis(id)
-    }
-    pub fn as_bytes(&self) -> Result<Vec<u8>> {
-        Ok(serde_json::to_vec(self)?)
-    }
-}
+use uuid::Uuid;
 
 // #[derive(Debug, Clone, Serialize, Deserialize, FromBytes, AsBytes)]
 // #[repr(C)]
@@ -45,7 +27,11 @@ impl DbKey {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BrokerDB {
+    #[serde(skip)]
     pub id: usize,
+    /// nil in the legacy layout, see `LegacyDbKey`
+    #[serde(default)]
+    pub uuid: Uuid,
     pub client_id: AString,
     pub name: AString,
     pub addr: AString,
@@ -53,13 +39,29 @@ pub struct BrokerDB {
     pub params: AString,
     pub use_credentials: bool,
     pub user_name: AString,
+    /// encrypted if the vault is enabled, see `Vault::seal`
     pub password: AString,
+    #[serde(default = "save_password_default")]
+    pub save_password: bool,
+    #[serde(default)]
+    pub folder: AString,
+    #[serde(default)]
+    pub tags: AString,
+    #[serde(default)]
+    pub color: BrokerColor,
+    #[serde(default)]
+    pub env: AString,
+}
+
+fn save_password_default() -> bool {
+    true
 }
 
 impl BrokerDB {
     pub fn to_broker(self, tx: Sender<AppEvent>) -> Broker {
         let Self {
             id,
+            uuid,
             client_id,
             name,
             addr,
@@ -68,9 +70,15 @@ impl BrokerDB {
             use_credentials,
             user_name,
             password,
+            save_password,
+            folder,
+            tags,
+            color,
+            env,
         } = self;
         Broker {
             id,
+            uuid,
             client_id,
             name,
             addr,
@@ -79,6 +87,11 @@ impl BrokerDB {
             use_credentials,
             user_name,
             password,
+            save_password,
+            folder,
+            tags,
+            color,
+            env,
             stored: true,
             tx,
             selected: false,
@@ -1,18 +1,35 @@
-use crate::data::common::{Broker, Id};
+use crate::bridge::BridgeRoute;
 use crate::data::common::{
-    Msg, PublicInput, PublicMsg, PublicStatus, SubscribeHis, SubscribeInput, SubscribeMsg,
-    SubscribeStatus, SubscribeTopic, TabStatus,
+    Bridge, Broker, BrokerFolder, Environment, Id, LocalBroker, TimelineEvent, TimelineKind,
+    PUBLIC_HIS_MAX, TIMELINE_MAX,
 };
+use crate::data::common::{
+    Msg, Probe, ProbeSample, ProfileInput, PublicHis, PublicInput, PublicMsg, PublicSchedule,
+    PublicStatus, PublicTemplate, QoS, RequestInput, RequestRecord, RequestStatus, RetainHandling,
+    RetainMsg, RetainScan, Rule, Script, SubscribeFilter, SubscribeHis, SubscribeHisInput,
+    SubscribeHisSort, SubscribeInput, SubscribeMsg, SubscribeStatus, SubscribeTopic, TabStatus,
+    VaultInput,
+};
+use crate::data::db::BrokerDB;
 use crate::data::{AString, AppEvent, EventUnSubscribe};
-use crate::util::db::ArcDb;
+use crate::local_broker::LocalBrokerEvent;
+use crate::profile::{unique_name, Bundle, Conflict, ImportSummary, Profile, BUNDLE_VERSION};
+use crate::rule::RuleMatcher;
+use crate::util::db::{ArcDb, DbKey};
+use crate::util::env::{has_var, parse_vars};
 use crate::util::hint::*;
+use crate::util::vault::{VaultKey, VaultMeta};
+use crate::util::{general_id, now_millis};
 use anyhow::bail;
 use anyhow::Result;
+use crossbeam_channel::Sender;
 use custom_utils::{tx, tx_async};
 use druid::im::Vector;
 use druid::{im::HashMap, Data, Lens};
-use log::{debug, error, warn};
+use log::{debug, error, info, warn};
 use rumqttc::v5::mqttbytes::*;
+use std::sync::Arc;
+use uuid::Uuid;
 
 #[derive(Debug, Clone, Lens, Data)]
 pub struct AppData {
@@ -20,20 +37,160 @@ pub struct AppData {
     pub broker_tabs: Vector<usize>,
     pub tab_statuses: HashMap<usize, TabStatus>,
     pub subscribe_hises: HashMap<usize, Vector<SubscribeHis>>,
+    pub public_templates: HashMap<usize, Vector<PublicTemplate>>,
+    pub public_hises: HashMap<usize, Vector<PublicHis>>,
     pub subscribe_topics: HashMap<usize, Vector<SubscribeTopic>>,
     pub msgs: HashMap<usize, Vector<Msg>>,
     pub subscribe_input: HashMap<usize, SubscribeInput>,
     pub public_input: HashMap<usize, PublicInput>,
+    pub public_schedules: HashMap<usize, Vector<PublicSchedule>>,
+    pub probes: HashMap<usize, Probe>,
+    /// kept across the reconnects
+    pub timelines: HashMap<usize, Vector<TimelineEvent>>,
+    pub request_input: HashMap<usize, RequestInput>,
+    pub requests: HashMap<usize, Vector<RequestRecord>>,
+    pub retain_scans: HashMap<usize, RetainScan>,
+    pub scripts: HashMap<usize, Script>,
+    pub bridges: HashMap<usize, Bridge>,
+    pub local_broker: LocalBroker,
+    pub vault: VaultInput,
+    pub profile_input: ProfileInput,
+    /// filter the broker list by the name, the address, the folder or the tags
+    pub broker_search: AString,
+    /// the folders collapsed in the broker list
+    pub collapsed_folders: Vector<AString>,
+    pub subscribe_his_input: SubscribeHisInput,
+    pub environments: Vector<Environment>,
+    pub env_input: Environment,
+    pub rules: HashMap<usize, Vector<Rule>>,
+    pub rule_input: HashMap<usize, Rule>,
     pub unsubscribe_ing: HashMap<usize, Vector<UnsubcribeTracing>>,
     #[data(ignore)]
     #[lens(ignore)]
     pub db: ArcDb,
+    /// the events to the logic worker
+    #[data(ignore)]
+    #[lens(ignore)]
+    pub tx: Sender<AppEvent>,
     pub hint: AString,
 }
 
 impl AppData {
+    /// the saved brokers and their lists, see `ArcDb::load_brokers`
+    pub fn load(db: ArcDb, tx: Sender<AppEvent>) -> Result<Self> {
+        let mut brokers = Vector::new();
+        let mut subscribe_hises = HashMap::new();
+        let mut public_templates = HashMap::new();
+        let mut public_hises = HashMap::new();
+        let mut scripts = HashMap::new();
+        let mut rules = HashMap::new();
+        for (index, uuid, mut broker) in db.load_brokers::<BrokerDB>()? {
+            broker.id = index;
+            let hises: Vector<SubscribeHis> = db.read_of(DbKey::subscribe_his_key(uuid))?;
+            let templates: Vector<PublicTemplate> = db.read_of(DbKey::public_template_key(uuid))?;
+            let script: Script = db.read_of(DbKey::script_key(uuid))?;
+            let broker_rules: Vector<Rule> = db.read_of(DbKey::rules_key(uuid))?;
+            let public_his: Vector<PublicHis> = db.read_of(DbKey::public_his_key(uuid))?;
+            debug!("{:?} {:?}", broker, hises);
+            brokers.push_back(broker.to_broker(tx.clone()));
+            subscribe_hises.insert(
+                index,
+                hises
+                    .into_iter()
+                    .map(|mut x| {
+                        x.broker_id = index;
+                        x
+                    })
+                    .collect(),
+            );
+            public_templates.insert(
+                index,
+                templates
+                    .into_iter()
+                    .map(|mut x| {
+                        x.broker_id = index;
+                        x
+                    })
+                    .collect(),
+            );
+            public_hises.insert(
+                index,
+                public_his
+                    .into_iter()
+                    .map(|mut x| {
+                        x.broker_id = index;
+                        x
+                    })
+                    .collect(),
+            );
+            scripts.insert(index, script);
+            rules.insert(index, broker_rules);
+        }
+        let environments: Vector<Environment> = db.read_environments()?;
+        Ok(AppData {
+            brokers,
+            broker_tabs: Default::default(),
+            tab_statuses: Default::default(),
+            subscribe_hises,
+            public_templates,
+            public_hises,
+            subscribe_topics: Default::default(),
+            msgs: Default::default(),
+            subscribe_input: Default::default(),
+            public_input: Default::default(),
+            public_schedules: Default::default(),
+            probes: Default::default(),
+            timelines: Default::default(),
+            request_input: Default::default(),
+            requests: Default::default(),
+            retain_scans: Default::default(),
+            scripts,
+            bridges: Default::default(),
+            local_broker: Default::default(),
+            vault: VaultInput {
+                master: Default::default(),
+                enabled: db.is_vault_enabled(),
+                locked: db.is_locked(),
+            },
+            profile_input: Default::default(),
+            broker_search: Default::default(),
+            collapsed_folders: Default::default(),
+            subscribe_his_input: Default::default(),
+            environments,
+            env_input: Default::default(),
+            rules,
+            rule_input: Default::default(),
+            unsubscribe_ing: Default::default(),
+            db,
+            tx,
+            hint: "".to_string().into(),
+        })
+    }
+    /// a new broker, saved when it's saved or connected
+    pub fn new_broker(&self) -> Broker {
+        Broker {
+            id: self.db.next_index(),
+            uuid: Uuid::new_v4(),
+            client_id: Arc::new("".to_string()),
+            name: Arc::new("".to_string()),
+            addr: Arc::new("broker-cn.emqx.io".to_string()),
+            port: 1883,
+            params: Arc::new(OPTION.to_string()),
+            use_credentials: false,
+            user_name: Arc::new("".to_string()),
+            password: Arc::new("".to_string()),
+            save_password: true,
+            folder: Default::default(),
+            tags: Default::default(),
+            color: Default::default(),
+            env: Default::default(),
+            stored: false,
+            tx: self.tx.clone(),
+            selected: false,
+        }
+    }
     pub fn add_broker(&mut self) {
-        let broker = self.db.new_broker();
+        let broker = self.new_broker();
         self.init_broker_tab(broker.id);
         self.brokers.push_back(broker);
     }
@@ -48,6 +205,7 @@ impl AppData {
                     id: id,
                     try_connect: false,
                     connected: false,
+                    ask_password: false,
                 },
             );
         }
@@ -72,26 +230,103 @@ impl AppData {
     pub fn get_selected_broker(&self) -> Option<&Broker> {
         self.brokers.iter().find(|x| x.selected)
     }
+    /// the saved brokers matching the search, grouped by the folder in the saved order
+    pub fn broker_list(&self) -> Vector<Broker> {
+        let mut brokers: Vec<Broker> = self
+            .brokers
+            .iter()
+            .filter(|x| x.stored && x.matches(&self.broker_search))
+            .cloned()
+            .collect();
+        brokers.sort_by(|a, b| a.folder.cmp(&b.folder));
+        brokers.into_iter().collect()
+    }
+    /// the broker list in the folders, the collapsed ones are expanded while searching
+    pub fn broker_folders(&self) -> Vector<BrokerFolder> {
+        let mut folders: Vector<BrokerFolder> = Vector::new();
+        for broker in self.broker_list() {
+            if folders.last().map_or(true, |x| x.name != broker.folder) {
+                folders.push_back(BrokerFolder {
+                    name: broker.folder.clone(),
+                    collapsed: self.broker_search.is_empty()
+                        && self.collapsed_folders.contains(&broker.folder),
+                    brokers: Vector::new(),
+                    count: 0,
+                });
+            }
+            if let Some(folder) = folders.last_mut() {
+                folder.count += 1;
+                if !folder.collapsed {
+                    folder.brokers.push_back(broker);
+                }
+            }
+        }
+        folders
+    }
+    pub fn toggle_broker_folder(&mut self, name: AString) {
+        match self.collapsed_folders.index_of(&name) {
+            Some(index) => {
+                self.collapsed_folders.remove(index);
+            }
+            None => self.collapsed_folders.push_back(name),
+        }
+    }
+    /// dropped on the other broker: take its position and its folder
+    pub fn move_broker(&mut self, from: usize, to: usize) -> Result<()> {
+        let (Some(from_index), Some(to_index)) = (
+            self.brokers.iter().position(|x| x.id == from),
+            self.brokers.iter().position(|x| x.id == to),
+        ) else {
+            bail!("can't find the broker: {} {}", from, to);
+        };
+        let folder = self.brokers[to_index].folder.clone();
+        let mut broker = self.brokers.remove(from_index);
+        let moved_folder = broker.folder != folder;
+        broker.folder = folder;
+        self.brokers.insert(to_index, broker);
+        self.db.move_broker(from, to)?;
+        if moved_folder {
+            self.save_broker(from)?;
+        }
+        Ok(())
+    }
     pub fn find_broker(&self, id: usize) -> Option<&Broker> {
         self.brokers.iter().find(|x| (*x).id == id)
     }
     pub fn save_broker(&mut self, id: usize) -> Result<()> {
         if let Some(broker) = self.brokers.iter_mut().find(|x| (*x).id == id) {
+            self.db.save_broker(id, &broker.clone_to_db())?;
             broker.stored = true;
-            self.db.save_broker(id, broker)?;
             if !self.subscribe_hises.contains_key(&id) {
                 self.subscribe_hises.insert(id, Vector::new());
             }
+            if !self.public_templates.contains_key(&id) {
+                self.public_templates.insert(id, Vector::new());
+            }
+            match self.public_hises.get(&id) {
+                // published before the broker is saved
+                Some(hises) => self.db.update_public_his(id, hises)?,
+                None => {
+                    self.public_hises.insert(id, Vector::new());
+                }
+            }
         }
         Ok(())
     }
+    /// the password isn't saved: the prompt is shown in the tab
+    pub fn ask_password(&mut self, id: usize) {
+        self.init_broker_tab(id);
+        if let Some(status) = self.tab_statuses.get_mut(&id) {
+            status.try_connect = false;
+            status.ask_password = true;
+        }
+        warn!("{}", PASSWORD_REQUIRED);
+    }
     pub fn reconnect(&mut self, id: usize) -> Result<()> {
         self.disconnect(id)?;
-        if let Some(broker) = self.brokers.iter().find(|x| (*x).id == id) {
-            tx!(self.db.tx, AppEvent::Connect(broker.clone()))
-        } else {
-            error!("not find the broker");
-        }
+        self.push_timeline(id, TimelineEvent::new(TimelineKind::Reconnect, ""));
+        let broker = self.resolved_broker(id)?;
+        tx!(self.tx, AppEvent::Connect(broker));
         Ok(())
     }
     pub fn init_connection(&mut self, id: usize) -> Result<()> {
@@ -99,25 +334,108 @@ impl AppData {
             status.try_connect = true;
         }
         if let Some(broker) = self.brokers.iter_mut().find(|x| (*x).id == id) {
-            broker.stored = true;
-            self.db.save_broker(id, broker)?;
+            match self.db.save_broker(id, &broker.clone_to_db()) {
+                Ok(()) => broker.stored = true,
+                // the password typed while locked, saved after unlocked
+                Err(e) => warn!("{}", e),
+            }
         }
         if self.subscribe_hises.get_mut(&id).is_none() {
             self.subscribe_hises.insert(id, Vector::new());
         }
+        if self.public_templates.get_mut(&id).is_none() {
+            self.public_templates.insert(id, Vector::new());
+        }
+        if self.public_hises.get_mut(&id).is_none() {
+            self.public_hises.insert(id, Vector::new());
+        }
         self.subscribe_topics.insert(id, Vector::new());
         self.msgs.insert(id, Vector::new());
         self.subscribe_input.insert(id, SubscribeInput::init(id));
         self.public_input.insert(id, PublicInput::default().into());
+        self.public_schedules.insert(id, Vector::new());
+        if self.probes.get(&id).is_none() {
+            self.probes.insert(id, Probe::default());
+        }
+        if self.timelines.get(&id).is_none() {
+            self.timelines.insert(id, Vector::new());
+        }
+        if self.request_input.get(&id).is_none() {
+            let response_topic = format!("for-mqtt/reply/{}", general_id());
+            self.request_input
+                .insert(id, RequestInput::init(response_topic));
+        }
+        self.requests.insert(id, Vector::new());
+        if self.retain_scans.get(&id).is_none() {
+            self.retain_scans.insert(id, RetainScan::default());
+        }
+        if self.scripts.get(&id).is_none() {
+            self.scripts.insert(id, Script::default());
+        }
+        if self.bridges.get(&id).is_none() {
+            self.bridges.insert(id, Bridge::default());
+        }
+        if self.rules.get(&id).is_none() {
+            self.rules.insert(id, Vector::new());
+        }
+        if self.rule_input.get(&id).is_none() {
+            self.rule_input.insert(id, Rule::default());
+        }
+        self.update_rules(id)?;
         Ok(())
     }
     pub fn connected(&mut self, id: usize) -> Result<()> {
         if let Some(status) = self.tab_statuses.get_mut(&id) {
             status.try_connect = false;
             status.connected = true;
+            status.ask_password = false;
+        }
+        if let Some(script) = self.scripts.get(&id) {
+            if script.enabled {
+                tx!(self.tx, AppEvent::LoadScript(id, script.code.clone()))
+            }
         }
         Ok(())
     }
+    /// the oldest is dropped if full
+    pub fn push_timeline(&mut self, id: usize, event: TimelineEvent) {
+        if self.timelines.get(&id).is_none() {
+            self.timelines.insert(id, Vector::new());
+        }
+        if let Some(timeline) = self.timelines.get_mut(&id) {
+            timeline.push_back(event);
+            if timeline.len() > TIMELINE_MAX {
+                // the pings go first, so that the connects and the errors stay
+                let ping = timeline
+                    .iter()
+                    .position(|x| matches!(x.kind, TimelineKind::PingReq | TimelineKind::PingResp));
+                match ping {
+                    Some(index) => {
+                        timeline.remove(index);
+                    }
+                    None => {
+                        timeline.pop_front();
+                    }
+                }
+            }
+        }
+    }
+    pub fn clear_timeline(&mut self, id: usize) {
+        if let Some(timeline) = self.timelines.get_mut(&id) {
+            timeline.clear();
+        }
+    }
+    /// one line per event, to copy
+    pub fn timeline_text(&self, id: usize) -> String {
+        let Some(timeline) = self.timelines.get(&id) else {
+            return String::new();
+        };
+        timeline
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
     pub fn disconnect(&mut self, id: usize) -> Result<()> {
         if let Some(status) = self.tab_statuses.get_mut(&id) {
             status.try_connect = false;
@@ -125,6 +443,7 @@ impl AppData {
         } else {
             debug!("not find the connection")
         }
+        self.stop_tasks(id);
         Ok(())
     }
     pub fn close_connection(&mut self, id: usize) {
@@ -134,23 +453,62 @@ impl AppData {
         } else {
             error!("can't find the connection");
         }
+        self.stop_tasks(id);
+    }
+    /// closed by the broker or the network, after the automatic reconnects.
+    /// false if it isn't connected or trying to
+    pub fn connection_lost(&mut self, id: usize) -> bool {
+        let Some(status) = self.tab_statuses.get_mut(&id) else {
+            return false;
+        };
+        let lost = status.connected || status.try_connect;
+        status.try_connect = false;
+        status.connected = false;
+        self.stop_tasks(id);
+        lost
+    }
+    /// as the tasks of the connection stopped by the logic worker
+    fn stop_tasks(&mut self, id: usize) {
+        if let Some(schedules) = self.public_schedules.get_mut(&id) {
+            schedules.clear();
+        }
+        if let Some(probe) = self.probes.get_mut(&id) {
+            probe.running = false;
+        }
+        if let Some(scan) = self.retain_scans.get_mut(&id) {
+            scan.scanning = false;
+            scan.confirming = false;
+        }
+        if let Some(script) = self.scripts.get_mut(&id) {
+            script.running = false;
+        }
+        if let Some(bridge) = self.bridges.get_mut(&id) {
+            bridge.running = false;
+        }
+        if let Some(requests) = self.requests.get_mut(&id) {
+            for request in requests.iter_mut() {
+                if request.status == RequestStatus::Waiting {
+                    request.status = RequestStatus::Timeout;
+                }
+            }
+        }
     }
     pub fn unscribeing(
         &mut self,
         broker_id: usize,
-        subscribe_pkid: u16,
+        subscribe_id: Id,
         unsubscribe_pkid: u16,
     ) -> Result<()> {
         if let Some(_broker) = self.find_broker(broker_id) {
             if let Some(list) = self.unsubscribe_ing.get_mut(&broker_id) {
                 list.push_back(UnsubcribeTracing {
-                    subscribe_pk_id: subscribe_pkid,
+                    subscribe_id,
                     unsubscribe_pk_id: unsubscribe_pkid,
                 })
             } else {
                 let mut list = Vector::new();
                 list.push_back(UnsubcribeTracing {
-                    subscribe_pk_id: subscribe_pkid,
+                    subscribe_id,
                     unsubscribe_pk_id: unsubscribe_pkid,
                 });
                 self.unsubscribe_ing.insert(broker_id, list);
@@ -175,7 +533,7 @@ impl AppData {
                         if let Some(index) = list
                             .iter_mut()
                             .enumerate()
-                            .find(|(_index, his)| (*his).pkid == tracing.subscribe_pk_id)
+                            .find(|(_index, his)| (*his).id == tracing.subscribe_id)
                             .map(|(index, _x)| index)
                         {
                             list.remove(index);
@@ -195,17 +553,17 @@ impl AppData {
         }
         Ok(())
     }
-    pub fn to_unscribe(&mut self, broker_id: usize, pkid: u16) -> Result<()> {
+    pub fn to_unscribe(&mut self, broker_id: usize, id: Id) -> Result<()> {
         if let Some(_broker) = self.find_broker(broker_id) {
             if let Some(list) = self.subscribe_topics.get_mut(&broker_id) {
-                if let Some(index) = list.iter_mut().find(|his| (*his).pkid == pkid) {
+                if let Some(index) = list.iter_mut().find(|his| (*his).id == id) {
                     index.status = SubscribeStatus::UnSubscribeIng;
                     let event = EventUnSubscribe {
                         broke_id: broker_id,
-                        subscribe_pk_id: index.pkid,
+                        subscribe_id: index.id,
                         topic: index.topic.as_ref().clone(),
                     };
-                    tx!(self.db.tx, AppEvent::UnSubscribeIng(event));
+                    tx!(self.tx, AppEvent::UnSubscribeIng(event));
                     return Ok(());
                 }
             }
@@ -245,34 +603,134 @@ impl AppData {
         pkid: u16,
     ) -> Result<()> {
         if let Some(subscribe_topics) = self.subscribe_topics.get_mut(&id) {
-            let sub = SubscribeTopic::from(input.clone(), pkid);
-            subscribe_topics.push_back(sub.into());
-        }
-        if let Some(subscribe_hises) = self.subscribe_hises.get_mut(&id) {
-            let his: SubscribeHis = input.into();
-            debug!("{:?}", subscribe_hises);
-            debug!("{:?}", his);
-            if subscribe_hises.iter().find(|x| *x == &his).is_none() {
-                subscribe_hises.push_back(his.into());
-                self.db.update_subscribe_his(id, &subscribe_hises)?;
+            for filter in input.filters.into_iter() {
+                let sub = SubscribeTopic::from(filter, pkid);
+                subscribe_topics.push_back(sub.into());
             }
         }
         Ok(())
     }
-    pub fn suback(&mut self, id: usize, input: SubAck) {
+    pub fn add_subscribe_filter(&mut self, id: usize) {
+        if let Some(input) = self.subscribe_input.get_mut(&id) {
+            input.filters.push_back(SubscribeFilter::default());
+        } else {
+            warn!("can't find the subscribe input");
+        }
+    }
+    pub fn remove_subscribe_filter(&mut self, id: usize, filter_id: Id) {
+        if let Some(input) = self.subscribe_input.get_mut(&id) {
+            input.filters.retain(|x| x.id != filter_id);
+            if input.filters.is_empty() {
+                input.filters.push_back(SubscribeFilter::default());
+            }
+        } else {
+            warn!("can't find the subscribe input");
+        }
+    }
+    /// the return codes of SUBACK are in the same order as the filters of SUBSCRIBE,
+    /// and so as the topics pushed by `subscribe_by_input`
+    pub fn suback(&mut self, id: usize, input: SubAck) -> Result<()> {
+        let mut success_hises = Vec::new();
         if let Some(subscribe_topics) = self.subscribe_topics.get_mut(&id) {
-            for msg in subscribe_topics.iter_mut() {
-                if msg.pkid == input.pkid {
-                    let code = input.return_codes[0];
-                    if code == SubscribeReasonCode::QoS0
-                        || code == SubscribeReasonCode::QoS1
-                        || code == SubscribeReasonCode::QoS2
-                    {
-                        msg.status = SubscribeStatus::SubscribeSuccess;
-                    }
+            let mut codes = input.return_codes.iter();
+            // the pkid is used again after the ack, only the pending subscribes are acked
+            for msg in subscribe_topics
+                .iter_mut()
+                .filter(|x| x.pkid == input.pkid && x.status == SubscribeStatus::SubscribeIng)
+            {
+                let Some(code) = codes.next() else {
+                    warn!("lack of return code for subscribe: {}", msg.topic);
+                    break;
+                };
+                if *code == SubscribeReasonCode::QoS0
+                    || *code == SubscribeReasonCode::QoS1
+                    || *code == SubscribeReasonCode::QoS2
+                {
+                    msg.status = SubscribeStatus::SubscribeSuccess;
+                    success_hises.push(SubscribeHis::from_topic(id, msg));
+                } else {
+                    msg.status = SubscribeStatus::SubscribeFail;
+                    warn!("subscribe {} fail: {:?}", msg.topic, code);
                 }
             }
         }
+        if let Some(subscribe_hises) = self.subscribe_hises.get_mut(&id) {
+            if success_hises.is_empty() {
+                return Ok(());
+            }
+            let now = now_millis();
+            for his in success_hises {
+                // the options of the last subscribe are kept
+                let used = SubscribeHis {
+                    use_count: 1,
+                    last_used: now,
+                    ..his
+                };
+                if let Some(x) = subscribe_hises.iter_mut().find(|x| *x == &used) {
+                    x.no_local = used.no_local;
+                    x.retain_as_published = used.retain_as_published;
+                    x.retain_handling = used.retain_handling;
+                    x.use_count += 1;
+                    x.last_used = now;
+                } else {
+                    subscribe_hises.push_back(used);
+                }
+            }
+            self.db.update_subscribe_his(id, &subscribe_hises)?;
+        }
+        Ok(())
+    }
+    /// the subscribe history of the selected broker matching the search: the pinned first,
+    /// then by the sort
+    pub fn subscribe_his_list(&self) -> Vector<SubscribeHis> {
+        let Some(id) = self.get_selected_broker_id() else {
+            return Vector::new();
+        };
+        let Some(hises) = self.subscribe_hises.get(&id) else {
+            return Vector::new();
+        };
+        let input = &self.subscribe_his_input;
+        let mut hises: Vec<SubscribeHis> = hises
+            .iter()
+            .filter(|x| x.matches(&input.search))
+            .cloned()
+            .collect();
+        match input.sort {
+            SubscribeHisSort::Recent => hises.sort_by(|a, b| b.last_used.cmp(&a.last_used)),
+            SubscribeHisSort::Frequent => hises.sort_by(|a, b| {
+                b.use_count
+                    .cmp(&a.use_count)
+                    .then(b.last_used.cmp(&a.last_used))
+            }),
+        }
+        hises.sort_by_key(|x| !x.pinned);
+        hises.into()
+    }
+    pub fn pin_subscribe_his(&mut self, his: SubscribeHis) -> Result<()> {
+        let id = his.broker_id;
+        let Some(hises) = self.subscribe_hises.get_mut(&id) else {
+            bail!("can't find the subscribe history of broker: {}", id);
+        };
+        let Some(x) = hises.iter_mut().find(|x| *x == &his) else {
+            bail!("can't find the subscribe history: {}", his.topic);
+        };
+        x.pinned = !x.pinned;
+        self.db.update_subscribe_his(id, hises)
+    }
+    /// the alias and the description of the selected subscribe history
+    pub fn save_subscribe_his_note(&mut self) -> Result<()> {
+        let Some(id) = self.get_selected_broker_id() else {
+            bail!(DELETE_SUBSCRIBE_NO_SELECTED);
+        };
+        let Some(hises) = self.subscribe_hises.get_mut(&id) else {
+            bail!(DELETE_SUBSCRIBE_NO_SELECTED);
+        };
+        let Some(his) = hises.iter_mut().find(|x| x.selected) else {
+            bail!(DELETE_SUBSCRIBE_NO_SELECTED);
+        };
+        his.alias = self.subscribe_his_input.alias.trim().to_string().into();
+        his.description = self.subscribe_his_input.description.clone();
+        self.db.update_subscribe_his(id, hises)
     }
     pub fn public(&mut self, id: usize, input: PublicInput, pkid: u16) {
         if let Some(msgs) = self.msgs.get_mut(&id) {
@@ -280,11 +738,632 @@ impl AppData {
             msgs.push_back(sub.into());
         }
     }
+    /// save the public input as a template, replace the one with the same name
+    pub fn save_public_template(&mut self, id: usize) -> Result<()> {
+        let Some(input) = self.public_input.get(&id) else {
+            bail!("can't find the public input");
+        };
+        if input.template.is_empty() {
+            bail!(SAVE_TEMPLATE_NO_NAME);
+        }
+        let template = PublicTemplate::from_input(id, input);
+        let Some(templates) = self.public_templates.get_mut(&id) else {
+            bail!("can't find the public templates");
+        };
+        if let Some(old) = templates.iter_mut().find(|x| x.name == template.name) {
+            *old = template;
+        } else {
+            templates.push_back(template);
+        }
+        self.db.update_public_templates(id, templates)?;
+        Ok(())
+    }
+    pub fn select_public_template(&mut self, id: usize, template_id: Id) -> Result<()> {
+        let Some(template) = self
+            .public_templates
+            .get(&id)
+            .and_then(|x| x.iter().find(|x| x.id == template_id))
+        else {
+            bail!("can't find the public template");
+        };
+        self.public_input.insert(id, template.to_input());
+        Ok(())
+    }
+    pub fn remove_public_template(&mut self, id: usize, template_id: Id) -> Result<()> {
+        let Some(templates) = self.public_templates.get_mut(&id) else {
+            bail!("can't find the public templates");
+        };
+        templates.retain(|x| x.id != template_id);
+        self.db.update_public_templates(id, templates)?;
+        Ok(())
+    }
+    /// the identical message moves to the front, the oldest not favourite is dropped if full.
+    /// It is kept in memory only until the broker is saved.
+    pub fn add_public_his(&mut self, id: usize, input: &PublicInput) -> Result<()> {
+        let Some(hises) = self.public_hises.get_mut(&id) else {
+            bail!("can't find the publish history");
+        };
+        let mut his = PublicHis::from_input(id, input);
+        his.last_used = now_millis();
+        if let Some(index) = hises.iter().position(|x| x == &his) {
+            his.favourite = hises.remove(index).favourite;
+        }
+        hises.push_front(his);
+        if hises.len() > PUBLIC_HIS_MAX {
+            if let Some(index) = hises.iter().rposition(|x| !x.favourite) {
+                hises.remove(index);
+            }
+        }
+        self.save_public_his(id)
+    }
+    /// the db knows only the saved brokers
+    fn save_public_his(&self, id: usize) -> Result<()> {
+        let (Some(hises), true) = (
+            self.public_hises.get(&id),
+            self.brokers.iter().any(|x| x.id == id && x.stored),
+        ) else {
+            return Ok(());
+        };
+        self.db.update_public_his(id, hises)
+    }
+    pub fn select_public_his(&mut self, id: usize, his_id: Id) -> Result<()> {
+        let Some(his) = self
+            .public_hises
+            .get(&id)
+            .and_then(|x| x.iter().find(|x| x.id == his_id))
+        else {
+            bail!("can't find the publish history");
+        };
+        self.public_input.insert(id, his.to_input());
+        Ok(())
+    }
+    pub fn favourite_public_his(&mut self, id: usize, his_id: Id) -> Result<()> {
+        let Some(hises) = self.public_hises.get_mut(&id) else {
+            bail!("can't find the publish history");
+        };
+        let Some(his) = hises.iter_mut().find(|x| x.id == his_id) else {
+            bail!("can't find the publish history");
+        };
+        his.favourite = !his.favourite;
+        self.save_public_his(id)
+    }
+    pub fn remove_public_his(&mut self, id: usize, his_id: Id) -> Result<()> {
+        let Some(hises) = self.public_hises.get_mut(&id) else {
+            bail!("can't find the publish history");
+        };
+        hises.retain(|x| x.id != his_id);
+        self.save_public_his(id)
+    }
+    pub fn add_schedule(&mut self, id: usize, schedule: PublicSchedule) {
+        if let Some(schedules) = self.public_schedules.get_mut(&id) {
+            schedules.push_back(schedule);
+        }
+    }
+    pub fn schedule_sent(&mut self, id: usize, schedule_id: Id) {
+        if let Some(schedule) = self
+            .public_schedules
+            .get_mut(&id)
+            .and_then(|x| x.iter_mut().find(|x| x.id == schedule_id))
+        {
+            schedule.sent += 1;
+        }
+    }
+    pub fn remove_schedule(&mut self, id: usize, schedule_id: Id) {
+        if let Some(schedules) = self.public_schedules.get_mut(&id) {
+            schedules.retain(|x| x.id != schedule_id);
+        }
+    }
+    pub fn probe_started(&mut self, id: usize) {
+        if let Some(probe) = self.probes.get_mut(&id) {
+            *probe = Probe {
+                running: true,
+                interval: probe.interval,
+                ..Probe::default()
+            };
+        }
+    }
+    pub fn probe_stopped(&mut self, id: usize) {
+        if let Some(probe) = self.probes.get_mut(&id) {
+            probe.running = false;
+        }
+    }
+    pub fn probe_sent(&mut self, id: usize) {
+        if let Some(probe) = self.probes.get_mut(&id) {
+            probe.sent += 1;
+        }
+    }
+    pub fn probe_received(&mut self, id: usize, sample: ProbeSample) {
+        if let Some(probe) = self.probes.get_mut(&id) {
+            probe.push(sample);
+        }
+    }
+    pub fn request_sent(&mut self, id: usize, record: RequestRecord) {
+        if let Some(requests) = self.requests.get_mut(&id) {
+            requests.push_front(record);
+        }
+    }
+    /// pair the msg with the waiting request by correlation data. false if it's not a reply
+    pub fn request_replied(&mut self, id: usize, msg: &SubscribeMsg, now_millis: u64) -> bool {
+        if msg.correlation.is_empty() {
+            return false;
+        }
+        let Some(requests) = self.requests.get_mut(&id) else {
+            return false;
+        };
+        let Some(request) = requests
+            .iter_mut()
+            .find(|x| x.correlation == msg.correlation)
+        else {
+            return false;
+        };
+        if request.status == RequestStatus::Replied {
+            warn!("duplicate reply: {}", msg.correlation);
+        }
+        request.status = RequestStatus::Replied;
+        request.reply = msg.msg.clone();
+        request.latency = now_millis.saturating_sub(request.sent_at);
+        true
+    }
+    pub fn request_timeout(&mut self, id: usize, correlation: AString) {
+        if let Some(request) = self
+            .requests
+            .get_mut(&id)
+            .and_then(|x| x.iter_mut().find(|x| x.correlation == correlation))
+        {
+            if request.status == RequestStatus::Waiting {
+                request.status = RequestStatus::Timeout;
+                warn!("{}", REQUEST_TIMEOUT);
+            }
+        }
+    }
+    /// the filter to scan, with the options of the user's subscription of the same filter if any,
+    /// which is subscribed again to get the retained messages but not unsubscribed at the end
+    pub fn retain_scan_filter(&self, id: usize) -> Result<SubscribeFilter> {
+        let Some(scan) = self.retain_scans.get(&id) else {
+            bail!("can't find the retain scan");
+        };
+        let subscribed = self.subscribe_topics.get(&id).and_then(|x| {
+            x.iter().find(|x| {
+                x.topic == scan.filter
+                    && matches!(
+                        x.status,
+                        SubscribeStatus::SubscribeIng | SubscribeStatus::SubscribeSuccess
+                    )
+            })
+        });
+        Ok(match subscribed {
+            Some(topic) => SubscribeFilter {
+                id: Id::default(),
+                topic: topic.topic.clone(),
+                qos: topic.qos.clone(),
+                no_local: topic.no_local,
+                retain_as_published: topic.retain_as_published,
+                retain_handling: RetainHandling::SendAtSubscribe,
+            },
+            None => SubscribeFilter {
+                topic: scan.filter.clone(),
+                qos: QoS::AtLeastOnce,
+                ..SubscribeFilter::default()
+            },
+        })
+    }
+    pub fn retain_scan_started(&mut self, id: usize) {
+        if let Some(scan) = self.retain_scans.get_mut(&id) {
+            scan.scanning = true;
+            scan.confirming = false;
+            if !scan.msgs.is_empty() {
+                scan.last = std::mem::take(&mut scan.msgs);
+            }
+        }
+    }
+    pub fn retain_received(&mut self, id: usize, mut msg: RetainMsg) {
+        if let Some(scan) = self.retain_scans.get_mut(&id) {
+            // the broker sends the retained message again when the same filter is subscribed again
+            if scan.msgs.iter().find(|x| x.topic == msg.topic).is_none() {
+                if let Some(last) = scan
+                    .last
+                    .iter()
+                    .find(|x| x.topic == msg.topic && x.hash == msg.hash)
+                {
+                    msg.first_seen = last.first_seen;
+                }
+                scan.msgs.push_back(msg);
+            }
+        }
+    }
+    pub fn retain_scan_end(&mut self, id: usize) {
+        if let Some(scan) = self.retain_scans.get_mut(&id) {
+            scan.scanning = false;
+            info!("{}: {}", RETAIN_SCAN_END, scan.msgs.len());
+        }
+    }
+    /// dry run: show the topics to clear, and wait for the confirmation
+    pub fn preview_retain_clear(&mut self, id: usize) {
+        let Some(scan) = self.retain_scans.get_mut(&id) else {
+            return;
+        };
+        let topics = scan.selected_topics();
+        if topics.is_empty() {
+            warn!("{}", RETAIN_NONE_SELECTED);
+            return;
+        }
+        scan.confirming = true;
+        info!(
+            "{} {}: {}",
+            RETAIN_CLEAR_PREVIEW,
+            topics.len(),
+            topics.join(", ")
+        );
+    }
+    pub fn cancel_retain_clear(&mut self, id: usize) {
+        if let Some(scan) = self.retain_scans.get_mut(&id) {
+            scan.confirming = false;
+        }
+    }
+    pub fn retain_cleared(&mut self, id: usize, topics: Vec<String>) {
+        if let Some(scan) = self.retain_scans.get_mut(&id) {
+            scan.confirming = false;
+            scan.msgs.retain(|x| {
+                !topics
+                    .iter()
+                    .any(|topic| topic.as_str() == x.topic.as_str())
+            });
+        }
+        info!("{}: {}", RETAIN_CLEAR_SUCCESS, topics.len());
+    }
+    /// a route to every target
+    pub fn bridge_routes(&self, id: usize) -> Result<Vec<BridgeRoute>> {
+        let Some(bridge) = self.bridges.get(&id) else {
+            bail!("can't find the bridge");
+        };
+        let connected = |id: usize| self.tab_statuses.get(&id).map_or(false, |x| x.connected);
+        let mut targets = Vec::new();
+        for target in bridge.target.split(',').map(|x| x.trim()) {
+            if target.is_empty() {
+                continue;
+            }
+            let Some(target) = self
+                .brokers
+                .iter()
+                .find(|x| x.name.as_str() == target)
+                .or_else(|| {
+                    target
+                        .parse::<usize>()
+                        .ok()
+                        .and_then(|target| self.find_broker(target))
+                })
+            else {
+                bail!("can't find the target broker: {}", target);
+            };
+            if target.id == id {
+                bail!("the target should be another broker");
+            }
+            if !connected(id) || !connected(target.id) {
+                bail!(BRIDGE_NOT_CONNECTED);
+            }
+            if !targets.contains(&target.id) {
+                targets.push(target.id);
+            }
+        }
+        if targets.is_empty() {
+            bail!("there is no target of the bridge");
+        }
+        let filters: Vec<String> = bridge
+            .filters
+            .split(',')
+            .map(|x| x.trim().to_string())
+            .filter(|x| !x.is_empty())
+            .collect();
+        if filters.is_empty() {
+            bail!("there is no filter of the bridge");
+        }
+        Ok(targets
+            .into_iter()
+            .map(|target| BridgeRoute {
+                source: id,
+                target,
+                filters: filters.clone(),
+                strip_prefix: bridge.strip_prefix.as_ref().clone(),
+                add_prefix: bridge.add_prefix.as_ref().clone(),
+                max_qos: bridge.max_qos.clone(),
+                both: bridge.both,
+            })
+            .collect())
+    }
+    pub fn bridge_started(&mut self, id: usize) {
+        if let Some(bridge) = self.bridges.get_mut(&id) {
+            bridge.running = true;
+            bridge.forwarded = 0;
+            bridge.backwarded = 0;
+            bridge.dropped = 0;
+        }
+    }
+    pub fn bridge_stopped(&mut self, id: usize) {
+        if let Some(bridge) = self.bridges.get_mut(&id) {
+            bridge.running = false;
+        }
+    }
+    pub fn bridge_forwarded(&mut self, id: usize, backward: bool) {
+        if let Some(bridge) = self.bridges.get_mut(&id) {
+            if backward {
+                bridge.backwarded += 1;
+            } else {
+                bridge.forwarded += 1;
+            }
+        }
+    }
+    pub fn bridge_dropped(&mut self, id: usize) {
+        if let Some(bridge) = self.bridges.get_mut(&id) {
+            bridge.dropped += 1;
+        }
+    }
+    /// add the broker entry of the embedded broker if not exists
+    /// the port is the one started, the input may have been edited since
+    pub fn local_broker_started(&mut self, port: u16) -> Result<()> {
+        self.local_broker.running = true;
+        info!("{}", LOCAL_BROKER_STARTED);
+        if self
+            .brokers
+            .iter()
+            .any(|x| x.addr.as_str() == "127.0.0.1" && x.port == port)
+        {
+            return Ok(());
+        }
+        let mut broker = self.new_broker();
+        broker.name = format!("local:{}", port).into();
+        broker.client_id = general_id().into();
+        broker.addr = "127.0.0.1".to_string().into();
+        broker.port = port;
+        let id = broker.id;
+        self.brokers.push_back(broker);
+        self.save_broker(id)?;
+        self.init_broker_tab(id);
+        Ok(())
+    }
+    pub fn vault_created(&mut self, meta: VaultMeta, key: VaultKey) -> Result<()> {
+        self.db.set_vault(meta, key)?;
+        self.vault.master = Default::default();
+        self.vault.enabled = true;
+        info!("{}", VAULT_SET);
+        let backups = self.db.backups()?;
+        if !backups.is_empty() {
+            warn!("{} {:?}", VAULT_PLAINTEXT_BACKUPS, backups);
+        }
+        Ok(())
+    }
+    pub fn unlock_vault(&mut self, meta: VaultMeta, key: VaultKey) -> Result<()> {
+        self.db.unlocked(meta, key);
+        for broker in self
+            .brokers
+            .iter_mut()
+            .filter(|x| x.stored && x.password.is_empty())
+        {
+            broker.password = self.db.read_password(broker.id)?.into();
+        }
+        self.vault.master = Default::default();
+        self.vault.locked = false;
+        info!("{}", VAULT_UNLOCKED);
+        Ok(())
+    }
+    /// the saved brokers of the ids, all if empty
+    pub fn export_profiles(&self, ids: &[usize], secrets: bool) -> Bundle {
+        let profiles = self
+            .brokers
+            .iter()
+            .filter(|x| x.stored && (ids.is_empty() || ids.contains(&x.id)))
+            .map(|x| {
+                let mut broker = x.clone_to_db();
+                if !secrets {
+                    broker.password = Default::default();
+                }
+                Profile {
+                    broker,
+                    subscribe_his: self.subscribe_hises.get(&x.id).cloned().unwrap_or_default(),
+                    public_templates: self
+                        .public_templates
+                        .get(&x.id)
+                        .cloned()
+                        .unwrap_or_default(),
+                    downgraded: false,
+                }
+            })
+            .collect();
+        Bundle {
+            version: BUNDLE_VERSION,
+            profiles,
+        }
+    }
+    /// the selected broker, or all the saved brokers if none is selected
+    pub fn export_profiles_to(&self, path: &str) -> Result<()> {
+        let ids: Vec<usize> = self.get_selected_broker_id().into_iter().collect();
+        let bundle = self.export_profiles(&ids, self.profile_input.secrets);
+        std::fs::write(path, serde_json::to_string_pretty(&bundle)?)?;
+        Ok(())
+    }
+    pub fn import_profiles(
+        &mut self,
+        profiles: Vec<Profile>,
+        conflict: Conflict,
+    ) -> Result<ImportSummary> {
+        let mut summary = ImportSummary::default();
+        for profile in profiles {
+            let Profile {
+                broker: mut imported,
+                subscribe_his,
+                public_templates,
+                downgraded,
+            } = profile;
+            let existing = self
+                .brokers
+                .iter()
+                .find(|x| x.stored && x.name == imported.name)
+                .map(|x| x.id);
+            let id = match (existing, conflict) {
+                (Some(_), Conflict::Skip) => {
+                    summary.skipped += 1;
+                    continue;
+                }
+                (Some(id), Conflict::Replace) => {
+                    summary.replaced += 1;
+                    id
+                }
+                (existing, _) => {
+                    if existing.is_some() {
+                        let used = self.brokers.iter().map(|x| x.name.to_string()).collect();
+                        imported.name = unique_name(&imported.name, &used).into();
+                    }
+                    let broker = self.new_broker();
+                    let id = broker.id;
+                    self.brokers.push_back(broker);
+                    summary.added += 1;
+                    id
+                }
+            };
+            let Some(broker) = self.brokers.iter_mut().find(|x| x.id == id) else {
+                bail!("can't find the broker: {}", id);
+            };
+            if downgraded {
+                summary.downgraded.push(imported.name.to_string());
+            }
+            let uuid = broker.uuid;
+            *broker = imported.to_broker(self.tx.clone());
+            broker.id = id;
+            broker.uuid = uuid;
+            self.save_broker(id)?;
+            let hises: Vector<SubscribeHis> = subscribe_his
+                .into_iter()
+                .map(|mut x| {
+                    x.broker_id = id;
+                    x
+                })
+                .collect();
+            let templates: Vector<PublicTemplate> = public_templates
+                .into_iter()
+                .map(|mut x| {
+                    x.broker_id = id;
+                    x
+                })
+                .collect();
+            self.db.update_subscribe_his(id, &hises)?;
+            self.db.update_public_templates(id, &templates)?;
+            self.subscribe_hises.insert(id, hises);
+            self.public_templates.insert(id, templates);
+        }
+        Ok(summary)
+    }
+    pub fn local_broker_event(&mut self, event: LocalBrokerEvent) {
+        let local = &mut self.local_broker;
+        match event {
+            LocalBrokerEvent::Connected(client_id) => {
+                if !local.clients.iter().any(|x| x.as_str() == client_id) {
+                    local.clients.push_back(client_id.into());
+                }
+            }
+            LocalBrokerEvent::Disconnected(client_id) => {
+                local.clients.retain(|x| x.as_str() != client_id);
+            }
+            LocalBrokerEvent::Retain(topic, size) => {
+                local.retains.retain(|x| x.topic.as_str() != topic);
+                if size > 0 {
+                    local.retains.push_back(RetainMsg {
+                        topic: topic.into(),
+                        size,
+                        preview: Default::default(),
+                        hash: 0,
+                        first_seen: now_millis(),
+                        expiry: 0,
+                        selected: false,
+                    });
+                }
+            }
+        }
+    }
+    pub fn add_rule(&mut self, id: usize) -> Result<()> {
+        let Some(input) = self.rule_input.get(&id) else {
+            bail!("can't find the rule input");
+        };
+        let rule = Rule {
+            id: Id::default(),
+            enabled: true,
+            fired: 0,
+            ..input.clone()
+        };
+        RuleMatcher::compile(&rule)?;
+        let Some(rules) = self.rules.get_mut(&id) else {
+            bail!("can't find the rules");
+        };
+        rules.push_back(rule);
+        self.db.update_rules(id, rules)?;
+        self.update_rules(id)
+    }
+    pub fn remove_rule(&mut self, id: usize, rule_id: Id) -> Result<()> {
+        let Some(rules) = self.rules.get_mut(&id) else {
+            bail!("can't find the rules");
+        };
+        rules.retain(|x| x.id != rule_id);
+        self.db.update_rules(id, rules)?;
+        self.update_rules(id)
+    }
+    pub fn toggle_rule(&mut self, id: usize, rule_id: Id) -> Result<()> {
+        let Some(rules) = self.rules.get_mut(&id) else {
+            bail!("can't find the rules");
+        };
+        if let Some(rule) = rules.iter_mut().find(|x| x.id == rule_id) {
+            rule.enabled = !rule.enabled;
+        }
+        self.db.update_rules(id, rules)?;
+        self.update_rules(id)
+    }
+    /// send the enabled rules to the logic worker
+    fn update_rules(&self, id: usize) -> Result<()> {
+        let rules = self
+            .rules
+            .get(&id)
+            .map(|x| x.iter().filter(|x| x.enabled).cloned().collect())
+            .unwrap_or_default();
+        self.tx.send(AppEvent::UpdateRules(id, rules))?;
+        Ok(())
+    }
+    pub fn rules_fired(&mut self, id: usize, rule_ids: Vec<Id>) {
+        if let Some(rules) = self.rules.get_mut(&id) {
+            for rule in rules.iter_mut() {
+                if rule_ids.contains(&rule.id) {
+                    rule.fired += 1;
+                }
+            }
+        }
+    }
+    pub fn script_loaded(&mut self, id: usize, code: AString) -> Result<()> {
+        let Some(script) = self.scripts.get_mut(&id) else {
+            bail!("can't find the script");
+        };
+        script.code = code;
+        script.enabled = true;
+        script.running = true;
+        self.db.update_script(id, script)?;
+        Ok(())
+    }
+    pub fn script_stopped(&mut self, id: usize) -> Result<()> {
+        let Some(script) = self.scripts.get_mut(&id) else {
+            bail!("can't find the script");
+        };
+        script.enabled = false;
+        script.running = false;
+        self.db.update_script(id, script)?;
+        Ok(())
+    }
+    pub fn script_output(&mut self, id: usize, lines: Vec<String>) {
+        if let Some(script) = self.scripts.get_mut(&id) {
+            for line in lines {
+                script.output(line);
+            }
+        }
+    }
     pub fn click_broker(&mut self, id: usize) -> Result<()> {
         self.select_broker(id);
         for (index, tab) in self.broker_tabs.iter().enumerate() {
             if *tab == id {
-                tx!(self.db.tx, AppEvent::SelectTabs(index));
+                tx!(self.tx, AppEvent::SelectTabs(index));
             }
         }
         Ok(())
@@ -298,11 +1377,16 @@ impl AppData {
         }
     }
     pub fn connect_broker(&mut self) {
-        if let Some(broker) = self.get_selected_broker() {
-            if let Err(e) = self.db.tx.send(AppEvent::Connect(broker.clone())) {
-                error!("{:?}", e);
+        if let Some(id) = self.get_selected_broker_id() {
+            match self.resolved_broker(id) {
+                Ok(broker) => {
+                    if let Err(e) = self.tx.send(AppEvent::Connect(broker)) {
+                        error!("{:?}", e);
+                    }
+                }
+                Err(e) => warn!("{}", e),
             }
-            self.init_broker_tab(broker.id);
+            self.init_broker_tab(id);
         } else {
             // todo
             warn!("connect_broker: not selected broker");
@@ -310,12 +1394,13 @@ impl AppData {
     }
     pub fn db_click_broker(&mut self, id: usize) {
         self.init_broker_tab(id);
-        if let Some(broker) = self.find_broker(id) {
-            if let Err(e) = self.db.tx.send(AppEvent::Connect(broker.clone())) {
-                error!("{:?}", e);
+        match self.resolved_broker(id) {
+            Ok(broker) => {
+                if let Err(e) = self.tx.send(AppEvent::Connect(broker)) {
+                    error!("{:?}", e);
+                }
             }
-        } else {
-            error!("can't find broker");
+            Err(e) => warn!("{}", e),
         }
     }
     fn select_broker(&mut self, id: usize) {
@@ -327,6 +1412,120 @@ impl AppData {
             }
         }
     }
+    /// copy the selected broker with its history, templates, script and rules. The client id is
+    /// generated again, or the brokers kick each other out.
+    pub fn duplicate_broker(&mut self) -> Result<()> {
+        let Some(source) = self.get_selected_broker().cloned() else {
+            bail!("not selected broker to duplicate");
+        };
+        let broker = self.new_broker();
+        let id = broker.id;
+        let used = self.brokers.iter().map(|x| x.name.to_string()).collect();
+        self.brokers.push_back(Broker {
+            id,
+            uuid: broker.uuid,
+            name: unique_name(&source.name, &used).into(),
+            // the variables resolve to the client id per environment
+            client_id: if source.client_id.is_empty() || has_var(&source.client_id) {
+                source.client_id.clone()
+            } else {
+                general_id().into()
+            },
+            selected: false,
+            ..source.clone()
+        });
+        self.save_broker(id)?;
+        let hises: Vector<SubscribeHis> = self
+            .subscribe_hises
+            .get(&source.id)
+            .cloned()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|x| SubscribeHis {
+                id: Id::default(),
+                broker_id: id,
+                selected: false,
+                ..x
+            })
+            .collect();
+        let templates: Vector<PublicTemplate> = self
+            .public_templates
+            .get(&source.id)
+            .cloned()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|x| PublicTemplate {
+                id: Id::default(),
+                broker_id: id,
+                ..x
+            })
+            .collect();
+        let script = self.scripts.get(&source.id).cloned().unwrap_or_default();
+        let rules: Vector<Rule> = self
+            .rules
+            .get(&source.id)
+            .cloned()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|x| Rule {
+                id: Id::default(),
+                fired: 0,
+                ..x
+            })
+            .collect();
+        self.db.update_subscribe_his(id, &hises)?;
+        self.db.update_public_templates(id, &templates)?;
+        self.db.update_script(id, &script)?;
+        self.db.update_rules(id, &rules)?;
+        self.subscribe_hises.insert(id, hises);
+        self.public_templates.insert(id, templates);
+        self.scripts.insert(id, script);
+        self.rules.insert(id, rules);
+        Ok(())
+    }
+    /// the broker with the variables of its environment substituted, to connect
+    pub fn resolved_broker(&self, id: usize) -> Result<Broker> {
+        let Some(broker) = self.find_broker(id) else {
+            bail!("can't find the broker: {}", id);
+        };
+        let env = if broker.env.is_empty() {
+            None
+        } else {
+            let Some(env) = self.environments.iter().find(|x| x.name == broker.env) else {
+                bail!("can't find the environment: {}", broker.env);
+            };
+            Some(env)
+        };
+        crate::util::env::resolve(broker, env)
+    }
+    pub fn add_environment(&mut self) -> Result<()> {
+        let name = self.env_input.name.trim().to_string();
+        if name.is_empty() {
+            bail!("{}", ENVIRONMENT_NO_NAME);
+        }
+        if self.environments.iter().any(|x| x.name.as_str() == name) {
+            bail!("the environment exists: {}", name);
+        }
+        parse_vars(&self.env_input.vars)?;
+        self.environments.push_back(Environment {
+            id: Id::default(),
+            name: name.into(),
+            vars: self.env_input.vars.clone(),
+        });
+        self.env_input = Environment::default();
+        self.db.update_environments(&self.environments)
+    }
+    pub fn remove_environment(&mut self, id: Id) -> Result<()> {
+        self.environments.retain(|x| x.id != id);
+        self.db.update_environments(&self.environments)
+    }
+    /// the variables edited in the list
+    pub fn save_environments(&mut self) -> Result<()> {
+        for env in self.environments.iter() {
+            parse_vars(&env.vars)?;
+        }
+        self.db.update_environments(&self.environments)
+    }
     pub fn delete_broker(&mut self) -> Result<()> {
         let mut selected_index = None;
         for (index, broker) in self.brokers.iter().enumerate() {
@@ -348,8 +1547,8 @@ impl AppData {
             }
             self.tab_statuses.remove(&broker.id);
             self.db.delete_broker(broker.id)?;
-            self.db.tx.send(AppEvent::Disconnect(index))?;
-            // self.db.tx.send(AppEvent::CloseBrokerTab(index))?;
+            self.tx.send(AppEvent::Disconnect(index))?;
+            // self.tx.send(AppEvent::CloseBrokerTab(index))?;
         } else {
             bail!("not selected broker to delete");
         }
@@ -369,6 +1568,8 @@ impl AppData {
                     x.selected = false;
                 }
             });
+            self.subscribe_his_input.alias = his.alias;
+            self.subscribe_his_input.description = his.description;
         } else {
             warn!("could not get subscribe hises of broker selected");
         }
@@ -387,7 +1588,7 @@ impl AppData {
                 self.brokers.remove(index);
                 self.tab_statuses.remove(&id);
             }
-            if self.db.tx.send(AppEvent::Disconnect(id)).is_err() {
+            if self.tx.send(AppEvent::Disconnect(id)).is_err() {
                 error!("fail to send event");
             }
         }
@@ -415,6 +1616,426 @@ impl AppData {
 }
 #[derive(Debug, Clone, Data)]
 pub struct UnsubcribeTracing {
-    pub subscribe_pk_id: u16,
+    pub subscribe_id: Id,
     pub unsubscribe_pk_id: u16,
 }
+
+const OPTION: &str = r#"{
+	"keep_alive": 60,
+	"clean_session": true,
+	"max_incoming_packet_size": 10240,
+	"max_outgoing_packet_size": 10240,
+	"inflight": 100,
+	"conn_timeout": 5
+}
+        "#;
+
+#[cfg(test)]
+mod test {
+    use crate::data::common::{
+        Id, PublicInput, PublicSchedule, QoS, RetainMsg, SubscribeFilter, SubscribeHis,
+        SubscribeHisSort, SubscribeInput, SubscribeMsg, SubscribeStatus, TimelineEvent,
+        TimelineKind, PUBLIC_HIS_MAX, TIMELINE_MAX,
+    };
+    use crate::data::hierarchy::AppData;
+    use crate::util::db::ArcDb;
+    use druid::im::vector;
+    use rumqttc::v5::mqttbytes::{SubAck, SubscribeReasonCode};
+
+    #[test]
+    fn test_move_broker() {
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let db = ArcDb::init_temporary_db().unwrap();
+        let mut data = AppData::load(db.clone(), tx.clone()).unwrap();
+        for (name, folder, tags) in [
+            ("a", "dev", "edge"),
+            ("b", "prod", ""),
+            ("c", "", "cloud, v5"),
+        ] {
+            data.add_broker();
+            let broker = data.brokers.last_mut().unwrap();
+            broker.name = name.to_string().into();
+            broker.folder = folder.to_string().into();
+            broker.tags = tags.to_string().into();
+            let id = broker.id;
+            data.save_broker(id).unwrap();
+        }
+        let names = |data: &AppData| -> Vec<String> {
+            data.broker_list()
+                .iter()
+                .map(|x| x.name.to_string())
+                .collect()
+        };
+        // grouped by the folder
+        assert_eq!(names(&data), ["c", "a", "b"]);
+        data.broker_search = "V5".to_string().into();
+        assert_eq!(names(&data), ["c"]);
+        data.broker_search = Default::default();
+        let folders = |data: &AppData| -> Vec<(String, usize, usize)> {
+            data.broker_folders()
+                .iter()
+                .map(|x| (x.name.to_string(), x.count, x.brokers.len()))
+                .collect()
+        };
+        data.toggle_broker_folder("dev".to_string().into());
+        assert_eq!(
+            folders(&data),
+            [("", 1, 1), ("dev", 1, 0), ("prod", 1, 1)].map(|(a, b, c)| (a.to_string(), b, c))
+        );
+        // expanded while searching
+        data.broker_search = "edge".to_string().into();
+        assert_eq!(folders(&data), [("dev".to_string(), 1, 1)]);
+        data.toggle_broker_folder("dev".to_string().into());
+        assert!(data.collapsed_folders.is_empty());
+        data.broker_search = "pro".to_string().into();
+        assert_eq!(names(&data), ["b"]);
+        data.broker_search = "broker-cn".to_string().into();
+        assert_eq!(names(&data).len(), 3);
+        data.broker_search = Default::default();
+
+        // c dropped on a: moved into the folder of a
+        data.move_broker(2, 0).unwrap();
+        assert_eq!(data.brokers[0].folder.as_str(), "dev");
+        assert_eq!(names(&data), ["c", "a", "b"]);
+        data.move_broker(2, 1).unwrap();
+        assert_eq!(data.brokers[2].name.as_str(), "c");
+        assert_eq!(names(&data), ["a", "b", "c"]);
+
+        let restarted = ArcDb::open(db.db.clone(), None).unwrap();
+        let data = AppData::load(restarted.clone(), tx.clone()).unwrap();
+        let saved: Vec<_> = data
+            .brokers
+            .iter()
+            .map(|x| (x.name.to_string(), x.folder.to_string()))
+            .collect();
+        assert_eq!(
+            saved,
+            [("a", "dev"), ("b", "prod"), ("c", "prod")]
+                .map(|(a, b)| (a.to_string(), b.to_string()))
+        );
+    }
+
+    #[test]
+    fn test_duplicate_broker() {
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let db = ArcDb::init_temporary_db().unwrap();
+        let mut data = AppData::load(db.clone(), tx.clone()).unwrap();
+        data.add_broker();
+        let broker = data.brokers.last_mut().unwrap();
+        broker.name = "staging".to_string().into();
+        broker.addr = "${HOST}".to_string().into();
+        broker.client_id = "${TENANT}-1".to_string().into();
+        broker.env = "staging".to_string().into();
+        let id = broker.id;
+        data.save_broker(id).unwrap();
+        data.db
+            .update_subscribe_his(
+                id,
+                &vector![SubscribeHis {
+                    id: Id::default(),
+                    broker_id: id,
+                    selected: false,
+                    topic: "a/#".to_string().into(),
+                    qos: QoS::AtLeastOnce,
+                    ..SubscribeHis::default()
+                }],
+            )
+            .unwrap();
+        let mut data = AppData::load(db.clone(), tx.clone()).unwrap();
+        data.brokers[0].selected = true;
+        data.duplicate_broker().unwrap();
+        let copied = data.brokers.last().unwrap().clone();
+        assert_ne!(copied.id, id);
+        assert_eq!(copied.name.as_str(), "staging (2)");
+        assert_eq!(copied.client_id.as_str(), "${TENANT}-1");
+        assert_eq!(copied.addr.as_str(), "${HOST}");
+        let hises = data.subscribe_hises.get(&copied.id).unwrap();
+        assert_eq!(hises[0].broker_id, copied.id);
+        assert_eq!(hises[0].topic.as_str(), "a/#");
+
+        // the environment is required once named
+        assert!(data.resolved_broker(id).is_err());
+        data.env_input.name = "staging".to_string().into();
+        data.env_input.vars = "HOST=staging.example.com\nTENANT=t1".to_string().into();
+        data.add_environment().unwrap();
+        assert!(data.add_environment().is_err());
+        let resolved = data.resolved_broker(id).unwrap();
+        assert_eq!(resolved.addr.as_str(), "staging.example.com");
+        assert_eq!(resolved.client_id.as_str(), "t1-1");
+        // nothing is substituted without the environment
+        let copied_id = copied.id;
+        data.brokers
+            .iter_mut()
+            .find(|x| x.id == copied_id)
+            .unwrap()
+            .env = "".to_string().into();
+        let resolved = data.resolved_broker(copied_id).unwrap();
+        assert_eq!(resolved.client_id.as_str(), "${TENANT}-1");
+
+        let restarted = ArcDb::open(db.db.clone(), None).unwrap();
+        let data = AppData::load(restarted.clone(), tx.clone()).unwrap();
+        assert_eq!(data.brokers.len(), 2);
+        assert_eq!(data.environments.len(), 1);
+        assert!(data.resolved_broker(id).is_ok());
+    }
+
+    #[test]
+    fn test_subscribe_his() {
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let db = ArcDb::init_temporary_db().unwrap();
+        let mut data = AppData::load(db.clone(), tx.clone()).unwrap();
+        data.add_broker();
+        let broker = data.brokers.last_mut().unwrap();
+        broker.selected = true;
+        let id = broker.id;
+        data.init_connection(id).unwrap();
+        let filter = |topic: &str, no_local: bool| SubscribeFilter {
+            topic: topic.to_string().into(),
+            no_local,
+            ..SubscribeFilter::default()
+        };
+        let suback = |data: &mut AppData, pkid: u16, filters: Vec<SubscribeFilter>| {
+            let return_codes = vec![SubscribeReasonCode::QoS0; filters.len()];
+            let input = SubscribeInput {
+                broker_id: id,
+                filters: filters.into_iter().collect(),
+            };
+            data.subscribe_by_input(id, input, pkid).unwrap();
+            let ack = SubAck {
+                pkid,
+                return_codes,
+                properties: None,
+            };
+            data.suback(id, ack).unwrap();
+        };
+        suback(
+            &mut data,
+            1,
+            vec![filter("a/#", false), filter("b/#", false)],
+        );
+        suback(&mut data, 2, vec![filter("a/#", true)]);
+        suback(&mut data, 3, vec![filter("a/#", true)]);
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        suback(&mut data, 4, vec![filter("b/#", false)]);
+        let hises = &data.subscribe_hises[&id];
+        assert_eq!(hises.len(), 2);
+        let a = hises[0].clone();
+        let b = hises[1].clone();
+        assert_eq!((a.use_count, b.use_count), (3, 2));
+        // the options of the last subscribe
+        assert!(a.no_local);
+        assert!(a.to_input().filters[0].no_local);
+
+        let topics = |data: &AppData| -> Vec<String> {
+            data.subscribe_his_list()
+                .iter()
+                .map(|x| x.topic.to_string())
+                .collect()
+        };
+        assert_eq!(topics(&data), ["b/#", "a/#"]);
+        data.subscribe_his_input.sort = SubscribeHisSort::Frequent;
+        assert_eq!(topics(&data), ["a/#", "b/#"]);
+        data.pin_subscribe_his(b).unwrap();
+        assert_eq!(topics(&data), ["b/#", "a/#"]);
+
+        data.click_subscribe_his(a).unwrap();
+        data.subscribe_his_input.alias = " sensors ".to_string().into();
+        data.subscribe_his_input.description = "all of the sensors".to_string().into();
+        data.save_subscribe_his_note().unwrap();
+        data.subscribe_his_input.search = "SENSOR".to_string().into();
+        assert_eq!(topics(&data), ["a/#"]);
+        assert_eq!(data.subscribe_his_list()[0].title().as_str(), "sensors");
+
+        let restarted = ArcDb::open(db.db.clone(), None).unwrap();
+        let data = AppData::load(restarted.clone(), tx.clone()).unwrap();
+        let saved = &data.subscribe_hises[&id];
+        assert_eq!(saved[0].alias.as_str(), "sensors");
+        assert_eq!(saved[0].use_count, 3);
+        assert!(saved[1].pinned);
+    }
+
+    #[test]
+    fn test_suback_pkid_reused() {
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let db = ArcDb::init_temporary_db().unwrap();
+        let mut data = AppData::load(db, tx).unwrap();
+        data.add_broker();
+        let id = data.brokers[0].id;
+        data.init_connection(id).unwrap();
+        let subscribe = |data: &mut AppData, topic: &str, code: SubscribeReasonCode| {
+            let input = SubscribeInput {
+                broker_id: id,
+                filters: vector![SubscribeFilter {
+                    topic: topic.to_string().into(),
+                    ..SubscribeFilter::default()
+                }],
+            };
+            data.subscribe_by_input(id, input, 1).unwrap();
+            let ack = SubAck {
+                pkid: 1,
+                return_codes: vec![code],
+                properties: None,
+            };
+            data.suback(id, ack).unwrap();
+        };
+        subscribe(&mut data, "a/#", SubscribeReasonCode::QoS0);
+        // the pkid is used again, the acked subscribe keeps its status
+        subscribe(&mut data, "b/#", SubscribeReasonCode::NotAuthorized);
+        let status = |topic: &str| {
+            data.subscribe_topics[&id]
+                .iter()
+                .find(|x| x.topic.as_str() == topic)
+                .map(|x| x.status.clone())
+        };
+        assert_eq!(status("a/#"), Some(SubscribeStatus::SubscribeSuccess));
+        assert_eq!(status("b/#"), Some(SubscribeStatus::SubscribeFail));
+    }
+
+    #[test]
+    fn test_public_his() {
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let db = ArcDb::init_temporary_db().unwrap();
+        let mut data = AppData::load(db.clone(), tx.clone()).unwrap();
+        data.add_broker();
+        let id = data.brokers[0].id;
+        data.save_broker(id).unwrap();
+        let input = |msg: &str| PublicInput {
+            topic: "a/1".to_string().into(),
+            msg: msg.to_string().into(),
+            qos: QoS::AtLeastOnce,
+            ..PublicInput::default()
+        };
+        data.add_public_his(id, &input("on")).unwrap();
+        data.add_public_his(id, &input("off")).unwrap();
+        let on = data.public_hises[&id][1].id;
+        data.favourite_public_his(id, on).unwrap();
+        // merged and moved to the front
+        data.add_public_his(id, &input("on")).unwrap();
+        let hises = &data.public_hises[&id];
+        assert_eq!(hises.len(), 2);
+        assert_eq!(hises[0].msg.as_str(), "on");
+        assert!(hises[0].favourite);
+
+        for index in 0..PUBLIC_HIS_MAX {
+            data.add_public_his(id, &input(&index.to_string())).unwrap();
+        }
+        let hises = &data.public_hises[&id];
+        assert_eq!(hises.len(), PUBLIC_HIS_MAX);
+        assert!(hises.iter().any(|x| x.msg.as_str() == "on"));
+        assert!(!hises.iter().any(|x| x.msg.as_str() == "off"));
+
+        let last = hises[0].id;
+        data.select_public_his(id, last).unwrap();
+        let selected = &data.public_input[&id];
+        assert_eq!(
+            selected.msg.as_str(),
+            (PUBLIC_HIS_MAX - 1).to_string().as_str()
+        );
+        data.remove_public_his(id, last).unwrap();
+        // in memory only until the broker is saved
+        data.add_broker();
+        let unsaved = data.brokers.last().unwrap().id;
+        data.public_hises.insert(unsaved, Default::default());
+        data.add_public_his(unsaved, &input("on")).unwrap();
+        data.save_broker(unsaved).unwrap();
+
+        let restarted = ArcDb::open(db.db.clone(), None).unwrap();
+        let data = AppData::load(restarted.clone(), tx.clone()).unwrap();
+        let saved = &data.public_hises[&id];
+        assert_eq!(saved.len(), PUBLIC_HIS_MAX - 1);
+        assert!(saved.iter().any(|x| x.msg.as_str() == "on" && x.favourite));
+        assert_eq!(data.public_hises[&unsaved].len(), 1);
+    }
+
+    #[test]
+    fn test_close_connection() {
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let db = ArcDb::init_temporary_db().unwrap();
+        let mut data = AppData::load(db.clone(), tx.clone()).unwrap();
+        data.add_broker();
+        let id = data.brokers[0].id;
+        data.init_connection(id).unwrap();
+        let input = PublicInput {
+            topic: "a/1".to_string().into(),
+            interval: 1000,
+            ..PublicInput::default()
+        };
+        data.add_schedule(id, PublicSchedule::from(Id::default(), &input));
+        assert_eq!(data.public_schedules[&id].len(), 1);
+        data.close_connection(id);
+        assert!(data.public_schedules[&id].is_empty());
+    }
+
+    #[test]
+    fn test_retain_scan() {
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let db = ArcDb::init_temporary_db().unwrap();
+        let mut data = AppData::load(db.clone(), tx.clone()).unwrap();
+        data.add_broker();
+        let id = data.brokers[0].id;
+        data.init_connection(id).unwrap();
+        data.retain_scans.get_mut(&id).unwrap().filter = "a/#".to_string().into();
+        let filter = data.retain_scan_filter(id).unwrap();
+        assert_eq!(filter.qos, QoS::AtLeastOnce);
+
+        // the user's subscription keeps its options
+        let input = SubscribeInput {
+            broker_id: id,
+            filters: vector![SubscribeFilter {
+                topic: "a/#".to_string().into(),
+                qos: QoS::ExactlyOnce,
+                no_local: true,
+                ..SubscribeFilter::default()
+            }],
+        };
+        data.subscribe_by_input(id, input, 1).unwrap();
+        let filter = data.retain_scan_filter(id).unwrap();
+        assert_eq!(filter.qos, QoS::ExactlyOnce);
+        assert!(filter.no_local);
+
+        let msg = |payload: &str| SubscribeMsg {
+            retain: true,
+            ..SubscribeMsg::sample("a/1", payload)
+        };
+        data.retain_scan_started(id);
+        data.retain_received(id, RetainMsg::from(&msg("on"), 1_000));
+        data.retain_scan_end(id);
+        let retain = &data.retain_scans[&id].msgs[0];
+        assert_eq!((retain.size, retain.preview.as_str()), (2, "on"));
+        // the age of an unchanged message is counted from the first scan
+        data.retain_scan_started(id);
+        data.retain_received(id, RetainMsg::from(&msg("on"), 61_000));
+        let retain = &data.retain_scans[&id].msgs[0];
+        assert_eq!(retain.age(61_000), "1m");
+        data.retain_scan_started(id);
+        data.retain_received(id, RetainMsg::from(&msg("off"), 62_000));
+        assert_eq!(data.retain_scans[&id].msgs[0].age(62_000), "0s");
+    }
+
+    #[test]
+    fn test_timeline() {
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let mut data = AppData::load(ArcDb::init_temporary_db().unwrap(), tx).unwrap();
+        data.push_timeline(0, TimelineEvent::new(TimelineKind::Connect, "a:1883"));
+        data.push_timeline(
+            0,
+            TimelineEvent::new(TimelineKind::PingResp, "round trip: 3ms"),
+        );
+        let text = data.timeline_text(0);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with(" CONNECT a:1883"));
+        assert!(lines[1].ends_with(" PINGRESP round trip: 3ms"));
+
+        for _ in 0..TIMELINE_MAX {
+            data.push_timeline(0, TimelineEvent::new(TimelineKind::PingReq, ""));
+        }
+        let timeline = &data.timelines[&0];
+        assert_eq!(timeline.len(), TIMELINE_MAX);
+        // the pings are dropped first
+        assert_eq!(timeline[0].kind, TimelineKind::Connect);
+        assert_eq!(timeline[1].kind, TimelineKind::PingReq);
+        data.clear_timeline(0);
+        assert_eq!(data.timeline_text(0), "");
+    }
+}
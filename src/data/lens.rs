@@ -1,6 +1,8 @@
-use crate::data::common::Broker;
+use crate::data::common::{Bridge, Broker, BrokerFolder};
 use crate::data::common::{
-    Msg, PublicInput, QoS, SubscribeHis, SubscribeInput, SubscribeTopic, TabStatus,
+    Msg, Probe, PublicHis, PublicInput, PublicSchedule, PublicTemplate, QoS, RequestInput,
+    RequestRecord, RetainScan, Rule, Script, SubscribeHis, SubscribeInput, SubscribeTopic,
+    TabStatus, TimelineEvent,
 };
 use crate::data::hierarchy::AppData;
 use crate::data::AString;
@@ -48,36 +50,19 @@ impl druid::Lens<AppData, Vector<SubscribeHis>> for BrokerIndexLensVecSubscribeH
     }
 }
 
+/// see `AppData::subscribe_his_list`, changed by the events only
 pub struct LensSelectedSubscribeHis;
 
 impl druid::Lens<AppData, Vector<SubscribeHis>> for LensSelectedSubscribeHis {
     fn with<V, F: FnOnce(&Vector<SubscribeHis>) -> V>(&self, data: &AppData, f: F) -> V {
-        if let Some(broker) = data.get_selected_broker() {
-            f(match data.subscribe_hises.get(&broker.id) {
-                Some(broker) => broker,
-                None => unreachable!(""),
-            })
-        } else {
-            let datas = Vector::new();
-            f(&datas)
-        }
+        f(&data.subscribe_his_list())
     }
     fn with_mut<V, F: FnOnce(&mut Vector<SubscribeHis>) -> V>(
         &self,
         data: &mut AppData,
         f: F,
     ) -> V {
-        let id = match data.get_selected_broker() {
-            Some(broker) => broker.id,
-            None => {
-                let mut datas = Vector::new();
-                return f(&mut datas);
-            }
-        };
-        f(match data.subscribe_hises.get_mut(&id) {
-            Some(broker) => broker,
-            None => unreachable!(""),
-        })
+        f(&mut data.subscribe_his_list())
     }
 }
 pub struct BrokerIndexLensVecSubscribeTopic(pub usize);
@@ -150,6 +135,226 @@ impl druid::Lens<AppData, PublicInput> for BrokerIndexLensPublicInput {
     }
 }
 
+pub struct BrokerIndexLensVecPublicTemplate(pub usize);
+
+impl druid::Lens<AppData, Vector<PublicTemplate>> for BrokerIndexLensVecPublicTemplate {
+    fn with<V, F: FnOnce(&Vector<PublicTemplate>) -> V>(&self, data: &AppData, f: F) -> V {
+        f(match data.public_templates.get(&self.0) {
+            Some(broker) => broker,
+            None => unreachable!(""),
+        })
+    }
+    fn with_mut<V, F: FnOnce(&mut Vector<PublicTemplate>) -> V>(
+        &self,
+        data: &mut AppData,
+        f: F,
+    ) -> V {
+        f(match data.public_templates.get_mut(&self.0) {
+            Some(broker) => broker,
+            None => unreachable!(""),
+        })
+    }
+}
+
+pub struct BrokerIndexLensVecPublicHis(pub usize);
+
+impl druid::Lens<AppData, Vector<PublicHis>> for BrokerIndexLensVecPublicHis {
+    fn with<V, F: FnOnce(&Vector<PublicHis>) -> V>(&self, data: &AppData, f: F) -> V {
+        f(match data.public_hises.get(&self.0) {
+            Some(broker) => broker,
+            None => unreachable!(""),
+        })
+    }
+    fn with_mut<V, F: FnOnce(&mut Vector<PublicHis>) -> V>(&self, data: &mut AppData, f: F) -> V {
+        f(match data.public_hises.get_mut(&self.0) {
+            Some(broker) => broker,
+            None => unreachable!(""),
+        })
+    }
+}
+
+pub struct BrokerIndexLensVecPublicSchedule(pub usize);
+
+impl druid::Lens<AppData, Vector<PublicSchedule>> for BrokerIndexLensVecPublicSchedule {
+    fn with<V, F: FnOnce(&Vector<PublicSchedule>) -> V>(&self, data: &AppData, f: F) -> V {
+        f(match data.public_schedules.get(&self.0) {
+            Some(broker) => broker,
+            None => unreachable!(""),
+        })
+    }
+    fn with_mut<V, F: FnOnce(&mut Vector<PublicSchedule>) -> V>(
+        &self,
+        data: &mut AppData,
+        f: F,
+    ) -> V {
+        f(match data.public_schedules.get_mut(&self.0) {
+            Some(broker) => broker,
+            None => unreachable!(""),
+        })
+    }
+}
+
+pub struct BrokerIndexLensRequestInput(pub usize);
+
+impl druid::Lens<AppData, RequestInput> for BrokerIndexLensRequestInput {
+    fn with<V, F: FnOnce(&RequestInput) -> V>(&self, data: &AppData, f: F) -> V {
+        f(match data.request_input.get(&self.0) {
+            Some(broker) => broker,
+            None => unreachable!(""),
+        })
+    }
+    fn with_mut<V, F: FnOnce(&mut RequestInput) -> V>(&self, data: &mut AppData, f: F) -> V {
+        f(match data.request_input.get_mut(&self.0) {
+            Some(broker) => broker,
+            None => unreachable!(""),
+        })
+    }
+}
+
+pub struct BrokerIndexLensVecRequestRecord(pub usize);
+
+impl druid::Lens<AppData, Vector<RequestRecord>> for BrokerIndexLensVecRequestRecord {
+    fn with<V, F: FnOnce(&Vector<RequestRecord>) -> V>(&self, data: &AppData, f: F) -> V {
+        f(match data.requests.get(&self.0) {
+            Some(broker) => broker,
+            None => unreachable!(""),
+        })
+    }
+    fn with_mut<V, F: FnOnce(&mut Vector<RequestRecord>) -> V>(
+        &self,
+        data: &mut AppData,
+        f: F,
+    ) -> V {
+        f(match data.requests.get_mut(&self.0) {
+            Some(broker) => broker,
+            None => unreachable!(""),
+        })
+    }
+}
+
+pub struct BrokerIndexLensRetainScan(pub usize);
+
+impl druid::Lens<AppData, RetainScan> for BrokerIndexLensRetainScan {
+    fn with<V, F: FnOnce(&RetainScan) -> V>(&self, data: &AppData, f: F) -> V {
+        f(match data.retain_scans.get(&self.0) {
+            Some(broker) => broker,
+            None => unreachable!(""),
+        })
+    }
+    fn with_mut<V, F: FnOnce(&mut RetainScan) -> V>(&self, data: &mut AppData, f: F) -> V {
+        f(match data.retain_scans.get_mut(&self.0) {
+            Some(broker) => broker,
+            None => unreachable!(""),
+        })
+    }
+}
+
+pub struct BrokerIndexLensBridge(pub usize);
+
+impl druid::Lens<AppData, Bridge> for BrokerIndexLensBridge {
+    fn with<V, F: FnOnce(&Bridge) -> V>(&self, data: &AppData, f: F) -> V {
+        f(match data.bridges.get(&self.0) {
+            Some(broker) => broker,
+            None => unreachable!(""),
+        })
+    }
+    fn with_mut<V, F: FnOnce(&mut Bridge) -> V>(&self, data: &mut AppData, f: F) -> V {
+        f(match data.bridges.get_mut(&self.0) {
+            Some(broker) => broker,
+            None => unreachable!(""),
+        })
+    }
+}
+
+pub struct BrokerIndexLensVecRule(pub usize);
+
+impl druid::Lens<AppData, Vector<Rule>> for BrokerIndexLensVecRule {
+    fn with<V, F: FnOnce(&Vector<Rule>) -> V>(&self, data: &AppData, f: F) -> V {
+        f(match data.rules.get(&self.0) {
+            Some(broker) => broker,
+            None => unreachable!(""),
+        })
+    }
+    fn with_mut<V, F: FnOnce(&mut Vector<Rule>) -> V>(&self, data: &mut AppData, f: F) -> V {
+        f(match data.rules.get_mut(&self.0) {
+            Some(broker) => broker,
+            None => unreachable!(""),
+        })
+    }
+}
+
+pub struct BrokerIndexLensRuleInput(pub usize);
+
+impl druid::Lens<AppData, Rule> for BrokerIndexLensRuleInput {
+    fn with<V, F: FnOnce(&Rule) -> V>(&self, data: &AppData, f: F) -> V {
+        f(match data.rule_input.get(&self.0) {
+            Some(broker) => broker,
+            None => unreachable!(""),
+        })
+    }
+    fn with_mut<V, F: FnOnce(&mut Rule) -> V>(&self, data: &mut AppData, f: F) -> V {
+        f(match data.rule_input.get_mut(&self.0) {
+            Some(broker) => broker,
+            None => unreachable!(""),
+        })
+    }
+}
+
+pub struct BrokerIndexLensScript(pub usize);
+
+impl druid::Lens<AppData, Script> for BrokerIndexLensScript {
+    fn with<V, F: FnOnce(&Script) -> V>(&self, data: &AppData, f: F) -> V {
+        f(match data.scripts.get(&self.0) {
+            Some(broker) => broker,
+            None => unreachable!(""),
+        })
+    }
+    fn with_mut<V, F: FnOnce(&mut Script) -> V>(&self, data: &mut AppData, f: F) -> V {
+        f(match data.scripts.get_mut(&self.0) {
+            Some(broker) => broker,
+            None => unreachable!(""),
+        })
+    }
+}
+
+pub struct BrokerIndexLensProbe(pub usize);
+
+impl druid::Lens<AppData, Probe> for BrokerIndexLensProbe {
+    fn with<V, F: FnOnce(&Probe) -> V>(&self, data: &AppData, f: F) -> V {
+        f(match data.probes.get(&self.0) {
+            Some(broker) => broker,
+            None => unreachable!(""),
+        })
+    }
+    fn with_mut<V, F: FnOnce(&mut Probe) -> V>(&self, data: &mut AppData, f: F) -> V {
+        f(match data.probes.get_mut(&self.0) {
+            Some(broker) => broker,
+            None => unreachable!(""),
+        })
+    }
+}
+
+pub struct BrokerIndexLensVecTimeline(pub usize);
+
+impl druid::Lens<AppData, Vector<TimelineEvent>> for BrokerIndexLensVecTimeline {
+    fn with<V, F: FnOnce(&Vector<TimelineEvent>) -> V>(&self, data: &AppData, f: F) -> V {
+        f(match data.timelines.get(&self.0) {
+            Some(broker) => broker,
+            None => unreachable!(""),
+        })
+    }
+    fn with_mut<V, F: FnOnce(&mut Vector<TimelineEvent>) -> V>(
+        &self,
+        data: &mut AppData,
+        f: F,
+    ) -> V {
+        f(match data.timelines.get_mut(&self.0) {
+            Some(broker) => broker,
+            None => unreachable!(""),
+        })
+    }
+}
+
 pub struct BrokerIndexLensTabStatus(pub usize);
 
 impl druid::Lens<AppData, TabStatus> for BrokerIndexLensTabStatus {
@@ -205,26 +410,19 @@ impl druid::Lens<AppData, DbIndex> for Index {
     }
 }
 
-pub struct BrokerStoredList;
+/// see `AppData::broker_list`
+pub struct BrokerFolderList;
 
-impl druid::Lens<AppData, Vector<Broker>> for BrokerStoredList {
-    fn with<V, F: FnOnce(&Vector<Broker>) -> V>(&self, data: &AppData, f: F) -> V {
-        let broker_list: Vector<Broker> = data
-            .brokers
-            .iter()
-            .filter(|x| x.stored)
-            .map(|x| x.clone())
-            .collect();
-        f(&broker_list)
+impl druid::Lens<AppData, Vector<BrokerFolder>> for BrokerFolderList {
+    fn with<V, F: FnOnce(&Vector<BrokerFolder>) -> V>(&self, data: &AppData, f: F) -> V {
+        f(&data.broker_folders())
     }
-    fn with_mut<V, F: FnOnce(&mut Vector<Broker>) -> V>(&self, data: &mut AppData, f: F) -> V {
-        let mut broker_list: Vector<Broker> = data
-            .brokers
-            .iter()
-            .filter(|x| x.stored)
-            .map(|x| x.clone())
-            .collect();
-        f(&mut broker_list)
+    fn with_mut<V, F: FnOnce(&mut Vector<BrokerFolder>) -> V>(
+        &self,
+        data: &mut AppData,
+        f: F,
+    ) -> V {
+        f(&mut data.broker_folders())
     }
 }
 
@@ -308,3 +506,67 @@ impl Lens<Broker, u16> for PortLens {
         f(&mut data.port)
     }
 }
+
+const MASK: char = '•';
+
+/// the password shown as `•`, see `unmask`
+pub struct Masked;
+
+impl Lens<AString, AString> for Masked {
+    fn with<V, F: FnOnce(&AString) -> V>(&self, data: &AString, f: F) -> V {
+        f(&mask(data))
+    }
+
+    fn with_mut<V, F: FnOnce(&mut AString) -> V>(&self, data: &mut AString, f: F) -> V {
+        let masked = mask(data);
+        let mut edited = masked.clone();
+        let rs = f(&mut edited);
+        if edited != masked {
+            *data = unmask(data, &edited).into();
+        }
+        rs
+    }
+}
+
+fn mask(text: &str) -> AString {
+    MASK.to_string().repeat(text.chars().count()).into()
+}
+
+/// the text after its mask is edited: the masks around the edit are the chars kept,
+/// and a char deleted in the middle is taken as the last one
+fn unmask(text: &str, edited: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let edited: Vec<char> = edited.chars().collect();
+    let prefix = edited
+        .iter()
+        .take_while(|x| **x == MASK)
+        .count()
+        .min(chars.len());
+    let suffix = edited[prefix..]
+        .iter()
+        .rev()
+        .take_while(|x| **x == MASK)
+        .count()
+        .min(chars.len() - prefix);
+    let mut rs: String = chars[..prefix].iter().collect();
+    rs.extend(&edited[prefix..edited.len() - suffix]);
+    rs.extend(&chars[chars.len() - suffix..]);
+    rs
+}
+
+#[cfg(test)]
+mod test {
+    use crate::data::lens::{mask, unmask};
+
+    #[test]
+    fn test_unmask() {
+        assert_eq!(mask("pass").as_str(), "••••");
+        // typed at the end and in the middle, deleted
+        assert_eq!(unmask("pass", "••••1"), "pass1");
+        assert_eq!(unmask("pass", "••0••"), "pa0ss");
+        assert_eq!(unmask("pass", "•••"), "pas");
+        // all selected and replaced
+        assert_eq!(unmask("pass", "word"), "word");
+        assert_eq!(unmask("", "p"), "p");
+    }
+}
@@ -3,7 +3,14 @@ pub mod db;
 pub mod hierarchy;
 pub mod lens;
 
-use crate::data::common::{Id, PublicInput, SubscribeHis, SubscribeInput, SubscribeMsg};
+use crate::bridge::BridgeRoute;
+use crate::data::common::{
+    Id, PublicInput, RequestInput, Rule, SubscribeFilter, SubscribeHis, SubscribeInput,
+    SubscribeMsg, TimelineEvent,
+};
+use crate::local_broker::LocalBrokerEvent;
+use crate::script::ScriptOutput;
+use crate::util::vault::VaultMeta;
 use common::Broker;
 use rumqttc::v5::mqttbytes::{PubAck, SubAck};
 use std::sync::Arc;
@@ -13,6 +20,10 @@ pub type AString = Arc<String>;
 #[derive(Debug)]
 pub enum AppEvent {
     RemoveSubscribeHis,
+    // toggle the pinned
+    PinSubscribeHis(SubscribeHis),
+    // the alias and the description of the selected history
+    SaveSubscribeHisNote,
     AddBroker,
     EditBroker,
     ConnectBroker,
@@ -22,12 +33,71 @@ pub enum AppEvent {
     SelectTabs(usize),
     Connect(Broker),
     Subscribe(SubscribeInput, usize),
+    AddSubscribeFilter(usize),
+    RemoveSubscribeFilter(usize, Id),
     SubscribeFromHis(SubscribeHis),
-    ToUnSubscribe { broker_id: usize, pk_id: u16 },
+    ToUnSubscribe { broker_id: usize, id: Id },
     UnSubscribeIng(EventUnSubscribe),
     ConnectAckSuccess(usize),
     ConnectAckFail(usize, Arc<String>),
+    // the event loop ended, true if disconnected by the user
+    ConnectionClosed(usize, bool),
+    // an entry of the connection timeline
+    Timeline(usize, TimelineEvent),
+    ClearTimeline(usize),
     Public(PublicInput, usize),
+    SavePublicTemplate(usize),
+    SelectPublicTemplate(usize, Id),
+    RemovePublicTemplate(usize, Id),
+    // fill the public input
+    SelectPublicHis(usize, Id),
+    // toggle the favourite
+    FavouritePublicHis(usize, Id),
+    RemovePublicHis(usize, Id),
+    StartSchedule(PublicInput, usize),
+    // the schedule's time to publish
+    ScheduleTick(usize, Id),
+    StopSchedule(usize, Id),
+    // start probe with the interval
+    StartProbe(usize, u64),
+    ProbeTick(usize),
+    StopProbe(usize),
+    Request(RequestInput, usize),
+    // correlation data of the request
+    RequestTimeout(usize, AString),
+    // subscribe the filter to collect the retained messages, released at the end of the scan
+    StartRetainScan(usize, SubscribeFilter),
+    RetainScanEnd(usize),
+    PreviewRetainClear(usize),
+    CancelRetainClear(usize),
+    // publish zero-length retained payloads to the topics
+    ClearRetain(usize, Vec<String>),
+    // start the embedded broker on the port, listening on all the interfaces if true
+    StartLocalBroker(u16, bool),
+    LocalBroker(LocalBrokerEvent),
+    // set or change the master password of the saved credentials
+    SetMasterPassword(AString),
+    UnlockVault(VaultMeta, AString),
+    // export the selected broker, or all the saved brokers if none is selected
+    ExportProfiles(AString),
+    ImportProfiles(AString),
+    // the routes from the source to every target
+    StartBridge(Vec<BridgeRoute>),
+    // source of the bridge
+    StopBridge(usize),
+    // add the rule input of the broker
+    AddRule(usize),
+    RemoveRule(usize, Id),
+    ToggleRule(usize, Id),
+    // the enabled rules of the broker to evaluate in the logic worker
+    UpdateRules(usize, Vec<Rule>),
+    // compile and run the script, replace the running one
+    LoadScript(usize, AString),
+    StopScript(usize),
+    // the timer set by the script expires
+    ScriptTimer(usize, String),
+    // a run of the script is done on its thread
+    ScriptOutput(usize, ScriptOutput),
     ReceivePublic(usize, SubscribeMsg),
     PubAck(usize, PubAck),
     SubAck(usize, SubAck),
@@ -40,6 +110,16 @@ pub enum AppEvent {
     CloseBrokerTab(usize),
     CloseConnectionTab(usize),
     DeleteBroker,
+    // copy the selected broker
+    DuplicateBroker,
+    AddEnvironment,
+    RemoveEnvironment(Id),
+    // persist the variables edited
+    SaveEnvironments,
+    // drag the broker and drop on the other
+    MoveBroker(usize, usize),
+    // click the header of the folder in the broker list
+    ToggleBrokerFolder(AString),
     // e.g: delete broker; close tab; click button "disconnect"
     Disconnect(usize),
     UpdateStatusBar(String),
@@ -47,6 +127,6 @@ pub enum AppEvent {
 #[derive(Debug, Clone)]
 pub struct EventUnSubscribe {
     pub broke_id: usize,
-    pub subscribe_pk_id: u16,
+    pub subscribe_id: Id,
     pub topic: String,
 }
@@ -4,9 +4,17 @@
 #![allow(unused_imports)]
 #![allow(unused_variables)]
 // pub mod config;
+pub mod api;
+pub mod bridge;
+pub mod cli;
 pub mod data;
+pub mod local_broker;
 pub mod logic;
 pub mod mqtt;
+pub mod profile;
+pub mod rule;
+pub mod script;
+pub mod session;
 pub mod ui;
 pub mod util;
 
@@ -18,6 +26,8 @@ pub enum ForError {
     InvalidPort,
     #[error("Only 0/1/2")]
     InvalidQos,
+    #[error("Only 0/1/2")]
+    InvalidRetainHandling,
     #[error("Not Empty")]
     NotEmpty,
 }
@@ -0,0 +1,147 @@
+//! The in-process broker (rumqttd) to test the devices without any infrastructure.
+//!
+//! It listens MQTT 5 on the loopback, the devices of the LAN can connect only when `lan` is
+//! chosen explicitly, then it listens on all the interfaces. rumqttd binds in its own thread
+//! and only logs the failure to bind, so the address is probed before, e.g. the port is in use.
+//! A local link subscribes `#` to collect the retained store, and the alerts of the router
+//! tell the clients connecting and disconnecting. rumqttd can't be shut down:
+//! once started, the broker runs until the app exits.
+use crate::data::AppEvent;
+use anyhow::{Context, Result};
+use config::{Config, File, FileFormat};
+use crossbeam_channel::Sender;
+use log::{debug, error};
+use rumqttd::{Alert, AlertEvent, Broker, Notification};
+use std::net::TcpListener;
+use std::thread;
+
+pub const DEFAULT_CONFIG: &str = include_str!("../../resources/local_broker.toml");
+/// client id of the local link, hidden from the clients
+const LINK_ID: &str = "for-mqtt-local-link";
+
+#[derive(Debug, Clone)]
+pub enum LocalBrokerEvent {
+    Connected(String),
+    Disconnected(String),
+    /// topic and size of the retained message, the size is 0 when it is cleared
+    Retain(String, usize),
+}
+
+/// fails if the address can't be bound
+pub fn start(port: u16, lan: bool, tx: Sender<AppEvent>) -> Result<()> {
+    // released right away for rumqttd
+    TcpListener::bind((ip(lan), port)).with_context(|| format!("can't listen on {}", port))?;
+    let mut broker = Broker::new(config(port, lan)?);
+    let (mut link_tx, mut link_rx) = broker.link(LINK_ID)?;
+    let mut alerts = broker.alerts()?;
+    thread::Builder::new()
+        .name("local-broker".to_string())
+        .spawn(move || {
+            if let Err(e) = broker.start() {
+                error!("{:?}", e);
+            }
+        })?;
+    link_tx.subscribe("#")?;
+    let link_events = tx.clone();
+    thread::Builder::new()
+        .name("local-broker-link".to_string())
+        .spawn(move || {
+            // the sender of the link must live as long as the receiver
+            let _link_tx = link_tx;
+            loop {
+                let notification = match link_rx.recv() {
+                    Ok(Some(notification)) => notification,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        error!("{:?}", e);
+                        return;
+                    }
+                };
+                let Notification::Forward(forward) = notification else {
+                    continue;
+                };
+                let publish = forward.publish;
+                if publish.retain {
+                    let topic = String::from_utf8_lossy(&publish.topic).to_string();
+                    send(
+                        &link_events,
+                        LocalBrokerEvent::Retain(topic, publish.payload.len()),
+                    );
+                }
+            }
+        })?;
+    thread::Builder::new()
+        .name("local-broker-alerts".to_string())
+        .spawn(move || loop {
+            let alerts = match alerts.recv() {
+                Ok(alerts) => alerts,
+                Err(e) => {
+                    error!("{:?}", e);
+                    return;
+                }
+            };
+            for alert in alerts {
+                debug!("{:?}", alert);
+                let event = match alert {
+                    Alert::Event(client_id, AlertEvent::Connect) => {
+                        LocalBrokerEvent::Connected(client_id)
+                    }
+                    Alert::Event(client_id, AlertEvent::Disconnect) => {
+                        LocalBrokerEvent::Disconnected(client_id)
+                    }
+                    _ => continue,
+                };
+                send(&tx, event);
+            }
+        })?;
+    Ok(())
+}
+
+fn ip(lan: bool) -> &'static str {
+    if lan {
+        "0.0.0.0"
+    } else {
+        "127.0.0.1"
+    }
+}
+
+fn config(port: u16, lan: bool) -> Result<rumqttd::Config> {
+    Ok(Config::builder()
+        .add_source(File::from_str(DEFAULT_CONFIG, FileFormat::Toml))
+        .set_override("v5.1.listen", format!("{}:{}", ip(lan), port))?
+        .build()?
+        .try_deserialize()?)
+}
+
+fn send(tx: &Sender<AppEvent>, event: LocalBrokerEvent) {
+    if let LocalBrokerEvent::Connected(id) | LocalBrokerEvent::Disconnected(id) = &event {
+        if id == LINK_ID {
+            return;
+        }
+    }
+    if let Err(e) = tx.send(AppEvent::LocalBroker(event)) {
+        error!("{:?}", e);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::local_broker::{config, start};
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_config() {
+        let v5 = config(11883, false).unwrap().v5.unwrap();
+        assert_eq!(v5["1"].listen.to_string(), "127.0.0.1:11883");
+        let v5 = config(11883, true).unwrap().v5.unwrap();
+        assert_eq!(v5["1"].listen.to_string(), "0.0.0.0:11883");
+    }
+
+    #[test]
+    fn test_port_in_use() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        assert!(start(port, false, tx).is_err());
+    }
+}
@@ -1,35 +1,114 @@
+use crate::bridge::{BridgeRoute, Routed};
 use crate::data::hierarchy::AppData;
-use crate::data::{AppEvent, EventUnSubscribe};
-use crate::mqtt::{init_connect, mqtt_public, mqtt_subscribe, to_unsubscribe};
+use crate::data::{AString, AppEvent, EventUnSubscribe};
 // use crate::ui::tabs::init_brokers_tabs;
-use crate::data::common::{Broker, Id, PublicInput, SubscribeHis, SubscribeInput, SubscribeMsg};
+use crate::data::common::{
+    Broker, Id, ProbeSample, PublicInput, PublicSchedule, QoS, RequestInput, RequestRecord,
+    RetainMsg, Rule, SubscribeFilter, SubscribeHis, SubscribeInput, SubscribeMsg, TimelineEvent,
+};
+use crate::local_broker::LocalBrokerEvent;
+use crate::mqtt::data::{
+    MqttPublicInput, MqttRequestInput, MqttSubscribeInput, MqttSubscribeManyInput,
+};
+use crate::profile::Conflict;
+use crate::rule::RuleMatcher;
+use crate::script::{
+    ScriptAction, ScriptInput, ScriptOutput, ScriptWorker, MAX_TIMERS, SCRIPT_PROPERTY,
+};
+use crate::session::types::ConnectOptions;
+use crate::session::{Session, SessionEvent, SessionObserver};
 use crate::ui::ids::{SELECTOR_TABS_SELECTED, TABS_ID};
 use crate::util::hint::{
-    DELETE_BROKER_SUCCESS, DELETE_SUBSCRIBE_SUCCESS, DISCONNECT_SUCCESS, PUBLISH_SUCCESS,
-    SAVE_BROKER_SUCCESS, SUBSCRIBE_SUCCESS, UNSUBSCRIBE_SUCCESS,
+    ADD_RULE_SUCCESS, CONNECTION_LOST, DELETE_BROKER_SUCCESS, DELETE_RULE_SUCCESS,
+    DELETE_SUBSCRIBE_SUCCESS, DELETE_TEMPLATE_SUCCESS, DISCONNECT_SUCCESS,
+    DUPLICATE_BROKER_SUCCESS, EXPORT_PROFILES_SUCCESS, IMPORT_PROFILES_SUCCESS, PROBE_NO_INTERVAL,
+    PUBLISH_SUCCESS, RULE_FIRED, SAVE_BROKER_SUCCESS, SAVE_ENVIRONMENTS_SUCCESS,
+    SAVE_SUBSCRIBE_HIS_SUCCESS, SAVE_TEMPLATE_SUCCESS, SCHEDULE_NO_INTERVAL, SCRIPT_ERROR,
+    SCRIPT_LOADED, SUBSCRIBE_SUCCESS, UNSUBSCRIBE_SUCCESS, VAULT_WRONG_PASSWORD,
 };
+use crate::util::vault::VaultMeta;
+use crate::util::{general_id, now_millis};
 use anyhow::Result;
 use crossbeam_channel::{Receiver, Sender};
 use custom_utils::rx;
 use log::{debug, error, info, warn};
 use rumqttc::v5::{
-    mqttbytes::{PubAck, SubAck},
+    mqttbytes::{matches, PubAck, SubAck},
     AsyncClient,
 };
 use std::collections::HashMap;
 use std::hash::Hash;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+struct ScheduleTask {
+    broker_id: usize,
+    input: PublicInput,
+    handle: JoinHandle<()>,
+}
+
+/// seconds to collect the retained messages
+const RETAIN_SCAN_SECS: u64 = 3;
+
+struct RetainScanTask {
+    filter: String,
+}
+
+struct ScriptTask {
+    worker: ScriptWorker,
+    code: AString,
+    timers: Vec<JoinHandle<()>>,
+    /// the value of `SCRIPT_PROPERTY` in the publishes of the script
+    marker: String,
+}
+
+struct ProbeTask {
+    topic: String,
+    seq: u64,
+    /// sending instant of the probes waiting for the echo
+    sent: HashMap<u64, Instant>,
+    handle: JoinHandle<()>,
+}
+
+/// probes lost, e.g. qos 0 dropped by the broker, are forgotten after these
+const PROBE_PENDING_MAX: u64 = 100;
+
+/// the druid frontend observes the session by the events of the logic worker
+impl SessionObserver for Sender<AppEvent> {
+    fn notify(&self, event: SessionEvent) {
+        let event = match event {
+            // the publishes of the frontend are done at PUBACK or sent
+            SessionEvent::PubComp(..) => return,
+            SessionEvent::ConnAckSuccess(id) => AppEvent::ConnectAckSuccess(id),
+            SessionEvent::ConnAckFail(id, msg) => AppEvent::ConnectAckFail(id, msg.into()),
+            SessionEvent::PubAck(id, ack) => AppEvent::PubAck(id, ack),
+            SessionEvent::SubAck(id, ack) => AppEvent::SubAck(id, ack),
+            SessionEvent::UnSubAck(id, pkid) => AppEvent::UnSubAck(id, pkid),
+            SessionEvent::Publish(id, msg) => AppEvent::ReceivePublic(id, msg),
+            SessionEvent::Timeline(id, event) => AppEvent::Timeline(id, event),
+            SessionEvent::Closed(id, by_user) => AppEvent::ConnectionClosed(id, by_user),
+        };
+        if let Err(_) = self.send(event) {
+            error!("fail to send event!");
+        }
+    }
+}
 
 #[tokio::main(flavor = "multi_thread", worker_threads = 10)]
 pub async fn deal_event(
     event_sink: druid::ExtEventSink,
     rx: Receiver<AppEvent>,
     tx: Sender<AppEvent>,
+    mut session: Session,
 ) -> Result<()> {
-    let mut mqtt_clients: HashMap<usize, AsyncClient> = HashMap::new();
     let mut clicks: HashMap<usize, usize> = HashMap::new();
     let mut click_his: Option<SubscribeHis> = None;
+    let mut schedules: HashMap<Id, ScheduleTask> = HashMap::new();
+    let mut probes: HashMap<usize, ProbeTask> = HashMap::new();
+    let mut retain_scans: HashMap<usize, RetainScanTask> = HashMap::new();
+    let mut scripts: HashMap<usize, ScriptTask> = HashMap::new();
+    let mut rules: HashMap<usize, Vec<RuleMatcher>> = HashMap::new();
     loop {
         // let event = ;
         // debug!("{:?}", event);
@@ -39,49 +118,220 @@ pub async fn deal_event(
             AppEvent::ConnectBroker => connect_broker(&event_sink),
             AppEvent::SaveBroker(index) => save_broker(&event_sink, index),
             AppEvent::RemoveSubscribeHis => delete_subscribe_his(&event_sink),
-            AppEvent::ToUnSubscribe { broker_id, pk_id } => {
-                to_un_subscribe(&event_sink, broker_id, pk_id)
+            AppEvent::PinSubscribeHis(his) => pin_subscribe_his(&event_sink, his),
+            AppEvent::SaveSubscribeHisNote => save_subscribe_his_note(&event_sink),
+            AppEvent::ToUnSubscribe { broker_id, id } => {
+                to_un_subscribe(&event_sink, broker_id, id)
             }
             AppEvent::UnSubscribeIng(event) => {
-                un_subscribe_ing(&event_sink, event, &mqtt_clients).await
+                un_subscribe_ing(&event_sink, event, &mut session).await
             }
             AppEvent::UnSubAck(broke_id, unsubscribe_pk_id) => {
-                un_sub_ack(&event_sink, broke_id, unsubscribe_pk_id)
-            }
-            AppEvent::Connect(broker) => {
-                connect(&event_sink, &mut mqtt_clients, tx.clone(), broker).await
+                if !session.take_internal_ack(broke_id, unsubscribe_pk_id) {
+                    un_sub_ack(&event_sink, broke_id, unsubscribe_pk_id)
+                }
             }
+            AppEvent::Connect(broker) => connect(&event_sink, &mut session, broker).await,
             AppEvent::Subscribe(input, index) => {
-                subscribe(&event_sink, &mqtt_clients, index, input).await
+                subscribe(&event_sink, &mut session, index, input).await
+            }
+            AppEvent::AddSubscribeFilter(index) => add_subscribe_filter(&event_sink, index),
+            AppEvent::RemoveSubscribeFilter(index, id) => {
+                remove_subscribe_filter(&event_sink, index, id)
             }
             AppEvent::SubscribeFromHis(his) => {
-                subscribe_from_his(&event_sink, &mqtt_clients, his).await
+                subscribe_from_his(&event_sink, &mut session, his).await
             }
             AppEvent::Public(input, index) => {
-                publish(&event_sink, &mqtt_clients, index, input).await
+                publish(&event_sink, &mut session, index, input).await
+            }
+            AppEvent::SavePublicTemplate(index) => save_public_template(&event_sink, index),
+            AppEvent::SelectPublicTemplate(index, id) => {
+                select_public_template(&event_sink, index, id)
+            }
+            AppEvent::RemovePublicTemplate(index, id) => {
+                remove_public_template(&event_sink, index, id)
+            }
+            AppEvent::SelectPublicHis(index, id) => select_public_his(&event_sink, index, id),
+            AppEvent::FavouritePublicHis(index, id) => favourite_public_his(&event_sink, index, id),
+            AppEvent::RemovePublicHis(index, id) => remove_public_his(&event_sink, index, id),
+            AppEvent::StartSchedule(input, index) => {
+                start_schedule(&event_sink, tx.clone(), &mut schedules, index, input)
+            }
+            AppEvent::ScheduleTick(index, id) => {
+                schedule_tick(&event_sink, &mut session, &schedules, index, id).await
+            }
+            AppEvent::StopSchedule(index, id) => {
+                stop_schedule(&event_sink, &mut schedules, index, id)
+            }
+            AppEvent::StartProbe(index, interval) => {
+                start_probe(
+                    &event_sink,
+                    tx.clone(),
+                    &mut session,
+                    &mut probes,
+                    index,
+                    interval,
+                )
+                .await
+            }
+            AppEvent::ProbeTick(index) => {
+                probe_tick(&event_sink, &mut session, &mut probes, index).await
+            }
+            AppEvent::StopProbe(index) => {
+                stop_probe(&event_sink, &mut session, &mut probes, index).await
+            }
+            AppEvent::Request(input, index) => {
+                request(&event_sink, tx.clone(), &mut session, index, input).await
+            }
+            AppEvent::RequestTimeout(index, correlation) => {
+                request_timeout(&event_sink, index, correlation)
+            }
+            AppEvent::StartRetainScan(index, filter) => {
+                start_retain_scan(
+                    &event_sink,
+                    tx.clone(),
+                    &mut session,
+                    &mut retain_scans,
+                    index,
+                    filter,
+                )
+                .await
+            }
+            AppEvent::RetainScanEnd(index) => {
+                retain_scan_end(&event_sink, &mut session, &mut retain_scans, index).await
+            }
+            AppEvent::PreviewRetainClear(index) => preview_retain_clear(&event_sink, index),
+            AppEvent::CancelRetainClear(index) => cancel_retain_clear(&event_sink, index),
+            AppEvent::ClearRetain(index, topics) => {
+                clear_retain(&event_sink, &mut session, index, topics).await
+            }
+            AppEvent::StartLocalBroker(port, lan) => {
+                start_local_broker(&event_sink, &tx, port, lan)
+            }
+            AppEvent::LocalBroker(event) => local_broker_event(&event_sink, event),
+            AppEvent::SetMasterPassword(master) => set_master_password(&event_sink, master),
+            AppEvent::UnlockVault(meta, master) => unlock_vault(&event_sink, meta, master),
+            AppEvent::ExportProfiles(path) => export_profiles(&event_sink, path),
+            AppEvent::ImportProfiles(path) => import_profiles(&event_sink, path),
+            AppEvent::StartBridge(routes) => start_bridge(&event_sink, &mut session, routes).await,
+            AppEvent::StopBridge(index) => stop_bridge(&event_sink, &mut session, index).await,
+            AppEvent::AddRule(index) => add_rule(&event_sink, index),
+            AppEvent::RemoveRule(index, id) => remove_rule(&event_sink, index, id),
+            AppEvent::ToggleRule(index, id) => toggle_rule(&event_sink, index, id),
+            AppEvent::UpdateRules(index, list) => update_rules(&mut rules, index, list),
+            AppEvent::LoadScript(index, code) => load_script(tx.clone(), &mut scripts, index, code),
+            AppEvent::StopScript(index) => stop_script(&event_sink, &mut scripts, index),
+            AppEvent::ScriptTimer(index, name) => script_timer(&event_sink, &scripts, index, name),
+            AppEvent::ScriptOutput(index, output) => {
+                script_output(
+                    &event_sink,
+                    tx.clone(),
+                    &mut session,
+                    &mut scripts,
+                    index,
+                    output,
+                )
+                .await
+            }
+            AppEvent::ReceivePublic(index, msg) => {
+                receive_public(
+                    &event_sink,
+                    &mut session,
+                    &mut probes,
+                    &retain_scans,
+                    &mut scripts,
+                    &mut rules,
+                    index,
+                    msg,
+                )
+                .await
             }
-            AppEvent::ReceivePublic(index, msg) => receive_public(&event_sink, index, msg),
             AppEvent::PubAck(id, ack) => pub_ack(&event_sink, id, ack),
-            AppEvent::SubAck(id, ack) => sub_ack(&event_sink, id, ack),
+            AppEvent::SubAck(id, ack) => {
+                if !session.take_internal_ack(id, ack.pkid) {
+                    sub_ack(&event_sink, id, ack)
+                }
+            }
             AppEvent::SelectTabs(id) => select_tabs(&event_sink, id),
             AppEvent::ClickBroker(id) => click_broker(&event_sink, tx.clone(), &mut clicks, id),
             AppEvent::DbClickCheck(id) => db_click_check(&mut clicks, id),
             AppEvent::ClickSubscribeHis(his) => {
-                click_subscribe_his(&event_sink, tx.clone(), &mqtt_clients, &mut click_his, his)
+                click_subscribe_his(&event_sink, tx.clone(), &mut session, &mut click_his, his)
                     .await
             }
             AppEvent::DbClickCheckSubscribeHis(his) => {
                 db_click_check_subscribe_his(&mut click_his, his).await
             }
-            AppEvent::ReConnect(id) => re_connect(&event_sink, &mut mqtt_clients, id).await,
-            AppEvent::Disconnect(id) => disconnect(&event_sink, &mut mqtt_clients, id).await,
+            AppEvent::ReConnect(id) => {
+                stop_broker(
+                    &event_sink,
+                    &mut session,
+                    &mut schedules,
+                    &mut probes,
+                    &mut retain_scans,
+                    &mut scripts,
+                    id,
+                )
+                .await;
+                re_connect(&event_sink, &mut session, id).await
+            }
+            AppEvent::Disconnect(id) => {
+                stop_broker(
+                    &event_sink,
+                    &mut session,
+                    &mut schedules,
+                    &mut probes,
+                    &mut retain_scans,
+                    &mut scripts,
+                    id,
+                )
+                .await;
+                disconnect(&event_sink, &mut session, id).await
+            }
             AppEvent::CloseBrokerTab(id) => close_broker_tab(&event_sink, id),
             AppEvent::CloseConnectionTab(id) => {
-                close_connection_tab(&event_sink, &mut mqtt_clients, id).await
+                stop_broker(
+                    &event_sink,
+                    &mut session,
+                    &mut schedules,
+                    &mut probes,
+                    &mut retain_scans,
+                    &mut scripts,
+                    id,
+                )
+                .await;
+                close_connection_tab(&event_sink, &mut session, id).await
             }
             AppEvent::DeleteBroker => delete_broker(&event_sink),
+            AppEvent::MoveBroker(from, to) => move_broker(&event_sink, from, to),
+            AppEvent::ToggleBrokerFolder(name) => toggle_broker_folder(&event_sink, name),
+            AppEvent::DuplicateBroker => duplicate_broker(&event_sink),
+            AppEvent::AddEnvironment => add_environment(&event_sink),
+            AppEvent::RemoveEnvironment(id) => remove_environment(&event_sink, id),
+            AppEvent::SaveEnvironments => save_environments(&event_sink),
             AppEvent::ConnectAckSuccess(id) => connect_ack_success(&event_sink, id), // _ => {}
-            AppEvent::ConnectAckFail(_id, _msg) => todo!(),
+            AppEvent::ConnectAckFail(id, msg) => {
+                connect_ack_fail(&event_sink, &mut session, id, msg)
+            }
+            AppEvent::ConnectionClosed(id, by_user) => {
+                // disconnected by the user: the state is cleared already
+                if !by_user {
+                    stop_broker(
+                        &event_sink,
+                        &mut session,
+                        &mut schedules,
+                        &mut probes,
+                        &mut retain_scans,
+                        &mut scripts,
+                        id,
+                    )
+                    .await;
+                    connection_lost(&event_sink, &mut session, id);
+                }
+            }
+            AppEvent::Timeline(id, event) => timeline(&event_sink, id, event),
+            AppEvent::ClearTimeline(id) => clear_timeline(&event_sink, id),
             AppEvent::UpdateStatusBar(msg) => {
                 update_status_bar(&event_sink, msg);
             }
@@ -114,6 +364,7 @@ fn save_broker(event_sink: &druid::ExtEventSink, index: usize) {
     event_sink.add_idle_callback(move |data: &mut AppData| {
         if let Err(e) = data.save_broker(index) {
             error!("{:?}", e);
+            warn!("{}", e.to_string());
         } else {
             info!("{}", SAVE_BROKER_SUCCESS);
         }
@@ -130,9 +381,27 @@ fn delete_subscribe_his(event_sink: &druid::ExtEventSink) {
     });
 }
 
-fn to_un_subscribe(event_sink: &druid::ExtEventSink, broker_id: usize, pk_id: u16) {
+fn pin_subscribe_his(event_sink: &druid::ExtEventSink, his: SubscribeHis) {
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        if let Err(e) = data.pin_subscribe_his(his) {
+            error!("{:?}", e);
+        }
+    });
+}
+
+fn save_subscribe_his_note(event_sink: &druid::ExtEventSink) {
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        if let Err(e) = data.save_subscribe_his_note() {
+            warn!("{}", e.to_string());
+        } else {
+            info!("{}", SAVE_SUBSCRIBE_HIS_SUCCESS);
+        }
+    });
+}
+
+fn to_un_subscribe(event_sink: &druid::ExtEventSink, broker_id: usize, id: Id) {
     event_sink.add_idle_callback(move |data: &mut AppData| {
-        if let Err(e) = data.to_unscribe(broker_id, pk_id) {
+        if let Err(e) = data.to_unscribe(broker_id, id) {
             error!("{:?}", e);
         }
     });
@@ -141,20 +410,29 @@ fn to_un_subscribe(event_sink: &druid::ExtEventSink, broker_id: usize, pk_id: u1
 async fn un_subscribe_ing(
     event_sink: &druid::ExtEventSink,
     event: EventUnSubscribe,
-    mqtt_clients: &HashMap<usize, AsyncClient>,
+    session: &mut Session,
 ) {
     let EventUnSubscribe {
         broke_id,
-        subscribe_pk_id,
+        subscribe_id,
         topic,
     } = event;
-    match to_unsubscribe(broke_id, topic, &mqtt_clients).await {
-        Ok(pk_id) => {
+    match session.unsubscribe(broke_id, topic).await {
+        Ok(Some(pk_id)) => {
+            event_sink.add_idle_callback(move |data: &mut AppData| {
+                if let Err(e) = data.unscribeing(broke_id, subscribe_id, pk_id) {
+                    error!("{:?}", e);
+                }
+            });
+        }
+        // still subscribed by a bridge: done without UNSUBSCRIBE, 0 is never a pkid
+        Ok(None) => {
             event_sink.add_idle_callback(move |data: &mut AppData| {
-                if let Err(e) = data.unscribeing(broke_id, subscribe_pk_id, pk_id) {
+                if let Err(e) = data.unscribeing(broke_id, subscribe_id, 0) {
                     error!("{:?}", e);
                 }
             });
+            un_sub_ack(event_sink, broke_id, 0);
         }
         Err(e) => {
             error!("{:?}", e);
@@ -172,16 +450,46 @@ fn un_sub_ack(event_sink: &druid::ExtEventSink, broke_id: usize, unsubscribe_pk_
     });
 }
 
-async fn connect(
+fn connect_ack_fail(
     event_sink: &druid::ExtEventSink,
-    mqtt_clients: &mut HashMap<usize, AsyncClient>,
-    tx: Sender<AppEvent>,
-    broker: Broker,
+    session: &mut Session,
+    id: usize,
+    msg: AString,
 ) {
-    match init_connect(broker.clone(), tx.clone()).await {
-        Ok(client) => {
+    warn!("connect fail: {}", msg);
+    // or the bridges and the api take the refused client as connected
+    session.remove_client(id);
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        data.close_connection(id);
+    });
+}
+fn connection_lost(event_sink: &druid::ExtEventSink, session: &mut Session, id: usize) {
+    session.remove_client(id);
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        if data.connection_lost(id) {
+            warn!("{}", CONNECTION_LOST);
+        }
+    });
+}
+fn timeline(event_sink: &druid::ExtEventSink, id: usize, event: TimelineEvent) {
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        data.push_timeline(id, event);
+    });
+}
+fn clear_timeline(event_sink: &druid::ExtEventSink, id: usize) {
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        data.clear_timeline(id);
+    });
+}
+async fn connect(event_sink: &druid::ExtEventSink, session: &mut Session, broker: Broker) {
+    if broker.use_credentials && !broker.save_password && broker.password.is_empty() {
+        let id = broker.id;
+        event_sink.add_idle_callback(move |data: &mut AppData| data.ask_password(id));
+        return;
+    }
+    match session.connect(&ConnectOptions::from(&broker)).await {
+        Ok(()) => {
             let id = broker.id;
-            mqtt_clients.insert(id, client.clone());
             event_sink.add_idle_callback(move |data: &mut AppData| {
                 if let Err(e) = data.init_connection(id) {
                     error!("{:?}", e);
@@ -196,11 +504,16 @@ async fn connect(
 
 async fn subscribe(
     event_sink: &druid::ExtEventSink,
-    mqtt_clients: &HashMap<usize, AsyncClient>,
+    session: &mut Session,
     index: usize,
     input: SubscribeInput,
 ) {
-    match mqtt_subscribe(index, input.clone().into(), &mqtt_clients).await {
+    let input = input.without_empty();
+    if input.filters.is_empty() {
+        warn!("there is no topic to subscribe");
+        return;
+    }
+    match session.subscribe_many(index, input.clone().into()).await {
         Ok(id) => {
             event_sink.add_idle_callback(move |data: &mut AppData| {
                 if let Err(e) = data.subscribe_by_input(index, input, id) {
@@ -214,12 +527,27 @@ async fn subscribe(
     }
 }
 
+fn add_subscribe_filter(event_sink: &druid::ExtEventSink, index: usize) {
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        data.add_subscribe_filter(index);
+    });
+}
+
+fn remove_subscribe_filter(event_sink: &druid::ExtEventSink, index: usize, id: Id) {
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        data.remove_subscribe_filter(index, id);
+    });
+}
+
 async fn subscribe_from_his(
     event_sink: &druid::ExtEventSink,
-    mqtt_clients: &HashMap<usize, AsyncClient>,
+    session: &mut Session,
     input: SubscribeHis,
 ) {
-    match mqtt_subscribe(input.broker_id, input.clone().into(), &mqtt_clients).await {
+    match session
+        .subscribe_many(input.broker_id, input.to_input().into())
+        .await
+    {
         Ok(id) => {
             event_sink.add_idle_callback(move |data: &mut AppData| {
                 if let Err(e) = data.subscribe(input.broker_id, input, id) {
@@ -235,15 +563,28 @@ async fn subscribe_from_his(
 
 async fn publish(
     event_sink: &druid::ExtEventSink,
-    mqtt_clients: &HashMap<usize, AsyncClient>,
+    session: &mut Session,
     index: usize,
     input: PublicInput,
 ) {
     debug!("{:?}", input);
-    match mqtt_public(index, input.clone().into(), &mqtt_clients).await {
-        Ok(id) => {
+    // the history keeps the placeholders
+    let origin = input.clone();
+    match session
+        .publish_template(index, input.clone().into(), input.expand)
+        .await
+    {
+        Ok((expanded, id)) => {
+            let input = PublicInput {
+                topic: expanded.topic.into(),
+                msg: expanded.msg.into(),
+                ..input
+            };
             event_sink.add_idle_callback(move |data: &mut AppData| {
                 data.public(index, input, id);
+                if let Err(e) = data.add_public_his(index, &origin) {
+                    error!("{:?}", e);
+                }
             });
         }
         Err(e) => {
@@ -252,162 +593,962 @@ async fn publish(
     }
 }
 
-fn receive_public(event_sink: &druid::ExtEventSink, index: usize, msg: SubscribeMsg) {
+fn save_public_template(event_sink: &druid::ExtEventSink, index: usize) {
     event_sink.add_idle_callback(move |data: &mut AppData| {
-        data.receive_msg(index, msg);
+        if let Err(e) = data.save_public_template(index) {
+            warn!("{}", e.to_string());
+        } else {
+            info!("{}", SAVE_TEMPLATE_SUCCESS);
+        }
     });
 }
 
-fn pub_ack(event_sink: &druid::ExtEventSink, id: usize, ack: PubAck) {
+fn select_public_template(event_sink: &druid::ExtEventSink, index: usize, id: Id) {
     event_sink.add_idle_callback(move |data: &mut AppData| {
-        data.puback(id, ack);
-        info!("{}", PUBLISH_SUCCESS);
+        if let Err(e) = data.select_public_template(index, id) {
+            error!("{:?}", e);
+        }
     });
 }
 
-fn sub_ack(event_sink: &druid::ExtEventSink, id: usize, ack: SubAck) {
+fn select_public_his(event_sink: &druid::ExtEventSink, index: usize, id: Id) {
     event_sink.add_idle_callback(move |data: &mut AppData| {
-        data.suback(id, ack);
-        info!("{}", SUBSCRIBE_SUCCESS);
+        if let Err(e) = data.select_public_his(index, id) {
+            error!("{:?}", e);
+        }
     });
 }
-fn select_tabs(event_sink: &druid::ExtEventSink, id: usize) {
-    if let Err(e) = event_sink.submit_command(SELECTOR_TABS_SELECTED, id, TABS_ID) {
-        error!("{:?}", e);
-    }
+
+fn favourite_public_his(event_sink: &druid::ExtEventSink, index: usize, id: Id) {
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        if let Err(e) = data.favourite_public_his(index, id) {
+            error!("{:?}", e);
+        }
+    });
 }
-fn click_broker(
+
+fn remove_public_his(event_sink: &druid::ExtEventSink, index: usize, id: Id) {
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        if let Err(e) = data.remove_public_his(index, id) {
+            error!("{:?}", e);
+        }
+    });
+}
+
+fn remove_public_template(event_sink: &druid::ExtEventSink, index: usize, id: Id) {
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        if let Err(e) = data.remove_public_template(index, id) {
+            error!("{:?}", e);
+        } else {
+            info!("{}", DELETE_TEMPLATE_SUCCESS);
+        }
+    });
+}
+
+fn start_schedule(
     event_sink: &druid::ExtEventSink,
     tx: Sender<AppEvent>,
-    clicks: &mut HashMap<usize, usize>,
-    id: usize,
+    schedules: &mut HashMap<Id, ScheduleTask>,
+    index: usize,
+    input: PublicInput,
 ) {
-    if let Some(_previous) = clicks.remove(&id) {
-        event_sink.add_idle_callback(move |data: &mut AppData| {
-            data.db_click_broker(id);
-        });
-    } else {
-        clicks.insert(id, id);
-        let async_tx = tx.clone();
-        event_sink.add_idle_callback(move |data: &mut AppData| {
-            if let Err(e) = data.click_broker(id) {
+    if input.interval == 0 {
+        warn!("{}", SCHEDULE_NO_INTERVAL);
+        return;
+    }
+    let id = Id::default();
+    let (interval, times) = (input.interval, input.times);
+    let handle = tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_millis(interval));
+        let mut sent = 0u64;
+        loop {
+            ticker.tick().await;
+            if let Err(e) = tx.send(AppEvent::ScheduleTick(index, id)) {
                 error!("{:?}", e);
+                return;
             }
-        });
-        tokio::spawn(async move {
-            tokio::time::sleep(Duration::from_millis(280)).await;
-            if let Err(e) = async_tx.send(AppEvent::DbClickCheck(id)) {
-                error!("{:?}", e);
+            sent += 1;
+            if times > 0 && sent >= times {
+                break;
             }
-        });
+        }
+        if let Err(e) = tx.send(AppEvent::StopSchedule(index, id)) {
+            error!("{:?}", e);
+        }
+    });
+    let schedule = PublicSchedule::from(id, &input);
+    schedules.insert(
+        id,
+        ScheduleTask {
+            broker_id: index,
+            input,
+            handle,
+        },
+    );
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        data.add_schedule(index, schedule);
+    });
+}
+
+async fn schedule_tick(
+    event_sink: &druid::ExtEventSink,
+    session: &mut Session,
+    schedules: &HashMap<Id, ScheduleTask>,
+    index: usize,
+    id: Id,
+) {
+    let Some(task) = schedules.get(&id) else {
+        debug!("schedule is stopped");
+        return;
+    };
+    publish(event_sink, session, index, task.input.clone()).await;
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        data.schedule_sent(index, id);
+    });
+}
+
+fn stop_schedule(
+    event_sink: &druid::ExtEventSink,
+    schedules: &mut HashMap<Id, ScheduleTask>,
+    index: usize,
+    id: Id,
+) {
+    if let Some(task) = schedules.remove(&id) {
+        task.handle.abort();
     }
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        data.remove_schedule(index, id);
+    });
 }
 
-fn db_click_check(clicks: &mut HashMap<usize, usize>, id: usize) {
-    if let Some(_previous) = clicks.remove(&id) {}
+fn stop_broker_schedules(schedules: &mut HashMap<Id, ScheduleTask>, broker_id: usize) {
+    schedules.retain(|_, task| {
+        if task.broker_id == broker_id {
+            task.handle.abort();
+            false
+        } else {
+            true
+        }
+    });
 }
 
-async fn click_subscribe_his(
+async fn start_probe(
     event_sink: &druid::ExtEventSink,
     tx: Sender<AppEvent>,
-    mqtt_clients: &HashMap<usize, AsyncClient>,
-    click_his: &mut Option<SubscribeHis>,
-    his: SubscribeHis,
+    session: &mut Session,
+    probes: &mut HashMap<usize, ProbeTask>,
+    index: usize,
+    interval: u64,
 ) {
-    let index = his.broker_id;
-    if let Some(_previous) = click_his.take() {
-        if _previous == his {
-            // double
-            if let Some(client) = mqtt_clients.get(&index) {
-                let Ok(pkid) = client.subscribe_and_tracing(his.topic.as_str(), his.qos.into()).await else {
-                    error!("!!!!!!");
-                    return;
-                };
-                event_sink.add_idle_callback(move |data: &mut AppData| {
-                    if let Err(e) = data.subscribe(index, _previous, pkid) {
-                        error!("{:?}", e);
-                    }
-                });
+    if probes.contains_key(&index) {
+        debug!("probe is running");
+        return;
+    }
+    if interval == 0 {
+        warn!("{}", PROBE_NO_INTERVAL);
+        return;
+    }
+    let topic = format!("for-mqtt/probe/{}", general_id());
+    let input = MqttSubscribeManyInput {
+        filters: vec![SubscribeFilter {
+            topic: topic.clone().into(),
+            qos: QoS::AtMostOnce,
+            ..SubscribeFilter::default()
+        }
+        .into()],
+    };
+    if let Err(e) = session.subscribe_internal(index, input).await {
+        error!("{:?}", e);
+        return;
+    }
+    let handle = tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_millis(interval));
+        loop {
+            ticker.tick().await;
+            if let Err(e) = tx.send(AppEvent::ProbeTick(index)) {
+                error!("{:?}", e);
+                return;
             }
-            return;
         }
+    });
+    probes.insert(
+        index,
+        ProbeTask {
+            topic,
+            seq: 0,
+            sent: HashMap::new(),
+            handle,
+        },
+    );
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        data.probe_started(index);
+    });
+}
+
+async fn probe_tick(
+    event_sink: &druid::ExtEventSink,
+    session: &mut Session,
+    probes: &mut HashMap<usize, ProbeTask>,
+    index: usize,
+) {
+    let Some(task) = probes.get_mut(&index) else {
+        debug!("probe is stopped");
+        return;
+    };
+    task.seq += 1;
+    let seq = task.seq;
+    task.sent.retain(|x, _| x + PROBE_PENDING_MAX > seq);
+    task.sent.insert(seq, Instant::now());
+    let input = MqttPublicInput {
+        topic: task.topic.clone(),
+        msg: ProbeSample::payload(seq),
+        qos: QoS::AtMostOnce.into(),
+        retain: false,
+        user_properties: Vec::new(),
+    };
+    if let Err(e) = session.publish(index, input).await {
+        error!("{:?}", e);
+        return;
     }
-    *click_his = Some(his.clone());
-    let data_his = his.clone();
     event_sink.add_idle_callback(move |data: &mut AppData| {
-        if let Err(e) = data.click_subscribe_his(data_his) {
-            error!("{:?}", e);
-        }
+        data.probe_sent(index);
     });
-    let async_tx = tx.clone();
-    tokio::spawn(async move {
-        tokio::time::sleep(Duration::from_millis(280)).await;
-        if let Err(e) = async_tx.send(AppEvent::DbClickCheckSubscribeHis(his)) {
+}
+
+async fn stop_probe(
+    event_sink: &druid::ExtEventSink,
+    session: &mut Session,
+    probes: &mut HashMap<usize, ProbeTask>,
+    index: usize,
+) {
+    if let Some(task) = probes.remove(&index) {
+        task.handle.abort();
+        if let Err(e) = session.unsubscribe_internal(index, task.topic).await {
             error!("{:?}", e);
         }
+    }
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        data.probe_stopped(index);
     });
 }
 
-async fn db_click_check_subscribe_his(click_his: &mut Option<SubscribeHis>, his: SubscribeHis) {
-    if click_his.as_ref().map_or(false, |x| *x == his) {
-        click_his.take();
+fn stop_broker_probe(probes: &mut HashMap<usize, ProbeTask>, broker_id: usize) {
+    if let Some(task) = probes.remove(&broker_id) {
+        task.handle.abort();
     }
 }
 
-async fn re_connect(
+async fn receive_public(
     event_sink: &druid::ExtEventSink,
-    mqtt_clients: &mut HashMap<usize, AsyncClient>,
-    id: usize,
+    session: &mut Session,
+    probes: &mut HashMap<usize, ProbeTask>,
+    retain_scans: &HashMap<usize, RetainScanTask>,
+    scripts: &mut HashMap<usize, ScriptTask>,
+    rules: &mut HashMap<usize, Vec<RuleMatcher>>,
+    index: usize,
+    mut msg: SubscribeMsg,
 ) {
-    if let Some(client) = mqtt_clients.remove(&id) {
-        if let Err(e) = client.disconnect().await {
-            error!("{:?}", e);
+    if let Some(task) = retain_scans.get(&index) {
+        if msg.retain && matches(msg.topic.as_str(), task.filter.as_str()) {
+            let retain = RetainMsg::from(&msg, now_millis());
+            event_sink.add_idle_callback(move |data: &mut AppData| {
+                data.retain_received(index, retain);
+            });
+            // only the scan subscribes it, else it goes on to the user, the scripts and the rules
+            if session.subscribers(index, msg.topic.as_str()) <= 1 {
+                return;
+            }
+        }
+    }
+    if let Some(task) = probes.get_mut(&index) {
+        if msg.topic.as_str() == task.topic.as_str() {
+            let Some(sample) = ProbeSample::parse(msg.msg.as_str(), &mut task.sent) else {
+                warn!("invalid probe: {}", msg.msg);
+                return;
+            };
+            event_sink.add_idle_callback(move |data: &mut AppData| {
+                data.probe_received(index, sample);
+            });
+            return;
+        }
+    }
+    for routed in session.received(index, &msg).await {
+        match routed {
+            Routed::Forward {
+                bridge, backward, ..
+            } => event_sink.add_idle_callback(move |data: &mut AppData| {
+                data.bridge_forwarded(bridge, backward);
+            }),
+            Routed::Echo { bridge } => event_sink.add_idle_callback(move |data: &mut AppData| {
+                data.bridge_dropped(bridge);
+            }),
+        }
+    }
+    // the script never receives its own messages, else it could publish them again and again
+    if let Some(task) = scripts
+        .get(&index)
+        .filter(|x| x.marker.as_str() != msg.script.as_str())
+    {
+        if !task.worker.push(ScriptInput::Message(msg.clone())) {
+            script_busy(event_sink, index, format!("the message of {}", msg.topic));
+        }
+    }
+    let mut fired = Vec::new();
+    let mut notify = false;
+    let now = Instant::now();
+    for rule in rules.get_mut(&index).into_iter().flatten() {
+        if !rule.matches(&msg) {
+            continue;
+        }
+        fired.push(rule.id);
+        msg.highlight |= rule.highlight;
+        notify |= rule.take_notify(now);
+        if let Some(input) = rule.publish_input(&msg) {
+            publish(event_sink, session, index, input).await;
         }
     }
     event_sink.add_idle_callback(move |data: &mut AppData| {
-        if let Err(e) = data.reconnect(id) {
-            error!("{:?}", e);
+        if !fired.is_empty() {
+            if notify {
+                info!("{}: {}", RULE_FIRED, msg.topic);
+                crate::rule::notify(RULE_FIRED, msg.topic.to_string());
+            }
+            data.rules_fired(index, fired);
+        }
+        if !data.request_replied(index, &msg, now_millis()) {
+            data.receive_msg(index, msg);
         }
     });
 }
 
-async fn disconnect(
+fn start_local_broker(
     event_sink: &druid::ExtEventSink,
-    mqtt_clients: &mut HashMap<usize, AsyncClient>,
-    id: usize,
+    tx: &Sender<AppEvent>,
+    port: u16,
+    lan: bool,
 ) {
-    if let Some(client) = mqtt_clients.remove(&id) {
-        if let Err(e) = client.disconnect().await {
-            error!("{:?}", e);
-        }
+    if let Err(e) = crate::local_broker::start(port, lan, tx.clone()) {
+        error!("{:?}", e);
+        warn!("{}", e.to_string());
+        return;
     }
     event_sink.add_idle_callback(move |data: &mut AppData| {
-        if let Err(e) = data.disconnect(id) {
+        if let Err(e) = data.local_broker_started(port) {
             error!("{:?}", e);
-        } else {
-            info!("{}", DISCONNECT_SUCCESS);
         }
     });
 }
 
-fn close_broker_tab(event_sink: &druid::ExtEventSink, id: usize) {
+fn local_broker_event(event_sink: &druid::ExtEventSink, event: LocalBrokerEvent) {
     event_sink.add_idle_callback(move |data: &mut AppData| {
-        if let Err(e) = data.close_tab(id) {
+        data.local_broker_event(event);
+    });
+}
+
+fn export_profiles(event_sink: &druid::ExtEventSink, path: AString) {
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        if let Err(e) = data.export_profiles_to(path.as_str()) {
             error!("{:?}", e);
+            warn!("{}", e.to_string());
+        } else {
+            info!("{}", EXPORT_PROFILES_SUCCESS);
         }
     });
 }
-async fn close_connection_tab(
-    event_sink: &druid::ExtEventSink,
-    mqtt_clients: &mut HashMap<usize, AsyncClient>,
-    id: usize,
+
+/// the names in use are renamed, see `Conflict`
+fn import_profiles(event_sink: &druid::ExtEventSink, path: AString) {
+    let profiles = match std::fs::read_to_string(path.as_str()) {
+        Ok(content) => crate::profile::parse(&content, None),
+        Err(e) => Err(e.into()),
+    };
+    let profiles = match profiles {
+        Ok(profiles) => profiles,
+        Err(e) => {
+            error!("{:?}", e);
+            warn!("{}", e.to_string());
+            return;
+        }
+    };
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        match data.import_profiles(profiles, Conflict::Rename) {
+            Ok(summary) => info!("{} {}", IMPORT_PROFILES_SUCCESS, summary),
+            Err(e) => error!("{:?}", e),
+        }
+    });
+}
+
+/// derive the key here, argon2 is too slow for the ui thread
+fn set_master_password(event_sink: &druid::ExtEventSink, master: AString) {
+    match VaultMeta::create(master.as_str()) {
+        Ok((meta, key)) => {
+            event_sink.add_idle_callback(move |data: &mut AppData| {
+                if let Err(e) = data.vault_created(meta, key) {
+                    error!("{:?}", e);
+                    warn!("{}", e.to_string());
+                }
+            });
+        }
+        Err(e) => {
+            error!("{:?}", e);
+            warn!("{}", e.to_string());
+        }
+    }
+}
+
+fn unlock_vault(event_sink: &druid::ExtEventSink, meta: VaultMeta, master: AString) {
+    match meta.unlock(master.as_str()) {
+        Ok(key) => {
+            event_sink.add_idle_callback(move |data: &mut AppData| {
+                if let Err(e) = data.unlock_vault(meta, key) {
+                    error!("{:?}", e);
+                }
+            });
+        }
+        Err(e) => {
+            debug!("{:?}", e);
+            warn!("{}", VAULT_WRONG_PASSWORD);
+        }
+    }
+}
+
+/// the routes from the source to every target, replace the running ones of the source
+async fn start_bridge(
+    event_sink: &druid::ExtEventSink,
+    session: &mut Session,
+    routes: Vec<BridgeRoute>,
+) {
+    let Some(source) = routes.first().map(|x| x.source) else {
+        return;
+    };
+    if let Err(e) = session.start_bridge(source, routes).await {
+        warn!("{}", e);
+        return;
+    }
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        data.bridge_started(source);
+    });
+}
+
+async fn stop_bridge(event_sink: &druid::ExtEventSink, session: &mut Session, source: usize) {
+    session.stop_bridge(source).await;
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        data.bridge_stopped(source);
+    });
+}
+
+/// stop everything running on the connection before it's closed, e.g. the bridges from or to it
+async fn stop_broker(
+    event_sink: &druid::ExtEventSink,
+    session: &mut Session,
+    schedules: &mut HashMap<Id, ScheduleTask>,
+    probes: &mut HashMap<usize, ProbeTask>,
+    retain_scans: &mut HashMap<usize, RetainScanTask>,
+    scripts: &mut HashMap<usize, ScriptTask>,
+    broker_id: usize,
+) {
+    stop_broker_schedules(schedules, broker_id);
+    stop_broker_probe(probes, broker_id);
+    retain_scans.remove(&broker_id);
+    stop_broker_script(scripts, broker_id);
+    for source in session.close(broker_id).await {
+        event_sink.add_idle_callback(move |data: &mut AppData| {
+            data.bridge_stopped(source);
+        });
+    }
+}
+
+fn add_rule(event_sink: &druid::ExtEventSink, index: usize) {
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        if let Err(e) = data.add_rule(index) {
+            warn!("{}", e.to_string());
+        } else {
+            info!("{}", ADD_RULE_SUCCESS);
+        }
+    });
+}
+
+fn remove_rule(event_sink: &druid::ExtEventSink, index: usize, id: Id) {
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        if let Err(e) = data.remove_rule(index, id) {
+            error!("{:?}", e);
+        } else {
+            info!("{}", DELETE_RULE_SUCCESS);
+        }
+    });
+}
+
+fn toggle_rule(event_sink: &druid::ExtEventSink, index: usize, id: Id) {
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        if let Err(e) = data.toggle_rule(index, id) {
+            error!("{:?}", e);
+        }
+    });
+}
+
+fn update_rules(rules: &mut HashMap<usize, Vec<RuleMatcher>>, index: usize, list: Vec<Rule>) {
+    let matchers = list
+        .iter()
+        .filter_map(|x| match RuleMatcher::compile(x) {
+            Ok(matcher) => Some(matcher),
+            Err(e) => {
+                error!("{:?}", e);
+                None
+            }
+        })
+        .collect();
+    rules.insert(index, matchers);
+}
+
+fn load_script(
+    tx: Sender<AppEvent>,
+    scripts: &mut HashMap<usize, ScriptTask>,
+    index: usize,
+    code: AString,
+) {
+    // reload: the timers of the old script are dropped too
+    stop_broker_script(scripts, index);
+    let worker = ScriptWorker::spawn(code.as_str().to_string(), move |output| {
+        if let Err(e) = tx.send(AppEvent::ScriptOutput(index, output)) {
+            error!("{:?}", e);
+        }
+    });
+    let task = ScriptTask {
+        worker,
+        code,
+        timers: Vec::new(),
+        marker: general_id(),
+    };
+    scripts.insert(index, task);
+}
+
+fn stop_script(
+    event_sink: &druid::ExtEventSink,
+    scripts: &mut HashMap<usize, ScriptTask>,
+    index: usize,
+) {
+    stop_broker_script(scripts, index);
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        if let Err(e) = data.script_stopped(index) {
+            error!("{:?}", e);
+        }
+    });
+}
+
+/// dropping the worker ends the thread of the script
+fn stop_broker_script(scripts: &mut HashMap<usize, ScriptTask>, broker_id: usize) {
+    if let Some(task) = scripts.remove(&broker_id) {
+        task.timers.iter().for_each(|x| x.abort());
+    }
+}
+
+fn script_timer(
+    event_sink: &druid::ExtEventSink,
+    scripts: &HashMap<usize, ScriptTask>,
+    index: usize,
+    name: String,
+) {
+    let Some(task) = scripts.get(&index) else {
+        debug!("script is stopped");
+        return;
+    };
+    let what = format!("the timer {}", name);
+    if !task.worker.push(ScriptInput::Timer(name)) {
+        script_busy(event_sink, index, what);
+    }
+}
+
+/// a run of the script is done on its thread
+async fn script_output(
+    event_sink: &druid::ExtEventSink,
+    tx: Sender<AppEvent>,
+    session: &mut Session,
+    scripts: &mut HashMap<usize, ScriptTask>,
+    index: usize,
+    output: ScriptOutput,
+) {
+    let Some(task) = scripts.get_mut(&index) else {
+        debug!("script is stopped");
+        return;
+    };
+    if task.worker.id() != output.worker {
+        debug!("script is replaced");
+        return;
+    }
+    if output.loaded {
+        if let Some(e) = output.error {
+            stop_broker_script(scripts, index);
+            script_error(event_sink, index, e);
+            return;
+        }
+        let code = task.code.clone();
+        event_sink.add_idle_callback(move |data: &mut AppData| {
+            if let Err(e) = data.script_loaded(index, code) {
+                error!("{:?}", e);
+            } else {
+                info!("{}", SCRIPT_LOADED);
+            }
+        });
+    }
+    run_script_actions(event_sink, tx, session, task, index, output.actions).await;
+    if let Some(e) = output.error {
+        script_error(event_sink, index, e);
+    }
+}
+
+/// perform the calls of the script's api
+async fn run_script_actions(
+    event_sink: &druid::ExtEventSink,
+    tx: Sender<AppEvent>,
+    session: &mut Session,
+    task: &mut ScriptTask,
+    index: usize,
+    actions: Vec<ScriptAction>,
+) {
+    let mut lines = Vec::new();
+    for action in actions {
+        match action {
+            ScriptAction::Publish {
+                topic,
+                payload,
+                qos,
+                retain,
+            } => {
+                let input = PublicInput {
+                    topic: topic.into(),
+                    msg: payload.into(),
+                    qos,
+                    retain,
+                    ..PublicInput::default()
+                };
+                let mqtt_input = MqttPublicInput {
+                    user_properties: vec![(SCRIPT_PROPERTY.to_string(), task.marker.clone())],
+                    ..input.clone().into()
+                };
+                match session.publish(index, mqtt_input).await {
+                    Ok(pkid) => {
+                        event_sink.add_idle_callback(move |data: &mut AppData| {
+                            data.public(index, input, pkid);
+                        });
+                    }
+                    Err(e) => lines.push(format!("error: publish {}: {}", input.topic, e)),
+                }
+            }
+            ScriptAction::Subscribe { topic, qos } => {
+                let input = MqttSubscribeInput {
+                    topic: topic.clone(),
+                    qos: qos.into(),
+                };
+                match session.subscribe(index, input).await {
+                    Ok(_) => lines.push(format!("subscribe {}", topic)),
+                    Err(e) => lines.push(format!("error: subscribe {}: {}", topic, e)),
+                }
+            }
+            ScriptAction::Timer { name, millis } => {
+                task.timers.retain(|x| !x.is_finished());
+                if task.timers.len() >= MAX_TIMERS {
+                    lines.push(format!("error: set_timer {}: too many timers", name));
+                    continue;
+                }
+                let tx = tx.clone();
+                task.timers.push(tokio::spawn(async move {
+                    tokio::time::sleep(Duration::from_millis(millis)).await;
+                    if let Err(e) = tx.send(AppEvent::ScriptTimer(index, name)) {
+                        error!("{:?}", e);
+                    }
+                }));
+            }
+            ScriptAction::Log(line) => lines.push(line),
+        }
+    }
+    if !lines.is_empty() {
+        event_sink.add_idle_callback(move |data: &mut AppData| {
+            data.script_output(index, lines);
+        });
+    }
+}
+
+fn script_error(event_sink: &druid::ExtEventSink, index: usize, e: String) {
+    let line = format!("error: {}", e);
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        data.script_output(index, vec![line]);
+        warn!("{}", SCRIPT_ERROR);
+    });
+}
+
+/// the queue of the script is full
+fn script_busy(event_sink: &druid::ExtEventSink, index: usize, what: String) {
+    let line = format!("error: the script is busy, {} is dropped", what);
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        data.script_output(index, vec![line]);
+    });
+}
+
+async fn start_retain_scan(
+    event_sink: &druid::ExtEventSink,
+    tx: Sender<AppEvent>,
+    session: &mut Session,
+    retain_scans: &mut HashMap<usize, RetainScanTask>,
+    index: usize,
+    filter: SubscribeFilter,
+) {
+    if retain_scans.contains_key(&index) {
+        debug!("retain scan is running");
+        return;
+    }
+    let task = RetainScanTask {
+        filter: filter.topic.as_ref().clone(),
+    };
+    let input = MqttSubscribeManyInput {
+        filters: vec![filter.into()],
+    };
+    if let Err(e) = session.subscribe_internal(index, input).await {
+        error!("{:?}", e);
+        return;
+    }
+    retain_scans.insert(index, task);
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        data.retain_scan_started(index);
+    });
+    // the retained messages are sent by the broker right after subscribing
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_secs(RETAIN_SCAN_SECS)).await;
+        if let Err(e) = tx.send(AppEvent::RetainScanEnd(index)) {
+            error!("{:?}", e);
+        }
+    });
+}
+
+async fn retain_scan_end(
+    event_sink: &druid::ExtEventSink,
+    session: &mut Session,
+    retain_scans: &mut HashMap<usize, RetainScanTask>,
+    index: usize,
+) {
+    let Some(task) = retain_scans.remove(&index) else {
+        return;
+    };
+    // only the reference of the scan is released, the user's subscription is kept
+    if let Err(e) = session.unsubscribe_internal(index, task.filter).await {
+        error!("{:?}", e);
+    }
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        data.retain_scan_end(index);
+    });
+}
+
+fn preview_retain_clear(event_sink: &druid::ExtEventSink, index: usize) {
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        data.preview_retain_clear(index);
+    });
+}
+
+fn cancel_retain_clear(event_sink: &druid::ExtEventSink, index: usize) {
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        data.cancel_retain_clear(index);
+    });
+}
+
+async fn clear_retain(
+    event_sink: &druid::ExtEventSink,
+    session: &mut Session,
+    index: usize,
+    topics: Vec<String>,
 ) {
-    if let Some(client) = mqtt_clients.remove(&id) {
-        if let Err(e) = client.disconnect().await {
+    let mut cleared = Vec::with_capacity(topics.len());
+    for topic in topics {
+        let input = MqttPublicInput {
+            topic: topic.clone(),
+            msg: String::new(),
+            qos: QoS::AtLeastOnce.into(),
+            retain: true,
+            user_properties: Vec::new(),
+        };
+        match session.publish(index, input).await {
+            Ok(_) => cleared.push(topic),
+            Err(e) => error!("{:?}", e),
+        }
+    }
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        data.retain_cleared(index, cleared);
+    });
+}
+
+async fn request(
+    event_sink: &druid::ExtEventSink,
+    tx: Sender<AppEvent>,
+    session: &mut Session,
+    index: usize,
+    input: RequestInput,
+) {
+    debug!("{:?}", input);
+    let subscribe = MqttSubscribeInput {
+        topic: input.response_topic.as_ref().clone(),
+        qos: input.qos.clone().into(),
+    };
+    if let Err(e) = session.subscribe_reply(index, subscribe).await {
+        error!("{:?}", e);
+        return;
+    }
+    let correlation = uuid::Uuid::new_v4().to_string();
+    let mqtt_input = MqttRequestInput::from(&input, correlation.clone());
+    if let Err(e) = session.request(index, mqtt_input).await {
+        error!("{:?}", e);
+        return;
+    }
+    let record = RequestRecord::from(&input, correlation.clone(), now_millis());
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        data.request_sent(index, record);
+    });
+    let timeout = Duration::from_secs(input.timeout);
+    tokio::spawn(async move {
+        tokio::time::sleep(timeout).await;
+        if let Err(e) = tx.send(AppEvent::RequestTimeout(index, correlation.into())) {
             error!("{:?}", e);
         }
+    });
+}
+
+fn request_timeout(event_sink: &druid::ExtEventSink, index: usize, correlation: AString) {
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        data.request_timeout(index, correlation);
+    });
+}
+
+fn pub_ack(event_sink: &druid::ExtEventSink, id: usize, ack: PubAck) {
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        data.puback(id, ack);
+        info!("{}", PUBLISH_SUCCESS);
+    });
+}
+
+fn sub_ack(event_sink: &druid::ExtEventSink, id: usize, ack: SubAck) {
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        if let Err(e) = data.suback(id, ack) {
+            error!("{:?}", e);
+        } else {
+            info!("{}", SUBSCRIBE_SUCCESS);
+        }
+    });
+}
+fn select_tabs(event_sink: &druid::ExtEventSink, id: usize) {
+    if let Err(e) = event_sink.submit_command(SELECTOR_TABS_SELECTED, id, TABS_ID) {
+        error!("{:?}", e);
+    }
+}
+fn click_broker(
+    event_sink: &druid::ExtEventSink,
+    tx: Sender<AppEvent>,
+    clicks: &mut HashMap<usize, usize>,
+    id: usize,
+) {
+    if let Some(_previous) = clicks.remove(&id) {
+        event_sink.add_idle_callback(move |data: &mut AppData| {
+            data.db_click_broker(id);
+        });
     } else {
-        error!("can't find client");
+        clicks.insert(id, id);
+        let async_tx = tx.clone();
+        event_sink.add_idle_callback(move |data: &mut AppData| {
+            if let Err(e) = data.click_broker(id) {
+                error!("{:?}", e);
+            }
+        });
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(280)).await;
+            if let Err(e) = async_tx.send(AppEvent::DbClickCheck(id)) {
+                error!("{:?}", e);
+            }
+        });
+    }
+}
+
+fn db_click_check(clicks: &mut HashMap<usize, usize>, id: usize) {
+    if let Some(_previous) = clicks.remove(&id) {}
+}
+
+async fn click_subscribe_his(
+    event_sink: &druid::ExtEventSink,
+    tx: Sender<AppEvent>,
+    session: &mut Session,
+    click_his: &mut Option<SubscribeHis>,
+    his: SubscribeHis,
+) {
+    let index = his.broker_id;
+    if let Some(_previous) = click_his.take() {
+        if _previous == his {
+            // double
+            match session.subscribe_many(index, his.to_input().into()).await {
+                Ok(pkid) => {
+                    event_sink.add_idle_callback(move |data: &mut AppData| {
+                        if let Err(e) = data.subscribe(index, _previous, pkid) {
+                            error!("{:?}", e);
+                        }
+                    });
+                }
+                Err(e) => {
+                    error!("{:?}", e);
+                }
+            }
+            return;
+        }
+    }
+    *click_his = Some(his.clone());
+    let data_his = his.clone();
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        if let Err(e) = data.click_subscribe_his(data_his) {
+            error!("{:?}", e);
+        }
+    });
+    let async_tx = tx.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(280)).await;
+        if let Err(e) = async_tx.send(AppEvent::DbClickCheckSubscribeHis(his)) {
+            error!("{:?}", e);
+        }
+    });
+}
+
+async fn db_click_check_subscribe_his(click_his: &mut Option<SubscribeHis>, his: SubscribeHis) {
+    if click_his.as_ref().map_or(false, |x| *x == his) {
+        click_his.take();
+    }
+}
+
+async fn re_connect(event_sink: &druid::ExtEventSink, session: &mut Session, id: usize) {
+    if let Err(e) = session.disconnect(id).await {
+        debug!("{:?}", e);
+    }
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        if let Err(e) = data.reconnect(id) {
+            error!("{:?}", e);
+        }
+    });
+}
+
+async fn disconnect(event_sink: &druid::ExtEventSink, session: &mut Session, id: usize) {
+    if let Err(e) = session.disconnect(id).await {
+        debug!("{:?}", e);
+    }
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        if let Err(e) = data.disconnect(id) {
+            error!("{:?}", e);
+        } else {
+            info!("{}", DISCONNECT_SUCCESS);
+        }
+    });
+}
+
+fn close_broker_tab(event_sink: &druid::ExtEventSink, id: usize) {
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        if let Err(e) = data.close_tab(id) {
+            error!("{:?}", e);
+        }
+    });
+}
+async fn close_connection_tab(event_sink: &druid::ExtEventSink, session: &mut Session, id: usize) {
+    if let Err(e) = session.disconnect(id).await {
+        error!("{:?}", e);
     }
     event_sink.add_idle_callback(move |data: &mut AppData| {
         data.close_connection(id);
@@ -423,6 +1564,50 @@ fn delete_broker(event_sink: &druid::ExtEventSink) {
         }
     });
 }
+fn duplicate_broker(event_sink: &druid::ExtEventSink) {
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        if let Err(e) = data.duplicate_broker() {
+            error!("{:?}", e);
+        } else {
+            info!("{}", DUPLICATE_BROKER_SUCCESS);
+        }
+    });
+}
+fn add_environment(event_sink: &druid::ExtEventSink) {
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        if let Err(e) = data.add_environment() {
+            warn!("{}", e.to_string());
+        }
+    });
+}
+fn remove_environment(event_sink: &druid::ExtEventSink, id: Id) {
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        if let Err(e) = data.remove_environment(id) {
+            error!("{:?}", e);
+        }
+    });
+}
+fn save_environments(event_sink: &druid::ExtEventSink) {
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        if let Err(e) = data.save_environments() {
+            warn!("{}", e.to_string());
+        } else {
+            info!("{}", SAVE_ENVIRONMENTS_SUCCESS);
+        }
+    });
+}
+fn move_broker(event_sink: &druid::ExtEventSink, from: usize, to: usize) {
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        if let Err(e) = data.move_broker(from, to) {
+            error!("{:?}", e);
+        }
+    });
+}
+fn toggle_broker_folder(event_sink: &druid::ExtEventSink, name: AString) {
+    event_sink.add_idle_callback(move |data: &mut AppData| {
+        data.toggle_broker_folder(name);
+    });
+}
 fn connect_ack_success(event_sink: &druid::ExtEventSink, id: usize) {
     info!("connect success!");
     event_sink.add_idle_callback(move |data: &mut AppData| {
@@ -431,3 +1616,23 @@ fn connect_ack_success(event_sink: &druid::ExtEventSink, id: usize) {
         }
     });
 }
+
+#[cfg(test)]
+mod test {
+    use crate::data::common::SubscribeMsg;
+    use crate::data::AppEvent;
+    use crate::session::{SessionEvent, SessionObserver};
+
+    #[test]
+    fn test_observer() {
+        let (tx, rx) = crossbeam_channel::bounded::<AppEvent>(8);
+        tx.notify(SessionEvent::Publish(
+            1,
+            SubscribeMsg::sample("a/b", "hello"),
+        ));
+        match rx.try_recv().unwrap() {
+            AppEvent::ReceivePublic(1, msg) => assert_eq!(msg.msg.as_str(), "hello"),
+            event => panic!("{:?}", event),
+        }
+    }
+}
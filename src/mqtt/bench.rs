@@ -0,0 +1,346 @@
+use crate::session::types::QoS;
+use crate::util::general_id;
+use anyhow::{bail, Result};
+use log::{debug, error, info, warn};
+use rumqttc::v5::mqttbytes::v5::{Packet, SubscribeReasonCode};
+use rumqttc::v5::{AsyncClient, Event, EventLoop, MqttOptions};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct BenchConfig {
+    pub addr: String,
+    pub port: u16,
+    pub user_name: Option<String>,
+    pub password: Option<String>,
+    /// publisher i publishes to `{topic}/{i}`, subscribers subscribe `{topic}/+`
+    pub topic: String,
+    pub publishers: usize,
+    pub subscribers: usize,
+    /// messages per second of all publishers
+    pub rate: u64,
+    pub payload_size: usize,
+    pub qos: QoS,
+    // seconds
+    pub duration: u64,
+    // seconds to wait for the acks and messages after publishing
+    pub drain: u64,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            addr: "127.0.0.1".to_string(),
+            port: 1883,
+            user_name: None,
+            password: None,
+            topic: "for-mqtt/bench".to_string(),
+            publishers: 1,
+            subscribers: 1,
+            rate: 100,
+            payload_size: 64,
+            qos: QoS::AtLeastOnce,
+            duration: 10,
+            drain: 3,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct LatencyReport {
+    pub samples: usize,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+impl LatencyReport {
+    pub fn from(mut latencies: Vec<Duration>) -> Self {
+        if latencies.is_empty() {
+            return Self::default();
+        }
+        latencies.sort();
+        let percentile = |p: f64| {
+            let index = ((latencies.len() - 1) as f64 * p).round() as usize;
+            latencies[index].as_secs_f64() * 1000.0
+        };
+        Self {
+            samples: latencies.len(),
+            p50_ms: percentile(0.5),
+            p90_ms: percentile(0.9),
+            p99_ms: percentile(0.99),
+            max_ms: percentile(1.0),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BenchReport {
+    pub config: BenchConfig,
+    pub elapsed_ms: u64,
+    pub sent: u64,
+    /// PUBACK of qos 1, PUBCOMP of qos 2
+    pub acked: u64,
+    pub received: u64,
+    /// every subscriber should receive every message sent
+    pub expected: u64,
+    pub loss: f64,
+    /// messages per second
+    pub send_throughput: f64,
+    pub receive_throughput: f64,
+    pub ack_latency: LatencyReport,
+}
+
+impl BenchReport {
+    pub fn export(&self, path: impl AsRef<Path>) -> Result<()> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Counter {
+    subscribed: AtomicU64,
+    subscribe_failed: AtomicU64,
+    sent: AtomicU64,
+    acked: AtomicU64,
+    received: AtomicU64,
+    latencies: Mutex<Vec<Duration>>,
+}
+
+/// the pkid is known only when `publish` returns, and the ack may arrive before that
+enum Track {
+    Sent(Instant),
+    Acked(Instant),
+}
+
+type Pending = Arc<Mutex<HashMap<u16, Track>>>;
+
+impl Counter {
+    fn track_sent(&self, pkid: u16, send_time: Instant, pending: &Pending) {
+        let mut pending = pending.lock().unwrap();
+        match pending.remove(&pkid) {
+            Some(Track::Acked(ack_time)) => self.sample(ack_time - send_time),
+            _ => {
+                pending.insert(pkid, Track::Sent(send_time));
+            }
+        }
+    }
+
+    fn ack(&self, pkid: u16, pending: &Option<Pending>) {
+        self.acked.fetch_add(1, Ordering::Relaxed);
+        let Some(pending) = pending else {
+            return;
+        };
+        let ack_time = Instant::now();
+        let mut pending = pending.lock().unwrap();
+        match pending.remove(&pkid) {
+            Some(Track::Sent(send_time)) => self.sample(ack_time - send_time),
+            _ => {
+                pending.insert(pkid, Track::Acked(ack_time));
+            }
+        }
+    }
+
+    fn sample(&self, latency: Duration) {
+        self.latencies.lock().unwrap().push(latency);
+    }
+}
+
+/// run the benchmark against the broker, e.g. a local one
+pub async fn run_bench(config: BenchConfig) -> Result<BenchReport> {
+    if config.publishers == 0 || config.rate == 0 {
+        bail!("publishers and rate should be more than 0");
+    }
+    let counter = Arc::new(Counter::default());
+    let mut clients = Vec::with_capacity(config.publishers + config.subscribers);
+    for index in 0..config.subscribers {
+        let client = start_client(&config, "sub", index, counter.clone(), None);
+        client
+            .subscribe(format!("{}/+", config.topic), config.qos.clone().into())
+            .await?;
+        clients.push(client);
+    }
+    // subscribers first, so that no message is missed
+    let subscribe_deadline = Instant::now() + Duration::from_secs(10);
+    while counter.subscribed.load(Ordering::Relaxed) < config.subscribers as u64 {
+        if counter.subscribe_failed.load(Ordering::Relaxed) > 0 {
+            bail!("subscribers are refused by the broker");
+        }
+        if Instant::now() > subscribe_deadline {
+            bail!("subscribers fail to subscribe in time");
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    info!("{} subscribers are ready", config.subscribers);
+
+    let start = Instant::now();
+    let deadline = start + Duration::from_secs(config.duration);
+    let interval = Duration::from_secs_f64(config.publishers as f64 / config.rate as f64);
+    let payload = vec![b'x'; config.payload_size];
+    let mut publishers = Vec::with_capacity(config.publishers);
+    for index in 0..config.publishers {
+        let pending: Pending = Default::default();
+        let client = start_client(
+            &config,
+            "pub",
+            index,
+            counter.clone(),
+            Some(pending.clone()),
+        );
+        clients.push(client.clone());
+        let (counter, payload) = (counter.clone(), payload.clone());
+        let (topic, qos) = (format!("{}/{}", config.topic, index), config.qos.clone());
+        publishers.push(tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            while Instant::now() < deadline {
+                ticker.tick().await;
+                let send_time = Instant::now();
+                match client
+                    .publish_and_tracing(topic.clone(), qos.clone().into(), false, payload.clone())
+                    .await
+                {
+                    Ok(pkid) => {
+                        counter.sent.fetch_add(1, Ordering::Relaxed);
+                        if qos != QoS::AtMostOnce {
+                            counter.track_sent(pkid, send_time, &pending);
+                        }
+                    }
+                    Err(e) => {
+                        error!("{:?}", e);
+                        break;
+                    }
+                }
+            }
+        }));
+    }
+    for publisher in publishers {
+        publisher.await?;
+    }
+    let publish_elapsed = start.elapsed();
+    tokio::time::sleep(Duration::from_secs(config.drain)).await;
+    for client in clients {
+        if let Err(e) = client.disconnect().await {
+            warn!("{:?}", e);
+        }
+    }
+
+    let sent = counter.sent.load(Ordering::Relaxed);
+    let received = counter.received.load(Ordering::Relaxed);
+    let expected = sent * config.subscribers as u64;
+    let latencies = std::mem::take(&mut *counter.latencies.lock().unwrap());
+    let secs = publish_elapsed.as_secs_f64();
+    Ok(BenchReport {
+        elapsed_ms: publish_elapsed.as_millis() as u64,
+        sent,
+        acked: counter.acked.load(Ordering::Relaxed),
+        received,
+        expected,
+        loss: if expected == 0 {
+            0.0
+        } else {
+            expected.saturating_sub(received) as f64 / expected as f64
+        },
+        send_throughput: sent as f64 / secs,
+        receive_throughput: received as f64 / secs,
+        ack_latency: LatencyReport::from(latencies),
+        config,
+    })
+}
+
+fn start_client(
+    config: &BenchConfig,
+    kind: &str,
+    index: usize,
+    counter: Arc<Counter>,
+    pending: Option<Pending>,
+) -> AsyncClient {
+    let client_id = format!("bench-{}-{}-{}", kind, index, general_id());
+    let mut options = MqttOptions::new(client_id, config.addr.as_str(), config.port);
+    if let (Some(user_name), Some(password)) = (&config.user_name, &config.password) {
+        options.set_credentials(user_name, password);
+    }
+    options.set_keep_alive(Duration::from_secs(60));
+    let (client, eventloop) = AsyncClient::new(options, 100);
+    tokio::spawn(poll(eventloop, counter, pending));
+    client
+}
+
+async fn poll(mut eventloop: EventLoop, counter: Arc<Counter>, pending: Option<Pending>) {
+    loop {
+        let event = match eventloop.poll().await {
+            Ok(Event::Incoming(event)) => event,
+            Ok(_) => continue,
+            Err(e) => {
+                debug!("{:?}", e);
+                break;
+            }
+        };
+        match *event {
+            Packet::PubAck(ack, _) => counter.ack(ack.pkid, &pending),
+            Packet::PubComp(comp, _) => counter.ack(comp.pkid, &pending),
+            Packet::SubAck(ack, _) => {
+                let success = ack.return_codes.iter().all(|x| {
+                    matches!(
+                        x,
+                        SubscribeReasonCode::QoS0
+                            | SubscribeReasonCode::QoS1
+                            | SubscribeReasonCode::QoS2
+                    )
+                });
+                if success {
+                    counter.subscribed.fetch_add(1, Ordering::Relaxed);
+                } else {
+                    warn!("subscribe fail: {:?}", ack.return_codes);
+                    counter.subscribe_failed.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            Packet::Publish(_, _) => {
+                counter.received.fetch_add(1, Ordering::Relaxed);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::mqtt::bench::{BenchConfig, Counter, LatencyReport, Pending};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_latency_report() {
+        let latencies = (1..=100).map(|x| Duration::from_millis(x)).collect();
+        let report = LatencyReport::from(latencies);
+        assert_eq!(report.samples, 100);
+        assert_eq!(report.p50_ms, 51.0);
+        assert_eq!(report.p99_ms, 99.0);
+        assert_eq!(report.max_ms, 100.0);
+        assert_eq!(LatencyReport::from(Vec::new()), LatencyReport::default());
+
+        let config = serde_json::to_string(&BenchConfig::default()).unwrap();
+        let config: BenchConfig = serde_json::from_str(&config).unwrap();
+        assert_eq!(config.port, 1883);
+    }
+
+    #[test]
+    fn test_ack_before_sent() {
+        let counter = Counter::default();
+        let pending = Pending::default();
+        let send_time = Instant::now();
+        // the ack of pkid 1 arrives before `publish` returns
+        counter.ack(1, &Some(pending.clone()));
+        counter.track_sent(1, send_time, &pending);
+        counter.track_sent(2, send_time, &pending);
+        counter.ack(2, &Some(pending.clone()));
+        assert_eq!(counter.latencies.lock().unwrap().len(), 2);
+        assert!(pending.lock().unwrap().is_empty());
+    }
+}
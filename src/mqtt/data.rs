@@ -1,41 +1,28 @@
-use crate::data::common::{PublicInput, SubscribeHis, SubscribeInput};
-use rumqttc::v5::mqttbytes::QoS;
+use rumqttc::v5::mqttbytes::{Filter, QoS};
 
+#[derive(Debug, Clone)]
 pub struct MqttPublicInput {
     pub topic: String,
     pub msg: String,
     pub qos: QoS,
     pub retain: bool,
+    /// user properties of mqtt5, e.g. the marker of the bridge
+    pub user_properties: Vec<(String, String)>,
 }
 
-pub struct MqttSubscribeInput {
+pub struct MqttRequestInput {
     pub topic: String,
+    pub msg: String,
     pub qos: QoS,
+    pub response_topic: String,
+    pub correlation: String,
 }
 
-impl From<PublicInput> for MqttPublicInput {
-    fn from(val: PublicInput) -> Self {
-        Self {
-            topic: val.topic.as_ref().clone(),
-            msg: val.msg.as_ref().clone(),
-            qos: QoS::AtLeastOnce,
-            retain: val.retain,
-        }
-    }
-}
-impl From<SubscribeInput> for MqttSubscribeInput {
-    fn from(val: SubscribeInput) -> Self {
-        Self {
-            topic: val.topic.as_ref().clone(),
-            qos: QoS::AtLeastOnce,
-        }
-    }
+pub struct MqttSubscribeInput {
+    pub topic: String,
+    pub qos: QoS,
 }
-impl From<SubscribeHis> for MqttSubscribeInput {
-    fn from(val: SubscribeHis) -> Self {
-        Self {
-            topic: val.topic.as_ref().clone(),
-            qos: val.qos.into(),
-        }
-    }
+
+pub struct MqttSubscribeManyInput {
+    pub filters: Vec<Filter>,
 }
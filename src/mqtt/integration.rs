@@ -0,0 +1,320 @@
+//! Tests of the mqtt functions and the ack bookkeeping of `AppData` against the embedded broker.
+//! The harness plays the logic worker: it calls the mqtt functions and deals the events with `AppData`.
+use crate::data::common::{
+    Id, Msg, PublicInput, PublicStatus, QoS, SubscribeFilter, SubscribeHis, SubscribeInput,
+    SubscribeStatus,
+};
+use crate::data::hierarchy::AppData;
+use crate::data::AppEvent;
+use crate::local_broker;
+use crate::mqtt::{init_connect, mqtt_public, mqtt_subscribe, mqtt_subscribe_many, to_unsubscribe};
+use crate::session::types::ConnectOptions;
+use crate::session::SessionObserver;
+use crate::util::db::ArcDb;
+use crate::util::general_id;
+use crossbeam_channel::{unbounded, Receiver};
+use rumqttc::v5::mqttbytes::{SubAck, SubscribeReasonCode};
+use rumqttc::v5::AsyncClient;
+use std::collections::HashMap;
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const ID: usize = 0;
+const WAIT: Duration = Duration::from_secs(3);
+
+struct Harness {
+    data: AppData,
+    rx: Receiver<AppEvent>,
+    observer: Arc<dyn SessionObserver>,
+    clients: HashMap<usize, AsyncClient>,
+}
+
+impl Harness {
+    /// the broker entry of a free port, the embedded broker listens on it if `listening`
+    fn new(listening: bool) -> Self {
+        let (tx, rx) = unbounded();
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        if listening {
+            local_broker::start(port, false, tx.clone()).unwrap();
+            wait_listening(port);
+        }
+        let db = ArcDb::init_temporary_db().unwrap();
+        let mut data = AppData::load(db.clone(), tx.clone()).unwrap();
+        data.add_broker();
+        let broker = data.brokers.iter_mut().find(|x| x.id == ID).unwrap();
+        broker.client_id = general_id().into();
+        broker.addr = "127.0.0.1".to_string().into();
+        broker.port = port;
+        Self {
+            data,
+            rx,
+            observer: Arc::new(tx),
+            clients: HashMap::new(),
+        }
+    }
+
+    /// as `AppEvent::Connect`
+    async fn connect(&mut self) {
+        let broker = self.data.find_broker(ID).unwrap().clone();
+        let client = init_connect(&ConnectOptions::from(&broker), self.observer.clone())
+            .await
+            .unwrap();
+        self.clients.insert(ID, client);
+        self.data.init_connection(ID).unwrap();
+    }
+
+    async fn subscribe(&mut self, topics: &[&str]) -> u16 {
+        let input = SubscribeInput {
+            broker_id: ID,
+            filters: topics
+                .iter()
+                .map(|x| SubscribeFilter {
+                    topic: x.to_string().into(),
+                    qos: QoS::AtLeastOnce,
+                    ..SubscribeFilter::default()
+                })
+                .collect(),
+        };
+        let pkid = mqtt_subscribe_many(ID, input.clone().into(), &self.clients)
+            .await
+            .unwrap();
+        self.data.subscribe_by_input(ID, input, pkid).unwrap();
+        pkid
+    }
+
+    async fn publish(&mut self, topic: &str, msg: &str, retain: bool) -> u16 {
+        let input = PublicInput {
+            topic: topic.to_string().into(),
+            msg: msg.to_string().into(),
+            qos: QoS::AtLeastOnce,
+            retain,
+            ..PublicInput::default()
+        };
+        let pkid = mqtt_public(ID, input.clone().into(), &self.clients)
+            .await
+            .unwrap();
+        self.data.public(ID, input, pkid);
+        pkid
+    }
+
+    /// deal the events as the logic worker until `f` is true, false if timeout
+    async fn deal_until(&mut self, f: impl Fn(&AppData) -> bool) -> bool {
+        let deadline = Instant::now() + WAIT;
+        while !f(&self.data) {
+            let Some(timeout) = deadline.checked_duration_since(Instant::now()) else {
+                return false;
+            };
+            let Ok(event) = self.rx.recv_timeout(timeout) else {
+                return false;
+            };
+            match event {
+                AppEvent::Connect(broker) => {
+                    let client =
+                        init_connect(&ConnectOptions::from(&broker), self.observer.clone())
+                            .await
+                            .unwrap();
+                    self.clients.insert(ID, client);
+                    self.data.init_connection(ID).unwrap();
+                }
+                AppEvent::ConnectAckSuccess(id) => self.data.connected(id).unwrap(),
+                AppEvent::SubAck(id, ack) => self.data.suback(id, ack).unwrap(),
+                AppEvent::PubAck(id, ack) => self.data.puback(id, ack),
+                AppEvent::ReceivePublic(id, msg) => self.data.receive_msg(id, msg),
+                AppEvent::UnSubscribeIng(event) => {
+                    let pkid = to_unsubscribe(event.broke_id, event.topic, &self.clients)
+                        .await
+                        .unwrap();
+                    self.data
+                        .unscribeing(event.broke_id, event.subscribe_id, pkid)
+                        .unwrap();
+                }
+                AppEvent::UnSubAck(id, pkid) => self.data.unsubscribe_ack(id, pkid).unwrap(),
+                _ => {}
+            }
+        }
+        true
+    }
+}
+
+fn wait_listening(port: u16) {
+    let deadline = Instant::now() + WAIT;
+    while TcpStream::connect(("127.0.0.1", port)).is_err() {
+        assert!(Instant::now() < deadline, "the local broker doesn't listen");
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+fn connected(data: &AppData) -> bool {
+    data.tab_statuses.get(&ID).map_or(false, |x| x.connected)
+}
+
+fn subscribed(data: &AppData, pkid: u16) -> bool {
+    data.subscribe_topics[&ID]
+        .iter()
+        .filter(|x| x.pkid == pkid)
+        .all(|x| x.status == SubscribeStatus::SubscribeSuccess)
+}
+
+fn published(data: &AppData, pkid: u16) -> bool {
+    data.msgs[&ID].iter().any(|x| match x {
+        Msg::Public(msg) => msg.pkid == pkid && msg.status == PublicStatus::Success,
+        _ => false,
+    })
+}
+
+fn received(data: &AppData, topic: &str) -> Vec<String> {
+    data.msgs[&ID]
+        .iter()
+        .filter_map(|x| match x {
+            Msg::Subscribe(msg) if msg.topic.as_str() == topic => Some(msg.msg.to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_subscribe_publish_unsubscribe() {
+    let mut harness = Harness::new(true);
+    harness.connect().await;
+    assert!(harness.deal_until(connected).await);
+
+    let pkid = harness.subscribe(&["a/#", "b/+"]).await;
+    assert!(harness.deal_until(|x| subscribed(x, pkid)).await);
+    assert_eq!(harness.data.subscribe_topics[&ID].len(), 2);
+    assert_eq!(harness.data.subscribe_hises[&ID].len(), 2);
+
+    let pkid = harness.publish("a/1", "hello", false).await;
+    assert!(
+        harness
+            .deal_until(|x| published(x, pkid) && received(x, "a/1") == ["hello"])
+            .await
+    );
+
+    let id = harness.data.subscribe_topics[&ID]
+        .iter()
+        .find(|x| x.topic.as_str() == "a/#")
+        .unwrap()
+        .id;
+    harness.data.to_unscribe(ID, id).unwrap();
+    assert!(
+        harness
+            .deal_until(|x| x.subscribe_topics[&ID].len() == 1)
+            .await
+    );
+    assert!(harness.data.unsubscribe_ing[&ID].is_empty());
+    // the messages of a connection keep the order
+    harness.publish("a/2", "unsubscribed", false).await;
+    harness.publish("b/1", "subscribed", false).await;
+    assert!(harness.deal_until(|x| !received(x, "b/1").is_empty()).await);
+    assert!(received(&harness.data, "a/2").is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_retain_and_reconnect() {
+    let mut harness = Harness::new(true);
+    harness.connect().await;
+    assert!(harness.deal_until(connected).await);
+    let pkid = harness.publish("r/1", "retained", true).await;
+    assert!(harness.deal_until(|x| published(x, pkid)).await);
+
+    let his = SubscribeHis {
+        id: Id::default(),
+        broker_id: ID,
+        selected: false,
+        topic: "r/#".to_string().into(),
+        qos: QoS::AtLeastOnce,
+        ..SubscribeHis::default()
+    };
+    let pkid = mqtt_subscribe(ID, his.clone().into(), &harness.clients)
+        .await
+        .unwrap();
+    harness.data.subscribe(ID, his, pkid).unwrap();
+    assert!(
+        harness
+            .deal_until(|x| subscribed(x, pkid) && received(x, "r/1") == ["retained"])
+            .await
+    );
+
+    // as `AppEvent::ReConnect`
+    let client = harness.clients.remove(&ID).unwrap();
+    client.disconnect().await.unwrap();
+    harness.data.reconnect(ID).unwrap();
+    assert!(!connected(&harness.data));
+    assert!(harness.deal_until(connected).await);
+    assert!(harness.data.subscribe_topics[&ID].is_empty());
+    assert!(harness.data.msgs[&ID].is_empty());
+
+    // subscribe again from the history
+    let his = harness.data.subscribe_hises[&ID][0].clone();
+    assert_eq!(his.topic.as_str(), "r/#");
+    let pkid = mqtt_subscribe(ID, his.clone().into(), &harness.clients)
+        .await
+        .unwrap();
+    harness.data.subscribe(ID, his, pkid).unwrap();
+    assert!(
+        harness
+            .deal_until(|x| subscribed(x, pkid) && received(x, "r/1") == ["retained"])
+            .await
+    );
+    assert_eq!(harness.data.subscribe_hises[&ID].len(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_failure() {
+    // nothing listens on the port
+    let mut harness = Harness::new(false);
+    harness.connect().await;
+    assert!(!harness.deal_until(connected).await);
+    // the event loop ends with the error of the connection
+    let input = PublicInput {
+        topic: "a/1".to_string().into(),
+        ..PublicInput::default()
+    };
+    assert!(mqtt_public(ID, input.clone().into(), &harness.clients)
+        .await
+        .is_err());
+    assert!(mqtt_public(ID + 1, input.into(), &harness.clients)
+        .await
+        .is_err());
+    assert!(to_unsubscribe(ID + 1, "a/#".to_string(), &harness.clients)
+        .await
+        .is_err());
+    assert!(harness.data.unsubscribe_ack(ID, 1).is_err());
+
+    let filter = |topic: &str| SubscribeFilter {
+        topic: topic.to_string().into(),
+        ..SubscribeFilter::default()
+    };
+    let input = SubscribeInput {
+        broker_id: ID,
+        filters: [filter("x/#"), filter("y/#")].into_iter().collect(),
+    };
+    harness.data.subscribe_by_input(ID, input, 7).unwrap();
+    let ack = SubAck {
+        pkid: 7,
+        return_codes: vec![
+            SubscribeReasonCode::QoS0,
+            SubscribeReasonCode::NotAuthorized,
+        ],
+        properties: None,
+    };
+    harness.data.suback(ID, ack).unwrap();
+    let statuses: Vec<SubscribeStatus> = harness.data.subscribe_topics[&ID]
+        .iter()
+        .map(|x| x.status.clone())
+        .collect();
+    assert_eq!(
+        statuses,
+        [
+            SubscribeStatus::SubscribeSuccess,
+            SubscribeStatus::SubscribeFail
+        ]
+    );
+    assert_eq!(harness.data.subscribe_hises[&ID].len(), 1);
+}
@@ -1,29 +1,48 @@
+pub mod bench;
 pub mod data;
+#[cfg(test)]
+mod integration;
 
-use crate::data::common::Broker;
-use crate::data::common::SubscribeMsg;
-use crate::data::AppEvent;
-use crate::mqtt::data::{MqttPublicInput, MqttSubscribeInput};
+use crate::bridge::BRIDGE_PROPERTY;
+use crate::mqtt::data::{
+    MqttPublicInput, MqttRequestInput, MqttSubscribeInput, MqttSubscribeManyInput,
+};
+use crate::script::SCRIPT_PROPERTY;
+use crate::session::types::{ConnectOptions, SubscribeMsg, TimelineEvent, TimelineKind};
+use crate::session::{SessionEvent, SessionObserver};
 use anyhow::{bail, Result};
-use crossbeam_channel::Sender;
-use druid::piet::TextStorage;
 use log::{debug, error};
 use rumqttc::v5::mqttbytes::v5::Packet;
 use rumqttc::v5::{
-    mqttbytes::{ConnectReturnCode, Publish},
-    AsyncClient, Event, MqttOptions,
+    mqttbytes::{ConnectReturnCode, Publish, PublishProperties},
+    AsyncClient, ConnectionError, Event, MqttOptions,
 };
+use rumqttc::Outgoing;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// the failed reconnects in a row before the connection is closed
+const MAX_RECONNECTS: u32 = 5;
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(2);
 
-pub async fn init_connect(broker: Broker, tx: Sender<AppEvent>) -> Result<AsyncClient> {
+/// the event loop reconnects by itself once connected, see `MAX_RECONNECTS`, and
+/// `SessionEvent::Closed` is notified when it ends
+pub async fn init_connect(
+    broker: &ConnectOptions,
+    observer: Arc<dyn SessionObserver>,
+) -> Result<AsyncClient> {
     let mut mqttoptions =
         MqttOptions::new(broker.client_id.as_str(), broker.addr.as_str(), broker.port);
-    if broker.use_credentials {
-        mqttoptions.set_credentials(&*broker.user_name, &*broker.password);
+    if let Some((user_name, password)) = &broker.credentials {
+        mqttoptions.set_credentials(user_name.as_str(), password.as_str());
     }
-    let some = serde_json::from_str(broker.params.as_str())?;
+    let some: SomeMqttOption = serde_json::from_str(broker.params.as_str())?;
+    let connect = format!(
+        "{} {}:{} keep alive: {}s, clean session: {}",
+        broker.client_id, broker.addr, broker.port, some.keep_alive, some.clean_session
+    );
     update_option(&mut mqttoptions, some);
 
     debug!("{:?}", mqttoptions);
@@ -31,75 +50,171 @@ pub async fn init_connect(broker: Broker, tx: Sender<AppEvent>) -> Result<AsyncC
     let _client_tmp = client.clone();
     let id = broker.id;
     debug!("start");
+    timeline(observer.as_ref(), id, TimelineKind::Connect, connect);
     tokio::spawn(async move {
         debug!("start");
-        while let Ok(event) = eventloop.poll().await {
+        let mut ping = None;
+        // polled again after an error, the event loop reconnects once it has been connected
+        let mut connected = false;
+        let mut reconnects = 0;
+        let mut by_user = false;
+        loop {
+            let event = match eventloop.poll().await {
+                Ok(event) => event,
+                Err(e) => {
+                    timeline(
+                        observer.as_ref(),
+                        id,
+                        TimelineKind::Error,
+                        format!("{:?}", e),
+                    );
+                    // the client is dropped
+                    if matches!(e, ConnectionError::RequestsDone) {
+                        break;
+                    }
+                    if by_user || !connected || reconnects >= MAX_RECONNECTS {
+                        break;
+                    }
+                    reconnects += 1;
+                    tokio::time::sleep(RECONNECT_INTERVAL).await;
+                    timeline(
+                        observer.as_ref(),
+                        id,
+                        TimelineKind::Reconnect,
+                        format!("automatic {}/{}", reconnects, MAX_RECONNECTS),
+                    );
+                    continue;
+                }
+            };
             let event = match event {
                 Event::Incoming(event) => event,
+                Event::Outgoing(Outgoing::PingReq) => {
+                    ping = Some(Instant::now());
+                    timeline(observer.as_ref(), id, TimelineKind::PingReq, "");
+                    continue;
+                }
+                Event::Outgoing(Outgoing::Disconnect) => {
+                    by_user = true;
+                    timeline(observer.as_ref(), id, TimelineKind::Disconnect, "sent");
+                    continue;
+                }
                 _ => continue,
             };
-            let tx = tx.clone();
             debug!("{:?}", event);
             match *event {
                 Packet::ConnAck(ack) => {
-                    deal_conn_ack(ack.code, tx, id);
+                    // with the properties returned by the server
+                    timeline(
+                        observer.as_ref(),
+                        id,
+                        TimelineKind::ConnAck,
+                        format!("{:?}", ack),
+                    );
+                    if matches!(ack.code, ConnectReturnCode::Success) {
+                        connected = true;
+                        reconnects = 0;
+                    }
+                    deal_conn_ack(ack.code, observer.as_ref(), id);
                 }
-                Packet::PubAck(ack, _) => {
-                    if let Err(_) = tx.send(AppEvent::PubAck(id, ack)) {
-                        error!("fail to send event!");
+                Packet::PingResp { .. } => {
+                    let detail = match ping.take() {
+                        Some(ping) => format!("round trip: {}ms", ping.elapsed().as_millis()),
+                        None => String::new(),
                     };
+                    timeline(observer.as_ref(), id, TimelineKind::PingResp, detail);
+                }
+                packet @ Packet::Disconnect { .. } => {
+                    // with the reason code
+                    timeline(
+                        observer.as_ref(),
+                        id,
+                        TimelineKind::Disconnect,
+                        format!("{:?}", packet),
+                    );
+                }
+                Packet::PubAck(ack, _) => {
+                    observer.notify(SessionEvent::PubAck(id, ack));
+                }
+                Packet::PubComp(comp, _) => {
+                    observer.notify(SessionEvent::PubComp(id, comp.pkid));
                 }
                 Packet::SubAck(ack, _) => {
-                    if let Err(_) = tx.send(AppEvent::SubAck(id, ack)) {
-                        error!("fail to send event!");
-                    };
+                    observer.notify(SessionEvent::SubAck(id, ack));
                 }
                 Packet::UnsubAck(ack) => {
-                    if let Err(_) = tx.send(AppEvent::UnSubAck(id, ack.pkid)) {
-                        error!("fail to send event!");
-                    };
+                    observer.notify(SessionEvent::UnSubAck(id, ack.pkid));
                 }
-                Packet::Publish(msg, _) => {
+                Packet::Publish(msg, properties) => {
                     let Publish {
                         dup: _,
                         qos,
-                        retain: _,
+                        retain,
                         topic,
                         pkid,
                         payload,
                     } = msg;
-                    if let Err(_) = tx.send(AppEvent::ReceivePublic(
+                    let (correlation, expiry, user_properties) = match properties {
+                        Some(properties) => (
+                            properties
+                                .correlation_data
+                                .map(|x| String::from_utf8_lossy(x.as_ref()).to_string())
+                                .unwrap_or_default(),
+                            properties.message_expiry_interval.unwrap_or_default(),
+                            properties.user_properties,
+                        ),
+                        None => (String::new(), 0, Vec::new()),
+                    };
+                    let marker = |property: &str| {
+                        user_properties
+                            .iter()
+                            .find(|(key, _)| key.as_str() == property)
+                            .map(|(_, value)| value.clone())
+                            .unwrap_or_default()
+                    };
+                    let (bridge, script) = (marker(BRIDGE_PROPERTY), marker(SCRIPT_PROPERTY));
+                    observer.notify(SessionEvent::Publish(
                         id,
                         SubscribeMsg {
                             pkid,
                             topic: String::from_utf8_lossy(topic.as_ref()).to_string().into(),
                             msg: String::from_utf8_lossy(payload.as_ref()).to_string().into(),
+                            size: payload.len(),
                             qos: qos.into(),
+                            retain,
+                            correlation: correlation.into(),
+                            expiry,
+                            bridge: bridge.into(),
+                            script: script.into(),
+                            highlight: false,
                         },
-                    )) {
-                        error!("fail to send event!");
-                    };
+                    ));
                 }
                 _ => {}
             }
         }
         debug!("end");
+        observer.notify(SessionEvent::Closed(id, by_user));
     });
     Ok(client)
 }
 
-fn deal_conn_ack(ack_code: ConnectReturnCode, tx: Sender<AppEvent>, id: usize) {
+fn timeline(
+    observer: &dyn SessionObserver,
+    id: usize,
+    kind: TimelineKind,
+    detail: impl Into<String>,
+) {
+    observer.notify(SessionEvent::Timeline(id, TimelineEvent::new(kind, detail)));
+}
+
+fn deal_conn_ack(ack_code: ConnectReturnCode, observer: &dyn SessionObserver, id: usize) {
     match ack_code {
         ConnectReturnCode::Success => {
             debug!("connect success!");
-            if let Err(_) = tx.send(AppEvent::ConnectAckSuccess(id)) {
-                error!("fail to send event!");
-            }
+            observer.notify(SessionEvent::ConnAckSuccess(id));
         }
         error => {
-            if let Err(_) = tx.send(AppEvent::ConnectAckFail(id, format!("{:?}", error).into())) {
-                error!("fail to send event!");
-            }
+            observer.notify(SessionEvent::ConnAckFail(id, format!("{:?}", error)));
         }
     }
 }
@@ -115,6 +230,17 @@ pub async fn mqtt_subscribe(
     Ok(client.subscribe_and_tracing(input.topic, input.qos).await?)
 }
 
+pub async fn mqtt_subscribe_many(
+    index: usize,
+    input: MqttSubscribeManyInput,
+    clients: &HashMap<usize, AsyncClient>,
+) -> Result<u16> {
+    let Some(client) = clients.get(&index) else {
+        bail!("can't get mqtt client: {}", index);
+    };
+    Ok(client.subscribe_many_and_tracing(input.filters).await?)
+}
+
 pub async fn to_unsubscribe(
     index: usize,
     topic: String,
@@ -134,8 +260,53 @@ pub async fn mqtt_public(
     let Some(client) = clients.get(&index) else {
         bail!("can't get mqtt client: {}", index);
     };
+    if input.user_properties.is_empty() {
+        return Ok(client
+            .publish_and_tracing(input.topic, input.qos, input.retain, input.msg)
+            .await?);
+    }
+    let properties = PublishProperties {
+        payload_format_indicator: None,
+        message_expiry_interval: None,
+        topic_alias: None,
+        response_topic: None,
+        correlation_data: None,
+        user_properties: input.user_properties,
+        subscription_identifiers: Vec::new(),
+        content_type: None,
+    };
+    Ok(client
+        .publish_with_properties_and_tracing(
+            input.topic,
+            input.qos,
+            input.retain,
+            input.msg,
+            properties,
+        )
+        .await?)
+}
+
+/// publish with the response topic and correlation data of mqtt5
+pub async fn mqtt_request(
+    index: usize,
+    input: MqttRequestInput,
+    clients: &HashMap<usize, AsyncClient>,
+) -> Result<u16> {
+    let Some(client) = clients.get(&index) else {
+        bail!("can't get mqtt client: {}", index);
+    };
+    let properties = PublishProperties {
+        payload_format_indicator: None,
+        message_expiry_interval: None,
+        topic_alias: None,
+        response_topic: Some(input.response_topic),
+        correlation_data: Some(input.correlation.into()),
+        user_properties: Vec::new(),
+        subscription_identifiers: Vec::new(),
+        content_type: None,
+    };
     Ok(client
-        .publish_and_tracing(input.topic, input.qos, input.retain, input.msg)
+        .publish_with_properties_and_tracing(input.topic, input.qos, false, input.msg, properties)
         .await?)
 }
 
@@ -159,13 +330,13 @@ fn update_option(option: &mut MqttOptions, some: SomeMqttOption) {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SomeMqttOption {
     // seconds
-    keep_alive: u64,
-    clean_session: bool,
-    max_incoming_packet_size: usize,
-    max_outgoing_packet_size: usize,
-    inflight: u16,
+    pub(crate) keep_alive: u64,
+    pub(crate) clean_session: bool,
+    pub(crate) max_incoming_packet_size: usize,
+    pub(crate) max_outgoing_packet_size: usize,
+    pub(crate) inflight: u16,
     // seconds
-    conn_timeout: u64,
+    pub(crate) conn_timeout: u64,
 }
 
 impl Default for SomeMqttOption {
@@ -0,0 +1,76 @@
+//! The connections saved by MQTT Explorer in `settings.json`: a map of connections under
+//! `ConnectionManager_connections`, or the map itself.
+use crate::mqtt::SomeMqttOption;
+use crate::profile::{broker, host, subscribe_his, Profile};
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+const CONNECTIONS_KEY: &str = "ConnectionManager_connections";
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Connection {
+    name: String,
+    host: String,
+    port: u16,
+    #[serde(default)]
+    protocol: String,
+    #[serde(default)]
+    tls: bool,
+    #[serde(default)]
+    client_id: String,
+    #[serde(default)]
+    username: String,
+    #[serde(default)]
+    password: String,
+    #[serde(default)]
+    subscriptions: Vec<Subscription>,
+}
+
+/// only the topic in the old versions
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Subscription {
+    Topic(String),
+    WithQos {
+        topic: String,
+        #[serde(default)]
+        qos: u8,
+    },
+}
+
+pub fn parse(mut json: Value) -> Result<Vec<Profile>> {
+    if let Some(connections) = json.get_mut(CONNECTIONS_KEY) {
+        json = connections.take();
+    }
+    let connections: BTreeMap<String, Connection> = serde_json::from_value(json)?;
+    connections
+        .into_values()
+        .map(|x| {
+            let downgraded = x.tls || x.protocol.starts_with("ws");
+            Ok(Profile {
+                broker: broker(
+                    x.name,
+                    x.client_id,
+                    host(&x.host),
+                    x.port,
+                    (x.username, x.password),
+                    downgraded,
+                    SomeMqttOption::default(),
+                )?,
+                subscribe_his: x
+                    .subscriptions
+                    .into_iter()
+                    .map(|x| match x {
+                        Subscription::Topic(topic) => subscribe_his(topic, 0),
+                        Subscription::WithQos { topic, qos } => subscribe_his(topic, qos),
+                    })
+                    .collect(),
+                public_templates: Default::default(),
+                downgraded,
+            })
+        })
+        .collect()
+}
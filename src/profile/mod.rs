@@ -0,0 +1,363 @@
+//! Export and import the broker profiles: the broker with its subscribe history and templates.
+//! Besides the bundle exported by for-mqtt, the connections of MQTTX and MQTT Explorer can be
+//! imported, see `mqttx` and `explorer`.
+mod explorer;
+mod mqttx;
+
+use crate::data::common::{Id, PublicTemplate, QoS, SubscribeHis};
+use crate::data::db::BrokerDB;
+use crate::mqtt::SomeMqttOption;
+use anyhow::{bail, Result};
+use druid::im::Vector;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use uuid::Uuid;
+
+pub const BUNDLE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bundle {
+    pub version: u32,
+    pub profiles: Vec<Profile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Profile {
+    pub broker: BrokerDB,
+    #[serde(default)]
+    pub subscribe_his: Vector<SubscribeHis>,
+    #[serde(default)]
+    pub public_templates: Vector<PublicTemplate>,
+    /// tls or websocket of the other clients, which isn't supported: imported as plain tcp
+    /// without the credentials, so that they aren't sent in clear
+    #[serde(skip)]
+    pub downgraded: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Bundle,
+    Mqttx,
+    MqttExplorer,
+}
+
+/// what to do if the name of the imported broker is used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Conflict {
+    /// import as `name (2)`
+    Rename,
+    Skip,
+    /// overwrite the broker, its history and templates
+    Replace,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ImportSummary {
+    pub added: usize,
+    pub replaced: usize,
+    pub skipped: usize,
+    /// the names of the downgraded profiles, see `Profile::downgraded`
+    pub downgraded: Vec<String>,
+}
+
+impl Format {
+    pub fn parse(input: &str) -> Result<Self> {
+        Ok(match input {
+            "bundle" => Self::Bundle,
+            "mqttx" => Self::Mqttx,
+            "explorer" => Self::MqttExplorer,
+            _ => bail!("unknown format: {}", input),
+        })
+    }
+    /// guess by the shape: the bundle has `profiles`, MQTTX exports an array of connections,
+    /// MQTT Explorer saves a map of connections
+    pub fn detect(json: &Value) -> Result<Self> {
+        Ok(match json {
+            Value::Object(map) if map.contains_key("profiles") => Self::Bundle,
+            Value::Array(_) => Self::Mqttx,
+            Value::Object(_) => Self::MqttExplorer,
+            _ => bail!("unknown format of profiles"),
+        })
+    }
+}
+
+impl Conflict {
+    pub fn parse(input: &str) -> Result<Self> {
+        Ok(match input {
+            "rename" => Self::Rename,
+            "skip" => Self::Skip,
+            "replace" => Self::Replace,
+            _ => bail!("unknown conflict resolution: {}", input),
+        })
+    }
+}
+
+impl Display for ImportSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "added {}, replaced {}, skipped {}",
+            self.added, self.replaced, self.skipped
+        )?;
+        if !self.downgraded.is_empty() {
+            write!(
+                f,
+                ", tls/websocket isn't supported, imported as tcp without the credentials: {}",
+                self.downgraded.join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// the format is detected if not specified
+pub fn parse(content: &str, format: Option<Format>) -> Result<Vec<Profile>> {
+    let json: Value = serde_json::from_str(content)?;
+    let format = match format {
+        Some(format) => format,
+        None => Format::detect(&json)?,
+    };
+    match format {
+        Format::Bundle => {
+            let bundle: Bundle = serde_json::from_value(json)?;
+            if bundle.version > BUNDLE_VERSION {
+                bail!(
+                    "the bundle is exported by a newer version: {}",
+                    bundle.version
+                );
+            }
+            Ok(bundle.profiles)
+        }
+        Format::Mqttx => mqttx::parse(json),
+        Format::MqttExplorer => explorer::parse(json),
+    }
+}
+
+/// the name not in used: `name (2)`, `name (3)`...
+pub fn unique_name(name: &str, used: &HashSet<String>) -> String {
+    if !used.contains(name) {
+        return name.to_string();
+    }
+    (2..)
+        .map(|index| format!("{} ({})", name, index))
+        .find(|x| !used.contains(x))
+        .unwrap_or_default()
+}
+
+/// the broker of the other clients, without the credentials if `downgraded`
+fn broker(
+    name: String,
+    client_id: String,
+    addr: String,
+    port: u16,
+    (user_name, password): (String, String),
+    downgraded: bool,
+    option: SomeMqttOption,
+) -> Result<BrokerDB> {
+    let (user_name, password) = if downgraded {
+        warn!(
+            "tls/websocket of {} isn't supported, imported as tcp without the credentials",
+            name
+        );
+        Default::default()
+    } else {
+        (user_name, password)
+    };
+    Ok(BrokerDB {
+        id: 0,
+        uuid: Uuid::nil(),
+        client_id: client_id.into(),
+        name: name.into(),
+        addr: addr.into(),
+        port,
+        params: serde_json::to_string_pretty(&option)?.into(),
+        use_credentials: !user_name.is_empty(),
+        user_name: user_name.into(),
+        password: password.into(),
+        save_password: true,
+        folder: Default::default(),
+        tags: Default::default(),
+        color: Default::default(),
+        env: Default::default(),
+    })
+}
+
+fn subscribe_his(topic: String, qos: u8) -> SubscribeHis {
+    SubscribeHis {
+        id: Id::default(),
+        broker_id: 0,
+        selected: false,
+        topic: topic.into(),
+        qos: match qos {
+            1 => QoS::AtLeastOnce,
+            2 => QoS::ExactlyOnce,
+            _ => QoS::AtMostOnce,
+        },
+        ..SubscribeHis::default()
+    }
+}
+
+/// e.g. `mqtt://broker.emqx.io` of the old versions
+fn host(host: &str) -> String {
+    match host.split_once("://") {
+        Some((_, host)) => host.to_string(),
+        None => host.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::data::hierarchy::AppData;
+    use crate::profile::{
+        parse, unique_name, Bundle, Conflict, Format, ImportSummary, BUNDLE_VERSION,
+    };
+    use crate::util::db::ArcDb;
+    use serde_json::json;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_parse() {
+        let mqttx = json!([{
+            "id": "4a3b",
+            "name": "emqx",
+            "clientId": "mqttx_1",
+            "host": "broker.emqx.io",
+            "port": 1883,
+            "username": "admin",
+            "password": "public",
+            "clean": false,
+            "keepalive": 30,
+            "connectTimeout": 10,
+            "ssl": false,
+            "subscriptions": [{"topic": "a/#", "qos": 1}, {"topic": "b", "qos": 0}],
+        }]);
+        let profiles = parse(&mqttx.to_string(), None).unwrap();
+        assert_eq!(profiles.len(), 1);
+        let broker = &profiles[0].broker;
+        assert_eq!(broker.name.as_str(), "emqx");
+        assert_eq!(
+            (broker.addr.as_str(), broker.port),
+            ("broker.emqx.io", 1883)
+        );
+        assert!(broker.use_credentials);
+        assert!(broker.params.contains("\"keep_alive\": 30"));
+        assert!(broker.params.contains("\"clean_session\": false"));
+        assert_eq!(profiles[0].subscribe_his.len(), 2);
+        assert!(!profiles[0].downgraded);
+        let tls = json!([{"name": "tls", "host": "broker.emqx.io", "port": 8883, "ssl": true,
+                          "username": "admin", "password": "public"}]);
+        let profiles = parse(&tls.to_string(), None).unwrap();
+        assert!(profiles[0].downgraded);
+        assert!(!profiles[0].broker.use_credentials);
+        assert_eq!(profiles[0].broker.password.as_str(), "");
+
+        let explorer = json!({
+            "ConnectionManager_connections": {
+                "mqtt.eclipse.org": {
+                    "id": "mqtt.eclipse.org",
+                    "type": "mqtt",
+                    "name": "mqtt.eclipse.org",
+                    "host": "mqtt.eclipse.org",
+                    "port": 1883,
+                    "protocol": "mqtt",
+                    "tls": false,
+                    "certValidation": true,
+                    "clientId": "mqtt-explorer-1",
+                    "subscriptions": ["#", "$SYS/#"]
+                },
+                "local": {
+                    "id": "local",
+                    "name": "local",
+                    "host": "mqtt://127.0.0.1",
+                    "port": 1884,
+                    "username": "user",
+                    "subscriptions": [{"topic": "a", "qos": 2}]
+                }
+            }
+        });
+        let profiles = parse(&explorer.to_string(), None).unwrap();
+        assert_eq!(profiles.len(), 2);
+        let local = profiles
+            .iter()
+            .find(|x| x.broker.name.as_str() == "local")
+            .unwrap();
+        assert_eq!(
+            (local.broker.addr.as_str(), local.broker.port),
+            ("127.0.0.1", 1884)
+        );
+        assert_eq!(local.subscribe_his[0].topic.as_str(), "a");
+        assert!(local.broker.use_credentials);
+
+        let bundle = serde_json::to_string(&Bundle {
+            version: BUNDLE_VERSION,
+            profiles,
+        })
+        .unwrap();
+        assert_eq!(parse(&bundle, None).unwrap().len(), 2);
+        assert!(parse(&bundle, Some(Format::Mqttx)).is_err());
+        let newer = json!({"version": BUNDLE_VERSION + 1, "profiles": []});
+        assert!(parse(&newer.to_string(), None).is_err());
+        assert!(parse("1", None).is_err());
+    }
+
+    #[test]
+    fn test_unique_name() {
+        let used: HashSet<String> = ["emq", "emq (2)"].iter().map(|x| x.to_string()).collect();
+        assert_eq!(unique_name("local", &used), "local");
+        assert_eq!(unique_name("emq", &used), "emq (3)");
+        assert_eq!(Conflict::parse("skip").unwrap(), Conflict::Skip);
+        assert!(Conflict::parse("merge").is_err());
+    }
+
+    #[test]
+    fn test_import() {
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let mut data = AppData::load(ArcDb::init_temporary_db().unwrap(), tx).unwrap();
+        let mqttx = json!([
+            {"name": "emqx", "host": "broker.emqx.io", "port": 1883, "password": "public",
+             "subscriptions": [{"topic": "a/#", "qos": 1}]},
+            {"name": "local", "host": "127.0.0.1", "port": 1883},
+        ]);
+        let profiles = parse(&mqttx.to_string(), None).unwrap();
+        let summary = data
+            .import_profiles(profiles.clone(), Conflict::Rename)
+            .unwrap();
+        assert_eq!(summary.added, 2);
+        let id = data.brokers[0].id;
+        assert_eq!(data.subscribe_hises.get(&id).unwrap().len(), 1);
+
+        let summary = data
+            .import_profiles(profiles.clone(), Conflict::Skip)
+            .unwrap();
+        assert_eq!(summary.skipped, 2);
+        let summary = data
+            .import_profiles(profiles.clone(), Conflict::Rename)
+            .unwrap();
+        assert_eq!(summary.added, 2);
+        assert!(data.brokers.iter().any(|x| x.name.as_str() == "emqx (2)"));
+        let summary = data.import_profiles(profiles, Conflict::Replace).unwrap();
+        assert_eq!(
+            summary,
+            ImportSummary {
+                added: 0,
+                replaced: 2,
+                skipped: 0,
+                downgraded: vec![],
+            }
+        );
+        assert_eq!(data.brokers.len(), 4);
+        assert_eq!(data.brokers[0].id, id);
+
+        // the passwords are excluded by default
+        let bundle = data.export_profiles(&[id], false);
+        assert_eq!(bundle.profiles.len(), 1);
+        assert_eq!(bundle.profiles[0].broker.password.as_str(), "");
+        assert_eq!(bundle.profiles[0].subscribe_his.len(), 1);
+        let bundle = data.export_profiles(&[], true);
+        assert_eq!(bundle.profiles.len(), 4);
+        assert_eq!(bundle.profiles[0].broker.password.as_str(), "public");
+    }
+}
@@ -0,0 +1,69 @@
+//! The connections exported by MQTTX: an array of connections.
+use crate::mqtt::SomeMqttOption;
+use crate::profile::{broker, host, subscribe_his, Profile};
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::Value;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Connection {
+    name: String,
+    #[serde(default)]
+    client_id: String,
+    host: String,
+    port: u16,
+    #[serde(default)]
+    username: String,
+    #[serde(default)]
+    password: String,
+    #[serde(default)]
+    ssl: bool,
+    clean: Option<bool>,
+    keepalive: Option<u64>,
+    // seconds
+    connect_timeout: Option<u64>,
+    #[serde(default)]
+    subscriptions: Vec<Subscription>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Subscription {
+    topic: String,
+    #[serde(default)]
+    qos: u8,
+}
+
+pub fn parse(json: Value) -> Result<Vec<Profile>> {
+    let connections: Vec<Connection> = serde_json::from_value(json)?;
+    connections
+        .into_iter()
+        .map(|x| {
+            let default = SomeMqttOption::default();
+            let option = SomeMqttOption {
+                keep_alive: x.keepalive.unwrap_or(default.keep_alive),
+                clean_session: x.clean.unwrap_or(default.clean_session),
+                conn_timeout: x.connect_timeout.unwrap_or(default.conn_timeout),
+                ..default
+            };
+            Ok(Profile {
+                broker: broker(
+                    x.name,
+                    x.client_id,
+                    host(&x.host),
+                    x.port,
+                    (x.username, x.password),
+                    x.ssl,
+                    option,
+                )?,
+                subscribe_his: x
+                    .subscriptions
+                    .into_iter()
+                    .map(|x| subscribe_his(x.topic, x.qos))
+                    .collect(),
+                public_templates: Default::default(),
+                downgraded: x.ssl,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,269 @@
+//! Declarative rules evaluated on every received message of a connection:
+//! when the topic matches the filter and the payload satisfies the condition,
+//! the rule fires: count, highlight the message, notify and/or publish.
+//!
+//! The condition is empty (always), a jsonpath starting with `$` (the payload is json and the
+//! path selects something, e.g. `$.alarm`), or a regex of the payload.
+//! A filter selector of the root (`$[?...]`) is tried on the payload itself too, so
+//! `$[?@.temp > 30]` works on `{"temp": 31}`, while the other paths only select in the payload.
+//! The topic and msg to publish may contain `{{topic}}` and `{{payload}}` of the message,
+//! besides the placeholders of `util::template`.
+//! The notification is a desktop notification with the sound of the system.
+use crate::data::common::{Id, PublicInput, Rule, SubscribeMsg};
+use anyhow::{bail, Result};
+use log::error;
+use regex::Regex;
+use rumqttc::v5::mqttbytes::matches;
+use serde_json::Value;
+use serde_json_path::JsonPath;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// a rule firing on every message of a flood notifies once in the interval
+const NOTIFY_INTERVAL: Duration = Duration::from_secs(5);
+#[cfg(target_os = "macos")]
+const NOTIFY_SOUND: &str = "Ping";
+#[cfg(target_os = "windows")]
+const NOTIFY_SOUND: &str = "IM";
+/// the sound of freedesktop
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+const NOTIFY_SOUND: &str = "message-new-instant";
+
+#[derive(Debug)]
+enum Condition {
+    Always,
+    /// true if the path starts with a filter selector of the root, see `matches`
+    JsonPath(JsonPath, bool),
+    Regex(Regex),
+}
+
+#[derive(Debug)]
+pub struct RuleMatcher {
+    pub id: Id,
+    pub highlight: bool,
+    notify: bool,
+    last_notified: Option<Instant>,
+    filter: String,
+    condition: Condition,
+    publish: Option<PublicInput>,
+}
+
+impl RuleMatcher {
+    pub fn compile(rule: &Rule) -> Result<Self> {
+        if rule.filter.is_empty() {
+            bail!("the filter of the rule is empty");
+        }
+        let condition = rule.condition.trim();
+        let condition = if condition.is_empty() {
+            Condition::Always
+        } else if condition.starts_with('$') {
+            Condition::JsonPath(JsonPath::parse(condition)?, condition.starts_with("$[?"))
+        } else {
+            Condition::Regex(Regex::new(condition)?)
+        };
+        let publish = if rule.topic.is_empty() {
+            None
+        } else {
+            // the message published by the rule would fire the rule again
+            let looped = match rule.topic.find("{{") {
+                Some(start) => may_match(&rule.topic[..start], rule.filter.as_str()),
+                None => matches(rule.topic.as_str(), rule.filter.as_str()),
+            };
+            if looped {
+                bail!("the topic to publish may match the filter of the rule");
+            }
+            Some(PublicInput {
+                topic: rule.topic.clone(),
+                msg: rule.msg.clone(),
+                qos: rule.qos.clone(),
+                retain: rule.retain,
+                ..PublicInput::default()
+            })
+        };
+        Ok(Self {
+            id: rule.id,
+            highlight: rule.highlight,
+            notify: rule.notify,
+            last_notified: None,
+            filter: rule.filter.as_ref().clone(),
+            condition,
+            publish,
+        })
+    }
+
+    pub fn matches(&self, msg: &SubscribeMsg) -> bool {
+        if !matches(msg.topic.as_str(), self.filter.as_str()) {
+            return false;
+        }
+        match &self.condition {
+            Condition::Always => true,
+            Condition::JsonPath(path, root_filter) => {
+                let Ok(val) = serde_json::from_str::<Value>(msg.msg.as_str()) else {
+                    return false;
+                };
+                // the filter selector of the root only selects among the members of the payload
+                !path.query(&val).is_empty()
+                    || (*root_filter && !path.query(&Value::Array(vec![val])).is_empty())
+            }
+            Condition::Regex(regex) => regex.is_match(msg.msg.as_str()),
+        }
+    }
+
+    /// whether to notify the firing, see `NOTIFY_INTERVAL`
+    pub fn take_notify(&mut self, now: Instant) -> bool {
+        if !self.notify
+            || self
+                .last_notified
+                .map_or(false, |x| now.duration_since(x) < NOTIFY_INTERVAL)
+        {
+            return false;
+        }
+        self.last_notified = Some(now);
+        true
+    }
+
+    /// the input to publish when fired by the message
+    pub fn publish_input(&self, msg: &SubscribeMsg) -> Option<PublicInput> {
+        let input = self.publish.as_ref()?;
+        let fill = |x: &str| {
+            x.replace("{{topic}}", msg.topic.as_str())
+                .replace("{{payload}}", msg.msg.as_str())
+        };
+        Some(PublicInput {
+            topic: fill(input.topic.as_str()).into(),
+            msg: fill(input.msg.as_str()).into(),
+            ..input.clone()
+        })
+    }
+}
+
+/// whether a topic starting with the prefix may match the filter, the last level of the prefix
+/// may be completed by the placeholder
+fn may_match(prefix: &str, filter: &str) -> bool {
+    let filter: Vec<&str> = filter.split('/').collect();
+    let mut levels: Vec<&str> = prefix.split('/').collect();
+    let partial = levels.pop().unwrap_or_default();
+    for (index, level) in levels.iter().enumerate() {
+        match filter.get(index) {
+            Some(&"#") => return true,
+            Some(&"+") => {}
+            Some(x) if x == level => {}
+            _ => return false,
+        }
+    }
+    match filter.get(levels.len()) {
+        Some(&"#") | Some(&"+") => true,
+        Some(x) => x.starts_with(partial),
+        None => false,
+    }
+}
+
+/// in a thread, showing it may block, e.g. on d-bus
+pub fn notify(summary: &str, body: String) {
+    let summary = summary.to_string();
+    let rs = thread::Builder::new()
+        .name("notification".to_string())
+        .spawn(move || {
+            if let Err(e) = notify_rust::Notification::new()
+                .summary(&summary)
+                .body(&body)
+                .sound_name(NOTIFY_SOUND)
+                .show()
+            {
+                error!("{:?}", e);
+            }
+        });
+    if let Err(e) = rs {
+        error!("{:?}", e);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::data::common::{Rule, SubscribeMsg};
+    use crate::rule::{may_match, RuleMatcher, NOTIFY_INTERVAL};
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    #[test]
+    fn test_rule() {
+        let rule = Rule {
+            filter: Arc::new("sensor/+/temp".to_string()),
+            condition: Arc::new("$[?@.temp > 30]".to_string()),
+            topic: Arc::new("alarm/{{topic}}".to_string()),
+            msg: Arc::new("hot: {{payload}}".to_string()),
+            ..Rule::default()
+        };
+        let matcher = RuleMatcher::compile(&rule).unwrap();
+        assert!(matcher.matches(&SubscribeMsg::sample("sensor/a/temp", r#"{"temp": 31}"#)));
+        assert!(!matcher.matches(&SubscribeMsg::sample("sensor/a/temp", r#"{"temp": 20}"#)));
+        assert!(!matcher.matches(&SubscribeMsg::sample("sensor/a/temp", "31")));
+        assert!(!matcher.matches(&SubscribeMsg::sample(
+            "sensor/a/humidity",
+            r#"{"temp": 31}"#
+        )));
+        // an index only selects in an array
+        let rule = Rule {
+            filter: Arc::new("sensor/#".to_string()),
+            condition: Arc::new("$[0]".to_string()),
+            ..Rule::default()
+        };
+        let index = RuleMatcher::compile(&rule).unwrap();
+        assert!(!index.matches(&SubscribeMsg::sample("sensor/a/temp", r#"{"temp": 31}"#)));
+        assert!(index.matches(&SubscribeMsg::sample("sensor/a/temp", "[31]")));
+        let input = matcher
+            .publish_input(&SubscribeMsg::sample("sensor/a/temp", r#"{"temp":31}"#))
+            .unwrap();
+        assert_eq!(input.topic.as_str(), "alarm/sensor/a/temp");
+        assert_eq!(input.msg.as_str(), r#"hot: {"temp":31}"#);
+
+        let rule = Rule {
+            filter: Arc::new("log/#".to_string()),
+            condition: Arc::new("^ERROR".to_string()),
+            ..Rule::default()
+        };
+        let matcher = RuleMatcher::compile(&rule).unwrap();
+        assert!(matcher.matches(&SubscribeMsg::sample("log/a", "ERROR: disk full")));
+        assert!(!matcher.matches(&SubscribeMsg::sample("log/a", "INFO: ERROR")));
+        assert!(matcher
+            .publish_input(&SubscribeMsg::sample("log/a", "ERROR"))
+            .is_none());
+
+        let rule = Rule {
+            filter: Arc::new("a/#".to_string()),
+            topic: Arc::new("a/b".to_string()),
+            ..Rule::default()
+        };
+        assert!(RuleMatcher::compile(&rule).is_err());
+        let rule = Rule {
+            filter: Arc::new("a/#".to_string()),
+            condition: Arc::new("(".to_string()),
+            ..Rule::default()
+        };
+        assert!(RuleMatcher::compile(&rule).is_err());
+        // the templated topic published back to the filter
+        let rule = Rule {
+            filter: Arc::new("sensor/#".to_string()),
+            topic: Arc::new("{{topic}}/alarm".to_string()),
+            ..Rule::default()
+        };
+        assert!(RuleMatcher::compile(&rule).is_err());
+
+        assert!(may_match("sensor/", "sensor/+/temp"));
+        assert!(may_match("sensor/a/te", "sensor/+/temp"));
+        assert!(!may_match("sensor/a/hu", "sensor/+/temp"));
+        assert!(!may_match("alarm/", "sensor/+/temp"));
+        assert!(!may_match("a/b/c/", "a/b"));
+
+        let rule = Rule {
+            filter: Arc::new("log/#".to_string()),
+            notify: true,
+            ..Rule::default()
+        };
+        let mut matcher = RuleMatcher::compile(&rule).unwrap();
+        let now = Instant::now();
+        assert!(matcher.take_notify(now));
+        assert!(!matcher.take_notify(now));
+        assert!(matcher.take_notify(now + NOTIFY_INTERVAL));
+    }
+}
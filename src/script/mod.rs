@@ -0,0 +1,363 @@
+//! Rhai scripts attached to a connection. A script can define the callbacks:
+//!
+//! - `fn on_message(topic, payload, props)`: `props` is a map of `qos`, `retain`, `correlation`, `expiry`
+//! - `fn on_timer(name)`: the timer set by `set_timer(name, millis)` expires
+//!
+//! and call `publish(topic, payload[, qos, retain])`, `subscribe(filter[, qos])`,
+//! `set_timer(name, millis)` and `log(msg)`/`print(msg)`.
+//! The callbacks share the map `this`, which is kept between the calls.
+//!
+//! The engine has no access to the file system or the network, and every run is limited
+//! by the count of operations and `RUN_TIME_LIMIT`. The calls of the api are only collected
+//! as `ScriptAction`s, the logic worker performs them after the run.
+//!
+//! Every script runs on its own thread (`ScriptWorker`), fed by a bounded queue, so a slow
+//! script never blocks the logic worker: the messages are dropped while the queue is full.
+//! The publishes of a script carry the user property `SCRIPT_PROPERTY`, so the script never
+//! receives its own messages again, and at most `MAX_TIMERS` timers are pending.
+use crate::session::types::{QoS, SubscribeMsg};
+use anyhow::{anyhow, Result};
+use crossbeam_channel::{bounded, Sender};
+use rhai::{CallFnOptions, Dynamic, Engine, Map, Scope, AST};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const MAX_OPERATIONS: u64 = 1_000_000;
+const RUN_TIME_LIMIT: Duration = Duration::from_millis(200);
+/// the inputs waiting for a script
+const QUEUE_SIZE: usize = 256;
+/// the timers pending of a script, the later ones are refused
+pub const MAX_TIMERS: usize = 64;
+/// user property marking the publishes of the scripts, the value is unique for every script
+pub const SCRIPT_PROPERTY: &str = "for-mqtt-script";
+
+static NEXT_WORKER: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptAction {
+    Publish {
+        topic: String,
+        payload: String,
+        qos: QoS,
+        retain: bool,
+    },
+    Subscribe {
+        topic: String,
+        qos: QoS,
+    },
+    Timer {
+        name: String,
+        millis: u64,
+    },
+    Log(String),
+}
+
+#[derive(Debug)]
+pub enum ScriptInput {
+    Message(SubscribeMsg),
+    Timer(String),
+}
+
+/// the result of a run of the script
+#[derive(Debug)]
+pub struct ScriptOutput {
+    /// the worker of the run: the outputs of a replaced script are ignored
+    pub worker: u64,
+    /// the top-level statements are run, i.e. the script is loaded or failed to
+    pub loaded: bool,
+    pub actions: Vec<ScriptAction>,
+    pub error: Option<String>,
+}
+
+/// the thread running a script, which ends when the worker is dropped
+pub struct ScriptWorker {
+    id: u64,
+    tx: Sender<ScriptInput>,
+}
+
+impl ScriptWorker {
+    /// load the script on a new thread, the output of every run is passed to `report`
+    pub fn spawn(code: String, report: impl Fn(ScriptOutput) + Send + 'static) -> Self {
+        let id = NEXT_WORKER.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = bounded::<ScriptInput>(QUEUE_SIZE);
+        std::thread::spawn(move || {
+            let mut host = match ScriptHost::load(code.as_str()) {
+                Ok((host, actions)) => {
+                    report(ScriptOutput::new(id, true, actions, Ok(())));
+                    host
+                }
+                Err(e) => {
+                    report(ScriptOutput::new(id, true, Vec::new(), Err(e)));
+                    return;
+                }
+            };
+            for input in rx.iter() {
+                let (actions, rs) = match input {
+                    ScriptInput::Message(msg) => host.on_message(&msg),
+                    ScriptInput::Timer(name) => host.on_timer(name),
+                };
+                report(ScriptOutput::new(id, false, actions, rs));
+            }
+        });
+        Self { id, tx }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// false if the queue is full or the script is stopped by an error of loading
+    pub fn push(&self, input: ScriptInput) -> bool {
+        self.tx.try_send(input).is_ok()
+    }
+}
+
+impl ScriptOutput {
+    fn new(worker: u64, loaded: bool, actions: Vec<ScriptAction>, rs: Result<()>) -> Self {
+        Self {
+            worker,
+            loaded,
+            actions,
+            error: rs.err().map(|e| e.to_string()),
+        }
+    }
+}
+
+pub struct ScriptHost {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    state: Dynamic,
+    actions: Arc<Mutex<Vec<ScriptAction>>>,
+    started: Arc<Mutex<Instant>>,
+}
+
+impl ScriptHost {
+    /// compile the script and run its top-level statements, e.g. `subscribe("a/#");`
+    pub fn load(code: &str) -> Result<(Self, Vec<ScriptAction>)> {
+        let actions: Arc<Mutex<Vec<ScriptAction>>> = Default::default();
+        let started = Arc::new(Mutex::new(Instant::now()));
+        let engine = init_engine(actions.clone(), started.clone());
+        let ast = engine.compile(code).map_err(|e| anyhow!("{}", e))?;
+        let mut host = Self {
+            engine,
+            ast,
+            scope: Scope::new(),
+            state: Map::new().into(),
+            actions,
+            started,
+        };
+        *host.started.lock().unwrap() = Instant::now();
+        let rs = host
+            .engine
+            .run_ast_with_scope(&mut host.scope, &host.ast)
+            .map_err(|e| anyhow!("{}", e));
+        let actions = host.take_actions();
+        rs?;
+        Ok((host, actions))
+    }
+
+    pub fn on_message(&mut self, msg: &SubscribeMsg) -> (Vec<ScriptAction>, Result<()>) {
+        let mut props = Map::new();
+        props.insert("qos".into(), (msg.qos.clone() as u8 as i64).into());
+        props.insert("retain".into(), msg.retain.into());
+        props.insert("correlation".into(), msg.correlation.as_str().into());
+        props.insert("expiry".into(), (msg.expiry as i64).into());
+        let args = (msg.topic.as_ref().clone(), msg.msg.as_ref().clone(), props);
+        self.call("on_message", 3, args)
+    }
+
+    pub fn on_timer(&mut self, name: String) -> (Vec<ScriptAction>, Result<()>) {
+        self.call("on_timer", 1, (name,))
+    }
+
+    /// the callback is optional: nothing happens if the script doesn't define it
+    fn call(
+        &mut self,
+        name: &str,
+        params: usize,
+        args: impl rhai::FuncArgs,
+    ) -> (Vec<ScriptAction>, Result<()>) {
+        if !self
+            .ast
+            .iter_functions()
+            .any(|x| x.name == name && x.params.len() == params)
+        {
+            return (Vec::new(), Ok(()));
+        }
+        *self.started.lock().unwrap() = Instant::now();
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .rewind_scope(true)
+            .bind_this_ptr(&mut self.state);
+        let rs = self
+            .engine
+            .call_fn_with_options::<Dynamic>(options, &mut self.scope, &self.ast, name, args)
+            .map(|_| ())
+            .map_err(|e| anyhow!("{}: {}", name, e));
+        (self.take_actions(), rs)
+    }
+
+    fn take_actions(&self) -> Vec<ScriptAction> {
+        std::mem::take(&mut *self.actions.lock().unwrap())
+    }
+}
+
+fn init_engine(actions: Arc<Mutex<Vec<ScriptAction>>>, started: Arc<Mutex<Instant>>) -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .set_max_operations(MAX_OPERATIONS)
+        .set_max_call_levels(32)
+        .set_max_expr_depths(64, 32)
+        .set_max_string_size(1024 * 1024)
+        .set_max_array_size(10_000)
+        .set_max_map_size(10_000)
+        .on_progress(move |count| {
+            if count % 1000 == 0 && started.lock().unwrap().elapsed() > RUN_TIME_LIMIT {
+                Some("run out of time".into())
+            } else {
+                None
+            }
+        });
+
+    let tmp = actions.clone();
+    engine.on_print(move |x| push(&tmp, ScriptAction::Log(x.to_string())));
+    let tmp = actions.clone();
+    engine.on_debug(move |x, _, pos| push(&tmp, ScriptAction::Log(format!("{} {}", pos, x))));
+    let tmp = actions.clone();
+    engine.register_fn("log", move |x: &str| {
+        push(&tmp, ScriptAction::Log(x.to_string()))
+    });
+    let tmp = actions.clone();
+    engine.register_fn("publish", move |topic: &str, payload: &str| {
+        push(&tmp, ScriptAction::publish(topic, payload, 0, false))
+    });
+    let tmp = actions.clone();
+    engine.register_fn(
+        "publish",
+        move |topic: &str, payload: &str, qos: i64, retain: bool| {
+            push(&tmp, ScriptAction::publish(topic, payload, qos, retain))
+        },
+    );
+    let tmp = actions.clone();
+    engine.register_fn("subscribe", move |topic: &str| {
+        push(&tmp, ScriptAction::subscribe(topic, 0))
+    });
+    let tmp = actions.clone();
+    engine.register_fn("subscribe", move |topic: &str, qos: i64| {
+        push(&tmp, ScriptAction::subscribe(topic, qos))
+    });
+    engine.register_fn("set_timer", move |name: &str, millis: i64| {
+        push(
+            &actions,
+            ScriptAction::Timer {
+                name: name.to_string(),
+                millis: millis.max(0) as u64,
+            },
+        )
+    });
+    engine
+}
+
+fn push(actions: &Mutex<Vec<ScriptAction>>, action: ScriptAction) {
+    actions.lock().unwrap().push(action);
+}
+
+impl ScriptAction {
+    fn publish(topic: &str, payload: &str, qos: i64, retain: bool) -> Self {
+        Self::Publish {
+            topic: topic.to_string(),
+            payload: payload.to_string(),
+            qos: to_qos(qos),
+            retain,
+        }
+    }
+    fn subscribe(topic: &str, qos: i64) -> Self {
+        Self::Subscribe {
+            topic: topic.to_string(),
+            qos: to_qos(qos),
+        }
+    }
+}
+
+fn to_qos(qos: i64) -> QoS {
+    match qos {
+        1 => QoS::AtLeastOnce,
+        2 => QoS::ExactlyOnce,
+        _ => QoS::AtMostOnce,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::script::{ScriptAction, ScriptHost, ScriptInput, ScriptOutput, ScriptWorker};
+    use crate::session::types::{QoS, SubscribeMsg};
+    use std::time::Duration;
+
+    #[test]
+    fn test_script() {
+        let code = r#"
+            subscribe("cmd/#", 1);
+            fn on_message(topic, payload, props) {
+                this.count = (this.count ?? 0) + 1;
+                if topic == "cmd/ping" {
+                    publish("cmd/pong", payload, props.qos, false);
+                    set_timer("later", 100);
+                }
+                log(`${this.count}`);
+            }
+        "#;
+        let (mut host, actions) = ScriptHost::load(code).unwrap();
+        assert_eq!(actions, vec![ScriptAction::subscribe("cmd/#", 1)]);
+        let msg = SubscribeMsg {
+            qos: QoS::AtLeastOnce,
+            ..SubscribeMsg::sample("cmd/ping", "hello")
+        };
+        let (actions, rs) = host.on_message(&msg);
+        rs.unwrap();
+        assert_eq!(
+            actions,
+            vec![
+                ScriptAction::publish("cmd/pong", "hello", 1, false),
+                ScriptAction::Timer {
+                    name: "later".to_string(),
+                    millis: 100
+                },
+                ScriptAction::Log("1".to_string()),
+            ]
+        );
+        let (actions, _) = host.on_message(&msg);
+        assert_eq!(actions.last(), Some(&ScriptAction::Log("2".to_string())));
+        // on_timer isn't defined
+        let (actions, rs) = host.on_timer("later".to_string());
+        assert!(actions.is_empty() && rs.is_ok());
+
+        let (mut host, _) = ScriptHost::load("fn on_timer(name) { loop {} }").unwrap();
+        assert!(host.on_timer("a".to_string()).1.is_err());
+        assert!(ScriptHost::load("fn on_message(").is_err());
+    }
+
+    #[test]
+    fn test_worker() {
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let report = move |output: ScriptOutput| tx.send(output).unwrap();
+        let worker = ScriptWorker::spawn("fn on_timer(name) { log(name); }".to_string(), report);
+        let output = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(output.loaded && output.error.is_none());
+        assert_eq!(output.worker, worker.id());
+        assert!(worker.push(ScriptInput::Timer("a".to_string())));
+        let output = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(!output.loaded);
+        assert_eq!(output.actions, vec![ScriptAction::Log("a".to_string())]);
+
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let report = move |output: ScriptOutput| tx.send(output).unwrap();
+        let worker = ScriptWorker::spawn("fn on_timer(".to_string(), report);
+        let output = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(output.loaded && output.error.is_some());
+        // the thread is ended
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(!worker.push(ScriptInput::Timer("a".to_string())));
+    }
+}
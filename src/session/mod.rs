@@ -0,0 +1,553 @@
+//! The core of the connections without any ui: `Session` owns the mqtt clients, the state of
+//! the subscribes and acks, the bridges and the db,
+//! and the packets from the brokers are notified to a `SessionObserver`.
+//! The druid frontend is one of the observers, see `logic`: it depends on the session,
+//! never the reverse.
+pub mod types;
+
+use crate::bridge::{BridgeRoute, Bridges, Routed};
+use crate::mqtt::data::{
+    MqttPublicInput, MqttRequestInput, MqttSubscribeInput, MqttSubscribeManyInput,
+};
+use crate::mqtt::{
+    init_connect, mqtt_public, mqtt_request, mqtt_subscribe, mqtt_subscribe_many, to_unsubscribe,
+};
+use crate::session::types::{
+    ConnectOptions, SavedBroker, SavedEnvironment, SavedSubscribe, SubscribeMsg, TimelineEvent,
+};
+use crate::util::db::{ArcDb, DbKey};
+use crate::util::env::parse_vars;
+use crate::util::hint::BRIDGE_NOT_CONNECTED;
+use crate::util::template::TemplateContext;
+use anyhow::{bail, Result};
+use log::{debug, error};
+use rumqttc::v5::mqttbytes::{matches, Filter, PubAck, QoS, RetainForwardRule, SubAck};
+use rumqttc::v5::AsyncClient;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+#[derive(Debug, Clone)]
+pub enum SessionEvent {
+    ConnAckSuccess(usize),
+    ConnAckFail(usize, String),
+    PubAck(usize, PubAck),
+    /// the pkid of the qos 2 publish completed
+    PubComp(usize, u16),
+    SubAck(usize, SubAck),
+    UnSubAck(usize, u16),
+    Publish(usize, SubscribeMsg),
+    Timeline(usize, TimelineEvent),
+    /// the event loop ended, true if disconnected by the client
+    Closed(usize, bool),
+}
+
+pub trait SessionObserver: Send + Sync {
+    fn notify(&self, event: SessionEvent);
+}
+
+/// notify the event to every observer, e.g. the druid frontend and the http api
+pub struct Observers(pub Vec<Arc<dyn SessionObserver>>);
+
+impl SessionObserver for Observers {
+    fn notify(&self, event: SessionEvent) {
+        for observer in self.0.iter() {
+            observer.notify(event.clone());
+        }
+    }
+}
+
+pub struct Session {
+    clients: HashMap<usize, AsyncClient>,
+    template_ctxs: HashMap<usize, TemplateContext>,
+    // the filters subscribed of every connection, with the count of the subscribers,
+    // e.g. the user and the bridges
+    filters: HashMap<usize, HashMap<String, usize>>,
+    // (connection, pkid) of the subscribes made by the tools, e.g. the probe and the bridges,
+    // not shown as the user's
+    internal_acks: HashSet<(usize, u16)>,
+    // response topics subscribed of every connection
+    reply_topics: HashMap<usize, HashSet<String>>,
+    bridges: Bridges,
+    observer: Arc<dyn SessionObserver>,
+    /// shared with the frontend, see `ArcDb`
+    db: ArcDb,
+}
+
+impl Session {
+    pub fn new(db: ArcDb, observer: Arc<dyn SessionObserver>) -> Self {
+        Self {
+            clients: HashMap::new(),
+            template_ctxs: HashMap::new(),
+            filters: HashMap::new(),
+            internal_acks: HashSet::new(),
+            reply_topics: HashMap::new(),
+            bridges: Bridges::default(),
+            observer,
+            db,
+        }
+    }
+
+    pub fn db(&self) -> &ArcDb {
+        &self.db
+    }
+
+    /// the brokers saved by the frontend, for the tools without it, e.g. the cli:
+    /// numbered again from 0, see `ArcDb::load_brokers`
+    pub fn saved_brokers(&self) -> Result<Vec<SavedBroker>> {
+        Ok(self
+            .db
+            .load_brokers::<SavedBroker>()?
+            .into_iter()
+            .map(|(id, uuid, broker)| SavedBroker { id, uuid, ..broker })
+            .collect())
+    }
+
+    /// the broker to connect: the variables of its environment substituted, see `util::env`
+    pub fn resolved_broker(&self, broker: &SavedBroker) -> Result<SavedBroker> {
+        let mut broker = broker.clone();
+        if broker.env.is_empty() {
+            return Ok(broker);
+        }
+        let environments: Vec<SavedEnvironment> = self.db.read_environments()?;
+        let Some(env) = environments.iter().find(|x| x.name == broker.env) else {
+            bail!("can't find the environment: {}", broker.env);
+        };
+        broker.resolve(&parse_vars(&env.vars)?);
+        Ok(broker)
+    }
+
+    /// the filters of the subscribe history of the saved broker
+    pub fn subscribe_history(&self, broker: &SavedBroker) -> Result<Vec<MqttSubscribeInput>> {
+        let hises: Vec<SavedSubscribe> = self.db.read_of(DbKey::subscribe_his_key(broker.uuid))?;
+        Ok(hises
+            .into_iter()
+            .map(|x| MqttSubscribeInput {
+                topic: x.topic,
+                qos: x.qos.into(),
+            })
+            .collect())
+    }
+
+    /// the saved password is read from the db if the credentials come without one
+    pub async fn connect(&mut self, broker: &ConnectOptions) -> Result<()> {
+        let mut broker = broker.clone();
+        if let Some((_, password)) = broker.credentials.as_mut() {
+            if password.is_empty() {
+                *password = self.db.read_password(broker.id).unwrap_or_default();
+            }
+        }
+        let client = init_connect(&broker, self.observer.clone()).await?;
+        self.clients.insert(broker.id, client);
+        self.template_ctxs
+            .insert(broker.id, TemplateContext::new(broker.client_id.clone()));
+        Ok(())
+    }
+
+    pub fn client(&self, id: usize) -> Option<&AsyncClient> {
+        self.clients.get(&id)
+    }
+
+    pub fn is_connected(&self, id: usize) -> bool {
+        self.clients.contains_key(&id)
+    }
+
+    pub async fn disconnect(&mut self, id: usize) -> Result<()> {
+        self.template_ctxs.remove(&id);
+        self.filters.remove(&id);
+        let Some(client) = self.clients.remove(&id) else {
+            bail!("can't find client: {}", id);
+        };
+        client.disconnect().await?;
+        Ok(())
+    }
+
+    /// the connection is refused: drop the client without sending DISCONNECT
+    pub fn remove_client(&mut self, id: usize) {
+        self.template_ctxs.remove(&id);
+        self.filters.remove(&id);
+        self.clients.remove(&id);
+    }
+
+    /// forget the state of the connection before it's closed: the sources of the bridges
+    /// stopped are returned, see `stop_bridge`
+    pub async fn close(&mut self, id: usize) -> Vec<usize> {
+        self.internal_acks.retain(|(x, _)| *x != id);
+        self.reply_topics.remove(&id);
+        let sources = self.bridges.bridges_of(id);
+        for source in sources.iter() {
+            self.stop_bridge(*source).await;
+        }
+        sources
+    }
+
+    pub async fn subscribe(&mut self, id: usize, input: MqttSubscribeInput) -> Result<u16> {
+        let topic = input.topic.clone();
+        let pkid = mqtt_subscribe(id, input, &self.clients).await?;
+        self.add_filters(id, vec![topic]);
+        Ok(pkid)
+    }
+
+    pub async fn subscribe_many(
+        &mut self,
+        id: usize,
+        input: MqttSubscribeManyInput,
+    ) -> Result<u16> {
+        let topics: Vec<String> = input.filters.iter().map(|x| x.path.clone()).collect();
+        let pkid = mqtt_subscribe_many(id, input, &self.clients).await?;
+        self.add_filters(id, topics);
+        Ok(pkid)
+    }
+
+    /// subscribe only the filters not subscribed yet, so the options of the others, e.g. the qos,
+    /// aren't replaced. None if every filter is subscribed already
+    pub async fn subscribe_shared(
+        &mut self,
+        id: usize,
+        mut input: MqttSubscribeManyInput,
+    ) -> Result<Option<u16>> {
+        let topics: Vec<String> = input.filters.iter().map(|x| x.path.clone()).collect();
+        if let Some(filters) = self.filters.get(&id) {
+            input
+                .filters
+                .retain(|x| !filters.contains_key(x.path.as_str()));
+        }
+        let pkid = if input.filters.is_empty() {
+            None
+        } else {
+            Some(mqtt_subscribe_many(id, input, &self.clients).await?)
+        };
+        self.add_filters(id, topics);
+        Ok(pkid)
+    }
+
+    /// the filter is unsubscribed once no one subscribes it: None if the others still do
+    pub async fn unsubscribe(&mut self, id: usize, topic: String) -> Result<Option<u16>> {
+        let filters = self.filters.entry(id).or_default();
+        match filters.get(topic.as_str()) {
+            Some(&count) if count > 1 => {
+                filters.insert(topic, count - 1);
+                return Ok(None);
+            }
+            _ => {
+                filters.remove(topic.as_str());
+            }
+        }
+        Ok(Some(to_unsubscribe(id, topic, &self.clients).await?))
+    }
+
+    /// the count of the subscribers of the filters matching the topic, the tools included
+    pub fn subscribers(&self, id: usize, topic: &str) -> usize {
+        self.filters
+            .get(&id)
+            .into_iter()
+            .flatten()
+            .filter(|(x, _)| matches(topic, x.as_str()))
+            .map(|(_, count)| *count)
+            .sum()
+    }
+
+    /// subscribe for the tools, e.g. the probe: the SUBACK isn't the user's, see `take_internal_ack`
+    pub async fn subscribe_internal(
+        &mut self,
+        id: usize,
+        input: MqttSubscribeManyInput,
+    ) -> Result<()> {
+        let pkid = self.subscribe_many(id, input).await?;
+        self.internal_acks.insert((id, pkid));
+        Ok(())
+    }
+
+    pub async fn unsubscribe_internal(&mut self, id: usize, topic: String) -> Result<()> {
+        if let Some(pkid) = self.unsubscribe(id, topic).await? {
+            self.internal_acks.insert((id, pkid));
+        }
+        Ok(())
+    }
+
+    /// true if the ack is of a subscribe of the tools, which is forgotten then
+    pub fn take_internal_ack(&mut self, id: usize, pkid: u16) -> bool {
+        self.internal_acks.remove(&(id, pkid))
+    }
+
+    /// the response topic is subscribed once per connection, as the tools do,
+    /// so it isn't shown in the user's subscribes
+    pub async fn subscribe_reply(&mut self, id: usize, input: MqttSubscribeInput) -> Result<()> {
+        if self
+            .reply_topics
+            .get(&id)
+            .map_or(false, |x| x.contains(&input.topic))
+        {
+            return Ok(());
+        }
+        let topic = input.topic.clone();
+        let input = MqttSubscribeManyInput {
+            filters: vec![Filter {
+                path: input.topic,
+                qos: input.qos,
+                nolocal: false,
+                preserve_retain: false,
+                retain_forward_rule: RetainForwardRule::OnEverySubscribe,
+            }],
+        };
+        self.subscribe_internal(id, input).await?;
+        self.reply_topics.entry(id).or_default().insert(topic);
+        Ok(())
+    }
+
+    fn add_filters(&mut self, id: usize, topics: Vec<String>) {
+        let filters = self.filters.entry(id).or_default();
+        for topic in topics {
+            *filters.entry(topic).or_default() += 1;
+        }
+    }
+
+    pub async fn publish(&self, id: usize, input: MqttPublicInput) -> Result<u16> {
+        mqtt_public(id, input, &self.clients).await
+    }
+
+    /// forward the message by the bridges of the connection,
+    /// the routes failed to publish are left out
+    pub async fn received(&mut self, id: usize, msg: &SubscribeMsg) -> Vec<Routed> {
+        let mut routed = Vec::new();
+        for x in self.bridges.route(id, msg) {
+            if let Routed::Forward { target, input, .. } = &x {
+                if let Err(e) = self.publish(*target, input.clone()).await {
+                    error!("{:?}", e);
+                    continue;
+                }
+            }
+            routed.push(x);
+        }
+        routed
+    }
+
+    /// replace the bridges of the source by the routes, which are all from the source
+    pub async fn start_bridge(&mut self, source: usize, routes: Vec<BridgeRoute>) -> Result<()> {
+        self.stop_bridge(source).await;
+        if routes
+            .iter()
+            .any(|x| !self.is_connected(x.source) || !self.is_connected(x.target))
+        {
+            bail!("{}", BRIDGE_NOT_CONNECTED);
+        }
+        for route in routes {
+            if let Err(e) = self.subscribe_bridge(&route).await {
+                self.stop_bridge(source).await;
+                return Err(e);
+            }
+            self.bridges.insert(route);
+        }
+        Ok(())
+    }
+
+    pub async fn stop_bridge(&mut self, source: usize) {
+        for route in self.bridges.remove(source) {
+            self.unsubscribe_bridge(&route).await;
+        }
+    }
+
+    /// shared with the subscriptions of the user and the other bridges, see `subscribe_shared`
+    async fn subscribe_bridge(&mut self, route: &BridgeRoute) -> Result<()> {
+        let input = |filters: &[String]| MqttSubscribeManyInput {
+            filters: filters
+                .iter()
+                .map(|x| Filter {
+                    path: x.clone(),
+                    qos: QoS::ExactlyOnce,
+                    nolocal: true,
+                    preserve_retain: true,
+                    retain_forward_rule: RetainForwardRule::OnEverySubscribe,
+                })
+                .collect(),
+        };
+        let mut filters = vec![(route.source, route.filters.clone())];
+        if route.both {
+            filters.push((route.target, route.target_filters()));
+        }
+        // rolled back if failed, so the counts of the filters of the others are kept
+        let mut subscribed: Vec<(usize, String)> = Vec::new();
+        for (id, filters) in filters {
+            match self.subscribe_shared(id, input(&filters)).await {
+                Ok(pkid) => {
+                    self.internal_acks.extend(pkid.map(|x| (id, x)));
+                    subscribed.extend(filters.into_iter().map(|x| (id, x)));
+                }
+                Err(e) => {
+                    self.unsubscribe_filters(subscribed).await;
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn unsubscribe_bridge(&mut self, route: &BridgeRoute) {
+        let mut filters: Vec<(usize, String)> = route
+            .filters
+            .iter()
+            .map(|x| (route.source, x.clone()))
+            .collect();
+        if route.both {
+            filters.extend(
+                route
+                    .target_filters()
+                    .into_iter()
+                    .map(|x| (route.target, x)),
+            );
+        }
+        self.unsubscribe_filters(filters).await;
+    }
+
+    async fn unsubscribe_filters(&mut self, filters: Vec<(usize, String)>) {
+        for (id, filter) in filters {
+            if !self.is_connected(id) {
+                continue;
+            }
+            if let Err(e) = self.unsubscribe_internal(id, filter).await {
+                debug!("{:?}", e);
+            }
+        }
+    }
+
+    /// expand the placeholders of the input before publishing if `expand`, see `util::template`
+    pub async fn publish_template(
+        &mut self,
+        id: usize,
+        input: MqttPublicInput,
+        expand: bool,
+    ) -> Result<(MqttPublicInput, u16)> {
+        let input = if expand {
+            self.template_ctxs
+                .entry(id)
+                .or_default()
+                .expand_input(input)?
+        } else {
+            input
+        };
+        let pkid = self.publish(id, input.clone()).await?;
+        Ok((input, pkid))
+    }
+
+    pub async fn request(&self, id: usize, input: MqttRequestInput) -> Result<u16> {
+        mqtt_request(id, input, &self.clients).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::bridge::BridgeRoute;
+    use crate::mqtt::data::{MqttPublicInput, MqttSubscribeInput};
+    use crate::session::types::{QoS, SubscribeMsg};
+    use crate::session::{Observers, Session, SessionEvent, SessionObserver};
+    use crate::util::db::ArcDb;
+    use rumqttc::v5::mqttbytes::QoS::AtMostOnce;
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+    use uuid::Uuid;
+
+    #[derive(Default)]
+    struct Events(Mutex<Vec<SessionEvent>>);
+
+    impl SessionObserver for Events {
+        fn notify(&self, event: SessionEvent) {
+            self.0.lock().unwrap().push(event);
+        }
+    }
+
+    #[test]
+    fn test_observers() {
+        let (first, second) = (Arc::new(Events::default()), Arc::new(Events::default()));
+        let observers = Observers(vec![first.clone(), second.clone()]);
+        observers.notify(SessionEvent::Publish(
+            1,
+            SubscribeMsg::sample("a/b", "hello"),
+        ));
+        for events in [first, second] {
+            match events.0.lock().unwrap().as_slice() {
+                [SessionEvent::Publish(1, msg)] => assert_eq!(msg.msg.as_str(), "hello"),
+                events => panic!("{:?}", events),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_session_without_connection() {
+        let db = ArcDb::init_temporary_db().unwrap();
+        let mut session = Session::new(db, Arc::new(Events::default()));
+        assert!(!session.is_connected(1));
+        assert!(session.disconnect(1).await.is_err());
+        let input = MqttSubscribeInput {
+            topic: "a/#".to_string(),
+            qos: AtMostOnce,
+        };
+        assert!(session.subscribe(1, input).await.is_err());
+        assert_eq!(session.subscribers(1, "a/b"), 0);
+        let input = MqttPublicInput {
+            topic: "a/{{seq}}".to_string(),
+            msg: String::new(),
+            qos: AtMostOnce,
+            retain: false,
+            user_properties: Vec::new(),
+        };
+        assert!(session.publish_template(1, input, true).await.is_err());
+        let route = BridgeRoute {
+            source: 1,
+            target: 2,
+            filters: vec!["a/#".to_string()],
+            strip_prefix: String::new(),
+            add_prefix: String::new(),
+            max_qos: QoS::AtMostOnce,
+            both: false,
+        };
+        assert!(session.start_bridge(1, vec![route]).await.is_err());
+        assert!(session.close(1).await.is_empty());
+    }
+
+    #[test]
+    fn test_subscribers() {
+        let db = ArcDb::init_temporary_db().unwrap();
+        let mut session = Session::new(db, Arc::new(Events::default()));
+        // the user and a retain scan on the same filter, a bridge on another one
+        session.add_filters(1, vec!["a/#".to_string(), "a/#".to_string()]);
+        session.add_filters(1, vec!["a/+".to_string()]);
+        assert_eq!(session.subscribers(1, "a/b"), 3);
+        assert_eq!(session.subscribers(1, "a/b/c"), 2);
+        assert_eq!(session.subscribers(1, "b"), 0);
+        assert_eq!(session.subscribers(2, "a/b"), 0);
+    }
+
+    #[test]
+    fn test_saved_brokers() {
+        let db = ArcDb::init_temporary_db().unwrap();
+        let session = Session::new(db.clone(), Arc::new(Events::default()));
+        let uuid = Uuid::new_v4();
+        let broker = json!({
+            "uuid": uuid,
+            "name": "staging",
+            "client_id": "for-mqtt-${TENANT}",
+            "addr": "${HOST}",
+            "port": 1883,
+            "params": "{}",
+            "use_credentials": true,
+            "user_name": "user",
+            "password": "secret",
+            "folder": "lab",
+            "env": "stage",
+        });
+        db.save_broker(db.next_index(), &broker).unwrap();
+        let hises = json!([{"topic": "a/#", "qos": "AtLeastOnce", "pinned": true}]);
+        db.update_subscribe_his(0, &hises).unwrap();
+        let envs = json!([{"name": "stage", "vars": "HOST=stage.example.com\nTENANT=t1"}]);
+        db.update_environments(&envs).unwrap();
+
+        let brokers = session.saved_brokers().unwrap();
+        assert_eq!(brokers.len(), 1);
+        assert_eq!((brokers[0].id, brokers[0].uuid), (0, uuid));
+        assert_eq!(brokers[0].password, "secret");
+        let resolved = session.resolved_broker(&brokers[0]).unwrap();
+        assert_eq!(resolved.addr, "stage.example.com");
+        assert_eq!(resolved.client_id, "for-mqtt-t1");
+        let history = session.subscribe_history(&brokers[0]).unwrap();
+        assert_eq!(history[0].topic, "a/#");
+    }
+}
@@ -0,0 +1,239 @@
+//! The types of the session core. They don't depend on the ui: the druid frontend implements
+//! `Data` for the ones it displays, see `data::common`.
+use crate::util::env::substitute;
+use crate::util::{format_millis, now_millis};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[repr(u8)]
+pub enum QoS {
+    AtMostOnce = 0,
+    AtLeastOnce = 1,
+    ExactlyOnce = 2,
+}
+
+/// a message received from the broker
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SubscribeMsg {
+    pub pkid: u16,
+    pub topic: Arc<String>,
+    pub msg: Arc<String>,
+    /// bytes of the payload, `msg` is converted lossily
+    pub size: usize,
+    pub qos: QoS,
+    pub retain: bool,
+    /// correlation data of mqtt5, empty if none
+    pub correlation: Arc<String>,
+    /// remaining seconds of message expiry interval, 0 if none
+    pub expiry: u32,
+    /// marker of the bridge publishing it, see `bridge`, empty if none
+    pub bridge: Arc<String>,
+    /// marker of the script publishing it, see `script`, empty if none
+    pub script: Arc<String>,
+    /// fired a rule to highlight, see `rule`
+    pub highlight: bool,
+}
+
+/// an entry of the timeline of a connection, see `mqtt::init_connect`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TimelineEvent {
+    /// millis
+    pub(crate) time: u64,
+    pub(crate) kind: TimelineKind,
+    pub(crate) detail: Arc<String>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TimelineKind {
+    Connect,
+    ConnAck,
+    PingReq,
+    /// with the round trip
+    PingResp,
+    Disconnect,
+    /// from the event loop, the connection is closed
+    Error,
+    Reconnect,
+}
+
+/// what `mqtt::init_connect` needs of a broker
+#[derive(Clone, Debug, Default)]
+pub struct ConnectOptions {
+    /// the index in the session
+    pub id: usize,
+    pub client_id: String,
+    pub addr: String,
+    pub port: u16,
+    /// json of `mqtt::SomeMqttOption`
+    pub params: String,
+    /// user name and password
+    pub credentials: Option<(String, String)>,
+}
+
+/// a broker saved by the frontend, read by the tools without it, e.g. the cli:
+/// the other fields of the frontend are ignored
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct SavedBroker {
+    /// the index in the session, see `ArcDb::load_brokers`
+    #[serde(skip)]
+    pub id: usize,
+    #[serde(skip)]
+    pub uuid: Uuid,
+    pub name: String,
+    pub client_id: String,
+    pub addr: String,
+    pub port: u16,
+    /// json of `mqtt::SomeMqttOption`
+    pub params: String,
+    pub use_credentials: bool,
+    pub user_name: String,
+    /// decrypted if the vault is unlocked
+    pub password: String,
+    #[serde(default = "save_password_default")]
+    pub save_password: bool,
+    /// the name of the environment, see `util::env`
+    #[serde(default)]
+    pub env: String,
+}
+
+fn save_password_default() -> bool {
+    true
+}
+
+/// the variables of an environment saved by the frontend, see `util::env`
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct SavedEnvironment {
+    pub name: String,
+    pub vars: String,
+}
+
+/// an entry of the subscribe history saved by the frontend
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct SavedSubscribe {
+    pub topic: String,
+    pub qos: QoS,
+}
+
+impl Default for QoS {
+    fn default() -> Self {
+        QoS::AtMostOnce
+    }
+}
+impl ToString for QoS {
+    fn to_string(&self) -> String {
+        match self {
+            QoS::AtMostOnce => "0".to_string(),
+            QoS::AtLeastOnce => "1".to_string(),
+            QoS::ExactlyOnce => "2".to_string(),
+        }
+    }
+}
+
+impl From<rumqttc::v5::mqttbytes::QoS> for QoS {
+    fn from(qos: rumqttc::v5::mqttbytes::QoS) -> Self {
+        match qos {
+            rumqttc::v5::mqttbytes::QoS::AtLeastOnce => Self::AtLeastOnce,
+            rumqttc::v5::mqttbytes::QoS::AtMostOnce => Self::AtMostOnce,
+            rumqttc::v5::mqttbytes::QoS::ExactlyOnce => Self::ExactlyOnce,
+        }
+    }
+}
+impl From<QoS> for rumqttc::v5::mqttbytes::QoS {
+    fn from(qos: QoS) -> Self {
+        match qos {
+            QoS::AtLeastOnce => Self::AtLeastOnce,
+            QoS::AtMostOnce => Self::AtMostOnce,
+            QoS::ExactlyOnce => Self::ExactlyOnce,
+        }
+    }
+}
+
+impl TimelineEvent {
+    pub fn new(kind: TimelineKind, detail: impl Into<String>) -> Self {
+        Self {
+            time: now_millis(),
+            kind,
+            detail: Arc::new(detail.into()),
+        }
+    }
+}
+
+impl Display for TimelineKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Connect => "CONNECT",
+            Self::ConnAck => "CONNACK",
+            Self::PingReq => "PINGREQ",
+            Self::PingResp => "PINGRESP",
+            Self::Disconnect => "DISCONNECT",
+            Self::Error => "ERROR",
+            Self::Reconnect => "RECONNECT",
+        })
+    }
+}
+
+impl Display for TimelineEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {}",
+            format_millis(self.time),
+            self.kind,
+            self.detail
+        )
+    }
+}
+
+#[cfg(test)]
+impl SubscribeMsg {
+    /// a message of qos 0 without any property, the tests change the fields they need
+    pub fn sample(topic: &str, payload: &str) -> Self {
+        Self {
+            pkid: 0,
+            topic: Arc::new(topic.to_string()),
+            msg: Arc::new(payload.to_string()),
+            size: payload.len(),
+            qos: QoS::AtMostOnce,
+            retain: false,
+            correlation: Default::default(),
+            expiry: 0,
+            bridge: Default::default(),
+            script: Default::default(),
+            highlight: false,
+        }
+    }
+}
+
+impl SavedBroker {
+    /// substitute the variables of the environment
+    pub fn resolve(&mut self, vars: &HashMap<String, String>) {
+        for field in [
+            &mut self.client_id,
+            &mut self.addr,
+            &mut self.user_name,
+            &mut self.password,
+            &mut self.params,
+        ] {
+            *field = substitute(field, vars);
+        }
+    }
+}
+
+impl From<&SavedBroker> for ConnectOptions {
+    fn from(val: &SavedBroker) -> Self {
+        Self {
+            id: val.id,
+            client_id: val.client_id.clone(),
+            addr: val.addr.clone(),
+            port: val.port,
+            params: val.params.clone(),
+            credentials: val
+                .use_credentials
+                .then(|| (val.user_name.clone(), val.password.clone())),
+        }
+    }
+}
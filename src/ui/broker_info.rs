@@ -1,6 +1,6 @@
-use crate::data::common::Broker;
+use crate::data::common::{Broker, BrokerColor};
 use crate::data::hierarchy::AppData;
-use crate::data::lens::{BrokerIndex, PortLens};
+use crate::data::lens::{BrokerIndex, Masked, PortLens};
 use crate::data::AppEvent;
 use crate::ui::common::{
     error_display_widget, label_static, BUTTON_PADDING, TEXTBOX_MULTI_WIDTH, TEXTBOX_WIDTH,
@@ -10,13 +10,35 @@ use crate::ui::ids::{
     TextBoxErrorDelegate, ID_ADDR, ID_BUTTON_CONNECT, ID_BUTTON_RECONNECT, ID_CLIENT_ID, ID_PORT,
 };
 use crate::util::general_id;
-use druid::widget::{Button, Container, Either, Flex, TextBox};
+use crate::util::hint::VAULT_LOCKED;
+use druid::widget::{Button, Checkbox, Container, Either, Flex, RadioGroup, SizedBox, TextBox};
 use druid::{Env, LensExt, UnitPoint};
 use druid::{LocalizedString, WidgetExt};
-use log::{debug, error};
+use log::{debug, error, warn};
 
 pub fn display_broker(id: usize) -> Container<AppData> {
+    let prompt = Flex::row()
+        .with_child(label_static("password", UnitPoint::RIGHT))
+        .with_child(
+            TextBox::new()
+                .with_placeholder("not saved, input to connect")
+                .fix_width(TEXTBOX_WIDTH)
+                .lens(BrokerIndex(id).then(Broker::password).then(Masked)),
+        )
+        .with_child(
+            Button::new(LocalizedString::new("Connect"))
+                .on_click(move |_ctx, data: &mut AppData, _env| connect(data, id))
+                .padding(BUTTON_PADDING),
+        )
+        .align_left();
     let connection = Flex::column()
+        .with_child(Either::new(
+            move |data: &AppData, _: &Env| {
+                data.tab_statuses.get(&id).map_or(false, |x| x.ask_password)
+            },
+            prompt,
+            SizedBox::empty(),
+        ))
         .with_child(
             Flex::row()
                 .with_child(label_static("name", UnitPoint::RIGHT))
@@ -27,6 +49,48 @@ pub fn display_broker(id: usize) -> Container<AppData> {
                 )
                 .align_left(),
         )
+        .with_child(
+            Flex::row()
+                .with_child(label_static("folder", UnitPoint::RIGHT))
+                .with_child(
+                    TextBox::new()
+                        .with_placeholder("e.g. dev/staging/prod")
+                        .fix_width(TEXTBOX_WIDTH)
+                        .lens(BrokerIndex(id).then(Broker::folder)),
+                )
+                .align_left(),
+        )
+        .with_child(
+            Flex::row()
+                .with_child(label_static("tags", UnitPoint::RIGHT))
+                .with_child(
+                    TextBox::new()
+                        .with_placeholder("separated by comma")
+                        .fix_width(TEXTBOX_WIDTH)
+                        .lens(BrokerIndex(id).then(Broker::tags)),
+                )
+                .align_left(),
+        )
+        .with_child(
+            Flex::row()
+                .with_child(label_static("environment", UnitPoint::RIGHT))
+                .with_child(
+                    TextBox::new()
+                        .with_placeholder("the variables of ${NAME}")
+                        .fix_width(TEXTBOX_WIDTH)
+                        .lens(BrokerIndex(id).then(Broker::env)),
+                )
+                .align_left(),
+        )
+        .with_child(
+            Flex::row()
+                .with_child(label_static("color", UnitPoint::RIGHT))
+                .with_child(
+                    RadioGroup::row(BrokerColor::ALL.map(|x| (format!("{:?}", x), x)))
+                        .lens(BrokerIndex(id).then(Broker::color)),
+                )
+                .align_left(),
+        )
         .with_child(
             Flex::row()
                 .with_child(label_static("client id", UnitPoint::RIGHT))
@@ -73,6 +137,41 @@ pub fn display_broker(id: usize) -> Container<AppData> {
                 .with_child(error_display_widget(ID_PORT))
                 .align_left(),
         )
+        .with_child(
+            Flex::row()
+                .with_child(label_static("credentials", UnitPoint::RIGHT))
+                .with_child(
+                    Checkbox::new("use").lens(BrokerIndex(id).then(Broker::use_credentials)),
+                )
+                .with_child(
+                    Checkbox::new("save password")
+                        .lens(BrokerIndex(id).then(Broker::save_password))
+                        .padding(BUTTON_PADDING),
+                )
+                .align_left(),
+        )
+        .with_child(
+            Flex::row()
+                .with_child(label_static("user name", UnitPoint::RIGHT))
+                .with_child(
+                    TextBox::new()
+                        .fix_width(TEXTBOX_WIDTH)
+                        .lens(BrokerIndex(id).then(Broker::user_name))
+                        .disabled_if(move |data: &AppData, _env| !use_credentials(data, id)),
+                )
+                .align_left(),
+        )
+        .with_child(
+            Flex::row()
+                .with_child(label_static("password", UnitPoint::RIGHT))
+                .with_child(
+                    TextBox::new()
+                        .fix_width(TEXTBOX_WIDTH)
+                        .lens(BrokerIndex(id).then(Broker::password).then(Masked))
+                        .disabled_if(move |data: &AppData, _env| !use_credentials(data, id)),
+                )
+                .align_left(),
+        )
         .with_child(Either::new(
             move |data: &AppData, _: &Env| {
                 if let Some(broker) = data.tab_statuses.get(&id) {
@@ -85,7 +184,7 @@ pub fn display_broker(id: usize) -> Container<AppData> {
                 .with_child(
                     Button::new(LocalizedString::new("Save"))
                         .on_click(move |_ctx, data: &mut AppData, _env| {
-                            if let Err(e) = data.db.tx.send(AppEvent::SaveBroker(id)) {
+                            if let Err(e) = data.tx.send(AppEvent::SaveBroker(id)) {
                                 error!("{:?}", e);
                             }
                         })
@@ -95,7 +194,7 @@ pub fn display_broker(id: usize) -> Container<AppData> {
                     Button::new(LocalizedString::new("Reconnect"))
                         .on_click(move |_ctx, data: &mut AppData, _env| {
                             _ctx.set_focus(ID_BUTTON_RECONNECT);
-                            if let Err(e) = data.db.tx.send(AppEvent::ReConnect(id)) {
+                            if let Err(e) = data.tx.send(AppEvent::ReConnect(id)) {
                                 error!("{:?}", e);
                             }
                         })
@@ -103,7 +202,7 @@ pub fn display_broker(id: usize) -> Container<AppData> {
                 )
                 .with_child(Button::new(LocalizedString::new("Disconnect")).on_click(
                     move |_ctx, data: &mut AppData, _env| {
-                        if let Err(e) = data.db.tx.send(AppEvent::Disconnect(id)) {
+                        if let Err(e) = data.tx.send(AppEvent::Disconnect(id)) {
                             error!("{:?}", e);
                         }
                     },
@@ -112,25 +211,15 @@ pub fn display_broker(id: usize) -> Container<AppData> {
             Flex::row()
                 .with_child(Button::new(LocalizedString::new("Save")).on_click(
                     move |_ctx, data: &mut AppData, _env| {
-                        if let Err(e) = data.db.tx.send(AppEvent::SaveBroker(id)) {
+                        if let Err(e) = data.tx.send(AppEvent::SaveBroker(id)) {
                             error!("{:?}", e);
                         }
                     },
                 ))
                 .with_child(Button::new(LocalizedString::new("Connect")).on_click(
                     move |_ctx, data: &mut AppData, _env| {
-                        if let Some(broker) = data.brokers.iter_mut().find(|x| x.id == id) {
-                            debug!("{:?}", broker);
-                            _ctx.set_focus(ID_BUTTON_CONNECT);
-                            if broker.client_id.as_str().is_empty() {
-                                broker.client_id = general_id().into();
-                            }
-                            if let Err(e) = data.db.tx.send(AppEvent::Connect(broker.clone())) {
-                                error!("{:?}", e);
-                            }
-                        } else {
-                            error!("can't get the broker");
-                        }
+                        _ctx.set_focus(ID_BUTTON_CONNECT);
+                        connect(data, id);
                     },
                 ))
                 .align_left(),
@@ -150,3 +239,40 @@ pub fn display_broker(id: usize) -> Container<AppData> {
         );
     Container::new(connection)
 }
+
+fn connect(data: &mut AppData, id: usize) {
+    let Some(broker) = data.brokers.iter_mut().find(|x| x.id == id) else {
+        error!("can't get the broker");
+        return;
+    };
+    debug!("{:?}", broker);
+    if broker.client_id.as_str().is_empty() {
+        broker.client_id = general_id().into();
+    }
+    if data.vault.locked
+        && broker.use_credentials
+        && broker.save_password
+        && broker.password.is_empty()
+    {
+        warn!("{}", VAULT_LOCKED);
+        return;
+    }
+    if let Some(status) = data.tab_statuses.get_mut(&id) {
+        status.ask_password = false;
+    }
+    match data.resolved_broker(id) {
+        Ok(broker) => {
+            if let Err(e) = data.tx.send(AppEvent::Connect(broker)) {
+                error!("{:?}", e);
+            }
+        }
+        Err(e) => warn!("{}", e),
+    }
+}
+
+fn use_credentials(data: &AppData, id: usize) -> bool {
+    data.brokers
+        .iter()
+        .find(|x| x.id == id)
+        .map_or(false, |x| x.use_credentials)
+}
@@ -1,28 +1,53 @@
-use crate::data::common::{Broker, SubscribeHis};
+use crate::data::common::{
+    Broker, BrokerColor, BrokerFolder, SubscribeHis, SubscribeHisInput, SubscribeHisSort,
+};
 use crate::data::hierarchy::AppData;
-use crate::data::lens::{BrokerStoredList, LensSelectedSubscribeHis};
+use crate::data::lens::{BrokerFolderList, LensSelectedSubscribeHis};
 use crate::data::AppEvent;
 use crate::ui::common::{
     label_dy, label_dy_expand_width, label_static, label_static_expand_width, svg, title, QOS,
-    SILVER, TOPIC,
+    SILVER,
 };
+use crate::ui::environment::init_environments;
 use crate::ui::icons::{added_icon, connect_icon, copy_icon, modified_icon, removed_icon};
+use crate::ui::local_broker::init_local_broker;
+use crate::ui::profile::init_profile;
+use crate::ui::vault::init_vault;
 use crossbeam_channel::Sender;
 use druid::im::Vector;
 use druid::theme::{BORDER_LIGHT, TEXTBOX_BORDER_WIDTH};
 use druid::widget::Svg;
 use druid::widget::{
-    Button, Container, CrossAxisAlignment, Either, Flex, Label, List, Padding, Scroll, Split,
+    Button, Container, Controller, CrossAxisAlignment, Either, Flex, Label, List, Padding, Painter,
+    RadioGroup, Scroll, SizedBox, Split, TextBox,
 };
-use druid::{Env, EventCtx, UnitPoint};
+use druid::{Color, Env, Event, EventCtx, LensExt, LocalizedString, RenderContext, UnitPoint};
 use druid::{Widget, WidgetExt};
 use log::error;
+use std::cell::Cell;
+use std::rc::Rc;
 
 pub fn init_broker_list(tx: Sender<AppEvent>) -> impl Widget<AppData> {
-    Split::rows(init_connect(tx.clone()), init_subscribe_his_list(tx))
-        .split_point(0.55)
+    let lists = Split::rows(
+        init_connect(tx.clone()),
+        Split::rows(
+            init_subscribe_his_list(tx.clone()),
+            Split::rows(init_local_broker(), init_environments(tx))
+                .split_point(0.5)
+                .draggable(true)
+                .bar_size(3.0),
+        )
+        .split_point(0.5)
         .draggable(true)
-        .bar_size(3.0)
+        .bar_size(3.0),
+    )
+    .split_point(0.55)
+    .draggable(true)
+    .bar_size(3.0);
+    Flex::column()
+        .with_child(init_vault())
+        .with_child(init_profile())
+        .with_flex_child(lists, 1.0)
         .border(BORDER_LIGHT, TEXTBOX_BORDER_WIDTH)
         .padding(5.0)
 }
@@ -30,9 +55,28 @@ pub fn init_broker_list(tx: Sender<AppEvent>) -> impl Widget<AppData> {
 fn init_subscribe_his_list(tx: Sender<AppEvent>) -> impl Widget<AppData> {
     let his_fn = move || {
         let tx_click = tx.clone();
+        let tx_pin = tx.clone();
         Flex::row()
+            .with_child(
+                Label::dynamic(|data: &SubscribeHis, _: &Env| {
+                    if data.pinned { "★" } else { "☆" }.to_string()
+                })
+                .on_click(move |_ctx, data: &mut SubscribeHis, _env| {
+                    if let Err(_) = tx_pin.send(AppEvent::PinSubscribeHis(data.clone())) {
+                        error!("fail to send event")
+                    }
+                }),
+            )
             .with_child(QOS().lens(SubscribeHis::qos))
-            .with_child(TOPIC().lens(SubscribeHis::topic))
+            .with_flex_child(
+                Label::dynamic(|data: &SubscribeHis, _: &Env| format!("{}", data.title()))
+                    .expand_width(),
+                1.0,
+            )
+            .with_child(
+                Label::dynamic(|data: &SubscribeHis, _: &Env| format!("×{}", data.use_count))
+                    .with_text_size(8.),
+            )
             .expand_width()
             .on_click(move |_ctx, data: &mut SubscribeHis, _env| {
                 if let Err(_) = tx_click.send(AppEvent::ClickSubscribeHis(data.clone())) {
@@ -59,7 +103,7 @@ fn init_subscribe_his_list(tx: Sender<AppEvent>) -> impl Widget<AppData> {
         )
         .with_child(
             svg(removed_icon()).on_click(move |_ctx, data: &mut AppData, _env| {
-                if let Err(_) = data.db.tx.send(AppEvent::RemoveSubscribeHis) {
+                if let Err(_) = data.tx.send(AppEvent::RemoveSubscribeHis) {
                     error!("fail to send event")
                 }
             }),
@@ -67,7 +111,7 @@ fn init_subscribe_his_list(tx: Sender<AppEvent>) -> impl Widget<AppData> {
         .with_child(
             svg(connect_icon()).on_click(move |_ctx, data: &mut AppData, _env| {
                 if let Some(his) = data.get_selected_subscribe_his() {
-                    if let Err(_) = data.db.tx.send(AppEvent::SubscribeFromHis(his)) {
+                    if let Err(_) = data.tx.send(AppEvent::SubscribeFromHis(his)) {
                         error!("fail to send event");
                     }
                 }
@@ -81,66 +125,146 @@ fn init_subscribe_his_list(tx: Sender<AppEvent>) -> impl Widget<AppData> {
                 .expand_width()
                 .border(BORDER_LIGHT, TEXTBOX_BORDER_WIDTH),
         )
-        .with_flex_child(scroll.expand_width(), 1.0);
+        .with_child(
+            Flex::row()
+                .with_flex_child(
+                    TextBox::new()
+                        .with_placeholder("search topic, alias or description")
+                        .lens(AppData::subscribe_his_input.then(SubscribeHisInput::search))
+                        .expand_width(),
+                    1.0,
+                )
+                .with_child(
+                    RadioGroup::row([
+                        ("recent", SubscribeHisSort::Recent),
+                        ("frequent", SubscribeHisSort::Frequent),
+                    ])
+                    .lens(AppData::subscribe_his_input.then(SubscribeHisInput::sort)),
+                )
+                .expand_width(),
+        )
+        .with_flex_child(scroll.expand_width(), 1.0)
+        .with_child(
+            Flex::row()
+                .with_child(
+                    TextBox::new()
+                        .with_placeholder("alias")
+                        .lens(AppData::subscribe_his_input.then(SubscribeHisInput::alias))
+                        .fix_width(80.),
+                )
+                .with_flex_child(
+                    TextBox::new()
+                        .with_placeholder("description")
+                        .lens(AppData::subscribe_his_input.then(SubscribeHisInput::description))
+                        .expand_width(),
+                    1.0,
+                )
+                .with_child(Button::new(LocalizedString::new("Save")).on_click(
+                    move |_ctx, data: &mut AppData, _env| {
+                        if let Err(_) = data.tx.send(AppEvent::SaveSubscribeHisNote) {
+                            error!("fail to send event")
+                        }
+                    },
+                ))
+                .expand_width(),
+        );
     flex
 }
 
-pub fn init_connect(_tx: Sender<AppEvent>) -> Flex<AppData> {
+pub fn init_connect(tx: Sender<AppEvent>) -> Flex<AppData> {
     let name = || label_dy(|data: &Broker, _: &Env| format!("{}", data.name));
     let addr =
         || label_dy_expand_width(|data: &Broker, _: &Env| format!("{}:{}", data.addr, data.port));
+    let tags = || label_dy(|data: &Broker, _: &Env| format!("{}", data.tags));
+    let color = || {
+        Painter::new(|ctx, data: &Broker, _env| {
+            if let Some(color) = label_color(data.color) {
+                let rect = ctx.size().to_rect();
+                ctx.fill(rect, &color);
+            }
+        })
+        .fix_size(4.0, 16.0)
+    };
+    // the broker dragged, dropped on the other to move
+    let drag = Rc::new(Cell::new(None));
 
-    let list: List<Broker> = List::new(move || {
-        Either::new(
-            |data: &Broker, _env| data.selected,
+    let list = move |drag: Rc<Cell<Option<usize>>>| {
+        let row = |drag: Rc<Cell<Option<usize>>>| {
             Flex::row()
+                .with_child(color())
                 .with_child(name())
                 .with_flex_child(addr(), 1.0)
+                .with_child(tags())
+                .controller(DragBroker(drag))
                 .on_click(|_ctx: &mut EventCtx, data: &mut Broker, _env: &Env| {
                     if let Err(_e) = data.tx.send(AppEvent::ClickBroker(data.id)) {
                         error!("fail to send");
                     }
                 })
-                .background(SILVER),
-            Flex::row()
-                .with_child(name())
-                .with_flex_child(addr(), 1.0)
-                .on_click(|_ctx: &mut EventCtx, data: &mut Broker, _env: &Env| {
-                    if let Err(_e) = data.tx.send(AppEvent::ClickBroker(data.id)) {
-                        error!("fail to send");
-                    }
-                }),
+        };
+        Either::new(
+            |data: &Broker, _env| data.selected,
+            row(drag.clone()).background(SILVER),
+            row(drag.clone()),
         )
+    };
+    let folders: List<BrokerFolder> = List::new(move || {
+        let tx = tx.clone();
+        let header = label_dy_expand_width(|data: &BrokerFolder, _: &Env| {
+            let arrow = if data.collapsed { "▸" } else { "▾" };
+            format!("{} {} ({})", arrow, data.name, data.count)
+        })
+        .on_click(move |_ctx, data: &mut BrokerFolder, _env| {
+            if let Err(_e) = tx.send(AppEvent::ToggleBrokerFolder(data.name.clone())) {
+                error!("fail to send");
+            }
+        });
+        let drag = drag.clone();
+        let brokers: List<Broker> = List::new(move || list(drag.clone()));
+        Flex::column()
+            .with_child(Either::new(
+                |data: &BrokerFolder, _env| data.name.is_empty(),
+                SizedBox::empty(),
+                header,
+            ))
+            .with_child(brokers.lens(BrokerFolder::brokers))
     });
-    let scroll = Scroll::<Vector<Broker>, List<Broker>>::new(list);
+    let scroll = Scroll::<Vector<BrokerFolder>, List<BrokerFolder>>::new(folders);
 
     let buttons = Flex::row()
         // .cross_axis_alignment(CrossAxisAlignment::Center)
         .with_flex_child(title("Broker List", UnitPoint::LEFT).expand_width(), 1.0)
         .with_child(
             svg(added_icon()).on_click(move |_ctx, data: &mut AppData, _env| {
-                if let Err(_) = data.db.tx.send(AppEvent::AddBroker) {
+                if let Err(_) = data.tx.send(AppEvent::AddBroker) {
                     error!("fail to send event")
                 }
             }),
         )
         .with_child(
             svg(modified_icon()).on_click(move |_ctx, data: &mut AppData, _env| {
-                if let Err(_) = data.db.tx.send(AppEvent::EditBroker) {
+                if let Err(_) = data.tx.send(AppEvent::EditBroker) {
+                    error!("fail to send event")
+                }
+            }),
+        )
+        .with_child(
+            svg(copy_icon()).on_click(move |_ctx, data: &mut AppData, _env| {
+                if let Err(_) = data.tx.send(AppEvent::DuplicateBroker) {
                     error!("fail to send event")
                 }
             }),
         )
         .with_child(
             svg(removed_icon()).on_click(move |_ctx, data: &mut AppData, _env| {
-                if let Err(_) = data.db.tx.send(AppEvent::DeleteBroker) {
+                if let Err(_) = data.tx.send(AppEvent::DeleteBroker) {
                     error!("fail to send event")
                 }
             }),
         )
         .with_child(
             svg(connect_icon()).on_click(move |_ctx, data: &mut AppData, _env| {
-                if let Err(_) = data.db.tx.send(AppEvent::ConnectBroker) {
+                if let Err(_) = data.tx.send(AppEvent::ConnectBroker) {
                     error!("fail to send event")
                 }
             }),
@@ -156,6 +280,50 @@ pub fn init_connect(_tx: Sender<AppEvent>) -> Flex<AppData> {
                 // .padding(2.0)
                 .border(BORDER_LIGHT, TEXTBOX_BORDER_WIDTH),
         )
-        .with_flex_child(scroll.vertical().expand().lens(BrokerStoredList), 1.0);
+        .with_child(
+            TextBox::new()
+                .with_placeholder("search name, address, folder or tag")
+                .lens(AppData::broker_search)
+                .expand_width(),
+        )
+        .with_flex_child(scroll.vertical().expand().lens(BrokerFolderList), 1.0);
     flex
 }
+
+struct DragBroker(Rc<Cell<Option<usize>>>);
+
+impl<W: Widget<Broker>> Controller<Broker, W> for DragBroker {
+    fn event(
+        &mut self,
+        child: &mut W,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut Broker,
+        env: &Env,
+    ) {
+        match event {
+            Event::MouseDown(_) => self.0.set(Some(data.id)),
+            Event::MouseUp(_) if ctx.is_hot() => match self.0.take() {
+                Some(from) if from != data.id => {
+                    if let Err(_e) = data.tx.send(AppEvent::MoveBroker(from, data.id)) {
+                        error!("fail to send");
+                    }
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+        child.event(ctx, event, data, env)
+    }
+}
+
+pub fn label_color(color: BrokerColor) -> Option<Color> {
+    Some(match color {
+        BrokerColor::None => return None,
+        BrokerColor::Red => Color::rgb8(0xe5, 0x48, 0x4d),
+        BrokerColor::Orange => Color::rgb8(0xf7, 0x6b, 0x15),
+        BrokerColor::Green => Color::rgb8(0x30, 0xa4, 0x6c),
+        BrokerColor::Blue => Color::rgb8(0x00, 0x91, 0xff),
+        BrokerColor::Purple => Color::rgb8(0x8e, 0x4e, 0xc6),
+    })
+}
@@ -0,0 +1,84 @@
+use crate::data::common::ProbeSample;
+use crate::ui::common::GREEN;
+use druid::im::Vector;
+use druid::kurbo::BezPath;
+use druid::theme::BORDER_LIGHT;
+use druid::widget::prelude::*;
+use druid::Point;
+
+/// polyline of the latencies of probe, scaled by the max latency of samples
+pub struct LatencyChart {
+    height: f64,
+}
+
+impl LatencyChart {
+    pub fn new(height: f64) -> Self {
+        Self { height }
+    }
+}
+
+impl Widget<Vector<ProbeSample>> for LatencyChart {
+    fn event(
+        &mut self,
+        _ctx: &mut EventCtx,
+        _event: &Event,
+        _data: &mut Vector<ProbeSample>,
+        _env: &Env,
+    ) {
+    }
+
+    fn lifecycle(
+        &mut self,
+        _ctx: &mut LifeCycleCtx,
+        _event: &LifeCycle,
+        _data: &Vector<ProbeSample>,
+        _env: &Env,
+    ) {
+    }
+
+    fn update(
+        &mut self,
+        ctx: &mut UpdateCtx,
+        old_data: &Vector<ProbeSample>,
+        data: &Vector<ProbeSample>,
+        _env: &Env,
+    ) {
+        if !old_data.same(data) {
+            ctx.request_paint();
+        }
+    }
+
+    fn layout(
+        &mut self,
+        _ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        _data: &Vector<ProbeSample>,
+        _env: &Env,
+    ) -> Size {
+        bc.constrain(Size::new(bc.max().width, self.height))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &Vector<ProbeSample>, env: &Env) {
+        let size = ctx.size();
+        let bounds = size.to_rect();
+        ctx.stroke(bounds, &env.get(BORDER_LIGHT), 1.0);
+        if data.len() < 2 {
+            return;
+        }
+        let max = data.iter().map(|x| x.latency).fold(1.0, f64::max);
+        let step = size.width / (data.len() - 1) as f64;
+        let mut path = BezPath::new();
+        for (index, sample) in data.iter().enumerate() {
+            let point = Point::new(
+                index as f64 * step,
+                size.height - sample.latency / max * size.height,
+            );
+            if index == 0 {
+                path.move_to(point);
+            } else {
+                path.line_to(point);
+            }
+        }
+        ctx.stroke(path, &GREEN, 1.0);
+    }
+}
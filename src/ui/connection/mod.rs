@@ -1,46 +1,72 @@
-use crate::data::common::{Msg, PublicInput, QoS, SubscribeInput, SubscribeTopic};
+use crate::data::common::{
+    Bridge, Msg, Probe, PublicHis, PublicInput, PublicSchedule, PublicTemplate, QoS, RequestInput,
+    RequestRecord, RequestStatus, RetainMsg, RetainScan, Rule, Script, SubscribeFilter,
+    SubscribeInput, SubscribeStatus, SubscribeTopic, TimelineEvent,
+};
 use crate::data::hierarchy::AppData;
 use crate::data::lens::{
-    BrokerIndexLensPublicInput, BrokerIndexLensSubscribeInput, BrokerIndexLensVecMsg,
-    BrokerIndexLensVecSubscribeTopic, DbIndex, Index, MsgMsgLens, MsgQosLens, MsgTopicLens,
+    BrokerIndexLensBridge, BrokerIndexLensProbe, BrokerIndexLensPublicInput,
+    BrokerIndexLensRequestInput, BrokerIndexLensRetainScan, BrokerIndexLensRuleInput,
+    BrokerIndexLensScript, BrokerIndexLensSubscribeInput, BrokerIndexLensVecMsg,
+    BrokerIndexLensVecPublicHis, BrokerIndexLensVecPublicSchedule,
+    BrokerIndexLensVecPublicTemplate, BrokerIndexLensVecRequestRecord, BrokerIndexLensVecRule,
+    BrokerIndexLensVecSubscribeTopic, BrokerIndexLensVecTimeline, DbIndex, Index, MsgMsgLens,
+    MsgQosLens, MsgTopicLens,
 };
 use crate::data::{AString, AppEvent};
+use crate::ui::chart::LatencyChart;
 use crate::ui::common::{
-    error_display_widget, label_static, svg, BUTTON_PADDING, GREEN, MSG, QOS, SILVER, TOPIC, YELLOW,
-};
-use crate::ui::formatter::{check_no_empty, check_qos, MustInput};
-use crate::ui::icons::removed_icon;
-use crate::ui::ids::{
-    TextBoxErrorDelegate, ID_PUBLISH_MSG, ID_PUBLISH_QOS, ID_PUBLISH_TOPIC, ID_SUBSCRIBE_QOS,
-    ID_SUBSCRIBE_TOPIC,
+    error_display_widget, label_static, svg, BUTTON_PADDING, GREEN, LABLE_PADDING, MSG, QOS, RED,
+    SILVER, TOPIC, YELLOW,
 };
+use crate::ui::formatter::{check_no_empty, check_qos, check_retain_handling, MustInput};
+use crate::ui::icons::{added_icon, connect_icon, removed_icon};
+use crate::ui::ids::{TextBoxErrorDelegate, ID_PUBLISH_MSG, ID_PUBLISH_QOS, ID_PUBLISH_TOPIC};
+use crate::util::now_millis;
 use crossbeam_channel::Sender;
 use druid::im::Vector;
-use druid::text::EditableText;
+use druid::text::{EditableText, ParseFormatter};
 use druid::theme::{BORDER_LIGHT, TEXTBOX_BORDER_WIDTH};
 use druid::widget::{
-    Align, Button, Container, CrossAxisAlignment, Either, Flex, List, Padding, Scroll, Split, Svg,
-    TextBox,
+    Align, Button, Checkbox, Container, CrossAxisAlignment, Either, Flex, Label, LineBreaking,
+    List, Padding, Scroll, Split, Svg, TextBox,
 };
-use druid::{LensExt, LocalizedString};
-use druid::{UnitPoint, Widget, WidgetExt};
-use log::{debug, error};
+use druid::{Application, Env, LensExt, LocalizedString};
+use druid::{UnitPoint, Widget, WidgetExt, WidgetId};
+use log::{debug, error, warn};
 
 pub fn display_connection(id: usize, tx: Sender<AppEvent>) -> Container<AppData> {
     let subscribe_list = Padding::new(
         0.5,
         Container::new(
-            init_subscribe_list(id, tx), // Split::rows(init_subscribe_list(id), init_subscribe_his_list(id, tx))
-                                         //     .split_point(0.75)
-                                         //     .bar_size(1.0),
+            init_subscribe_list(id, tx.clone()), // Split::rows(init_subscribe_list(id), init_subscribe_his_list(id, tx))
+                                                 //     .split_point(0.75)
+                                                 //     .bar_size(1.0),
         ), // .border(BORDER_LIGHT, TEXTBOX_BORDER_WIDTH),
     );
     let subscribe = Padding::new(
         1.0,
         Container::new(
-            Split::rows(subscribe_list, init_subscribe_input(id))
-                .split_point(0.65)
+            Split::rows(
+                Split::rows(subscribe_list, init_subscribe_input(id, tx.clone()))
+                    .split_point(0.65)
+                    .bar_size(1.0),
+                Split::rows(
+                    init_probe(id),
+                    Split::rows(
+                        init_retain_scan(id),
+                        Split::rows(init_rules(id, tx.clone()), init_bridge(id))
+                            .split_point(0.5)
+                            .bar_size(1.0),
+                    )
+                    .split_point(0.33)
+                    .bar_size(1.0),
+                )
+                .split_point(0.33)
                 .bar_size(1.0),
+            )
+            .split_point(0.6)
+            .bar_size(1.0),
         )
         .border(BORDER_LIGHT, TEXTBOX_BORDER_WIDTH),
     );
@@ -49,8 +75,24 @@ pub fn display_connection(id: usize, tx: Sender<AppEvent>) -> Container<AppData>
         0.5,
         Container::new(
             Split::rows(
-                Align::centered(init_msgs_list(id)),
-                Align::centered(init_public_input(id)),
+                Split::columns(
+                    Align::centered(init_msgs_list(id)),
+                    Split::rows(
+                        Align::centered(init_request(id)),
+                        Split::rows(
+                            Align::centered(init_script(id)),
+                            Align::centered(init_timeline(id)),
+                        )
+                        .split_point(0.5)
+                        .bar_size(1.0),
+                    )
+                    .split_point(0.33)
+                    .bar_size(1.0),
+                )
+                .split_point(0.6)
+                .draggable(true)
+                .bar_size(1.0),
+                Align::centered(init_public_input(id, tx)),
             )
             .split_point(0.65)
             .bar_size(1.0),
@@ -74,7 +116,7 @@ fn init_subscribe_list(id: usize, tx: Sender<AppEvent>) -> impl Widget<AppData>
                 move |_ctx, data: &mut SubscribeTopic, _env| {
                     if let Err(_) = tx.send(AppEvent::ToUnSubscribe {
                         broker_id: id,
-                        pk_id: data.pkid,
+                        id: data.id,
                     }) {
                         error!("fail to send event")
                     }
@@ -83,7 +125,11 @@ fn init_subscribe_list(id: usize, tx: Sender<AppEvent>) -> impl Widget<AppData>
             .with_child(Either::new(
                 |data: &SubscribeTopic, _env| data.is_sucess(),
                 QOS().background(GREEN).lens(SubscribeTopic::qos),
-                QOS().background(YELLOW).lens(SubscribeTopic::qos),
+                Either::new(
+                    |data: &SubscribeTopic, _env| data.status == SubscribeStatus::SubscribeFail,
+                    QOS().background(RED).lens(SubscribeTopic::qos),
+                    QOS().background(YELLOW).lens(SubscribeTopic::qos),
+                ),
             ))
             .with_child(TextBox::new().lens(SubscribeTopic::topic).fix_width(150.0))
             .align_left()
@@ -146,7 +192,13 @@ fn init_msgs_list(id: usize) -> impl Widget<AppData> {
                 .with_child(
                     Flex::row()
                         .with_child(QOS().background(GREEN).lens(MsgTopicLens))
-                        .with_child(TextBox::<AString>::new().lens(MsgTopicLens))
+                        .with_child(Either::new(
+                            |data: &Msg, _env| data.is_highlight(),
+                            TextBox::<AString>::new()
+                                .background(YELLOW)
+                                .lens(MsgTopicLens),
+                            TextBox::<AString>::new().lens(MsgTopicLens),
+                        ))
                         .align_horizontal(UnitPoint::LEFT),
                 )
                 .with_child(
@@ -171,82 +223,832 @@ fn init_msgs_list(id: usize) -> impl Widget<AppData> {
 }
 
 //
-pub fn init_subscribe_input(id: usize) -> impl Widget<AppData> {
-    let connection = Flex::column()
+pub fn init_subscribe_input(id: usize, tx: Sender<AppEvent>) -> impl Widget<AppData> {
+    let list: List<SubscribeFilter> = List::new(move || {
+        let tx = tx.clone();
+        // every row reports its own errors
+        let topic_id = WidgetId::next();
+        let qos_id = WidgetId::next();
+        let retain_handling_id = WidgetId::next();
+        Flex::row()
+            .with_child(svg(removed_icon()).on_click(
+                move |_ctx, data: &mut SubscribeFilter, _env| {
+                    if let Err(_) = tx.send(AppEvent::RemoveSubscribeFilter(id, data.id)) {
+                        error!("fail to send event")
+                    }
+                },
+            ))
+            .with_child(
+                TextBox::new()
+                    .with_placeholder("topic")
+                    .with_formatter(MustInput)
+                    .update_data_while_editing(true)
+                    .validate_while_editing(true)
+                    .delegate(
+                        TextBoxErrorDelegate::new(topic_id, check_no_empty)
+                            .sends_partial_errors(true),
+                    )
+                    .lens(SubscribeFilter::topic)
+                    .fix_width(150.),
+            )
+            .with_child(
+                TextBox::new()
+                    .with_placeholder("qos")
+                    .with_formatter(MustInput)
+                    .update_data_while_editing(true)
+                    .validate_while_editing(true)
+                    .delegate(
+                        TextBoxErrorDelegate::new(qos_id, check_qos).sends_partial_errors(true),
+                    )
+                    .lens(SubscribeFilter::qos)
+                    .fix_width(30.),
+            )
+            .with_child(Checkbox::new("nl").lens(SubscribeFilter::no_local))
+            .with_child(Checkbox::new("rap").lens(SubscribeFilter::retain_as_published))
+            .with_child(
+                TextBox::new()
+                    .with_placeholder("rh")
+                    .with_formatter(MustInput)
+                    .update_data_while_editing(true)
+                    .validate_while_editing(true)
+                    .delegate(
+                        TextBoxErrorDelegate::new(retain_handling_id, check_retain_handling)
+                            .sends_partial_errors(true),
+                    )
+                    .lens(SubscribeFilter::retain_handling)
+                    .fix_width(30.),
+            )
+            .with_child(error_display_widget(topic_id))
+            .with_child(error_display_widget(qos_id))
+            .with_child(error_display_widget(retain_handling_id))
+            .align_left()
+    });
+    let filters = Scroll::<Vector<SubscribeFilter>, List<SubscribeFilter>>::new(list)
+        .vertical()
+        .lens(BrokerIndexLensSubscribeInput(id).then(SubscribeInput::filters))
+        .align_vertical(UnitPoint::TOP)
+        .expand_width();
+    let connection = Flex::column().with_flex_child(filters, 1.0).with_child(
+        Flex::row()
+            .with_child(
+                svg(added_icon()).on_click(move |_ctx, data: &mut AppData, _env| {
+                    if let Err(_) = data.tx.send(AppEvent::AddSubscribeFilter(id)) {
+                        error!("fail to send event")
+                    }
+                }),
+            )
+            .with_child(
+                Button::new(LocalizedString::new("Subscribe"))
+                    .on_click(move |_ctx, data: &mut DbIndex, _env| {
+                        debug!("{:?}", data.data);
+                        if let Some(input) = data.data.subscribe_input.get(&data.id) {
+                            if input.is_empty() {
+                                debug!("topic is empty");
+                                return;
+                            }
+                            if let Err(e) = data
+                                .data
+                                .tx
+                                .send(AppEvent::Subscribe(input.clone(), data.id))
+                            {
+                                error!("{:?}", e);
+                            }
+                        } else {
+                            error!("can't get the broker");
+                        }
+                    })
+                    .disabled_if(|data: &DbIndex, _env| {
+                        if let Some(broker) = data.data.tab_statuses.get(&data.id) {
+                            !broker.connected
+                        } else {
+                            true
+                        }
+                    })
+                    .padding(BUTTON_PADDING)
+                    .lens(Index(id)),
+            ),
+    );
+    connection
+}
+
+fn init_request(id: usize) -> impl Widget<AppData> {
+    let list: List<RequestRecord> = List::new(move || {
+        Flex::column()
+            .with_child(
+                Flex::row()
+                    .with_child(
+                        Label::dynamic(|data: &RequestRecord, _: &Env| match data.status {
+                            RequestStatus::Replied => format!("{}ms", data.latency),
+                            _ => data.status.to_string(),
+                        })
+                        .background(SILVER)
+                        .padding(1.0),
+                    )
+                    .with_child(Label::dynamic(|data: &RequestRecord, _: &Env| {
+                        format!("{}", data.topic)
+                    }))
+                    .align_left(),
+            )
+            .with_child(
+                TextBox::multiline()
+                    .lens(RequestRecord::msg)
+                    .expand_width()
+                    .padding(1.0),
+            )
+            .with_child(
+                TextBox::multiline()
+                    .with_placeholder("reply")
+                    .lens(RequestRecord::reply)
+                    .expand_width()
+                    .padding(1.0),
+            )
+            .border(BORDER_LIGHT, 1.0)
+            .padding(3.0)
+    });
+    let records = Scroll::<Vector<RequestRecord>, List<RequestRecord>>::new(list)
+        .vertical()
+        .lens(BrokerIndexLensVecRequestRecord(id))
+        .align_vertical(UnitPoint::TOP)
+        .expand_width();
+    Flex::column()
+        .with_child(
+            TextBox::new()
+                .with_placeholder("request topic")
+                .lens(BrokerIndexLensRequestInput(id).then(RequestInput::topic))
+                .expand_width(),
+        )
+        .with_child(
+            TextBox::new()
+                .with_placeholder("response topic")
+                .lens(BrokerIndexLensRequestInput(id).then(RequestInput::response_topic))
+                .expand_width(),
+        )
+        .with_child(
+            TextBox::multiline()
+                .with_placeholder("request")
+                .lens(BrokerIndexLensRequestInput(id).then(RequestInput::msg))
+                .expand_width()
+                .fix_height(40.),
+        )
         .with_child(
             Flex::row()
-                .with_child(label_static("topic", UnitPoint::RIGHT))
                 .with_child(
                     TextBox::new()
+                        .with_placeholder("qos")
                         .with_formatter(MustInput)
                         .update_data_while_editing(true)
-                        .validate_while_editing(true)
-                        .delegate(
-                            TextBoxErrorDelegate::new(ID_SUBSCRIBE_TOPIC, check_no_empty)
-                                .sends_partial_errors(true),
-                        )
-                        .lens(BrokerIndexLensSubscribeInput(id).then(SubscribeInput::topic))
+                        .lens(BrokerIndexLensRequestInput(id).then(RequestInput::qos))
+                        .fix_width(30.),
+                )
+                .with_child(
+                    TextBox::new()
+                        .with_placeholder("timeout(s)")
+                        .with_formatter(ParseFormatter::new())
+                        .update_data_while_editing(true)
+                        .lens(BrokerIndexLensRequestInput(id).then(RequestInput::timeout))
+                        .fix_width(80.),
+                )
+                .with_child(
+                    Button::new(LocalizedString::new("Request"))
+                        .on_click(move |_ctx, data: &mut DbIndex, _env| {
+                            let Some(input) = data.data.request_input.get(&data.id) else {
+                                error!("can't get the broker");
+                                return;
+                            };
+                            if input.topic.is_empty() || input.response_topic.is_empty() {
+                                debug!("topic or response topic is empty");
+                                return;
+                            }
+                            if let Err(e) =
+                                data.data.tx.send(AppEvent::Request(input.clone(), data.id))
+                            {
+                                error!("{:?}", e);
+                            }
+                        })
+                        .disabled_if(|data: &DbIndex, _env| {
+                            if let Some(broker) = data.data.tab_statuses.get(&data.id) {
+                                !broker.connected
+                            } else {
+                                true
+                            }
+                        })
+                        .padding(BUTTON_PADDING)
+                        .lens(Index(id)),
+                )
+                .align_left(),
+        )
+        .with_flex_child(records, 1.0)
+}
+
+fn init_retain_scan(id: usize) -> impl Widget<AppData> {
+    let list: List<RetainMsg> = List::new(|| {
+        Flex::row()
+            .with_child(Checkbox::new("").lens(RetainMsg::selected))
+            .with_child(Label::dynamic(|data: &RetainMsg, _: &Env| {
+                let age = data.age(now_millis());
+                if data.expiry > 0 {
+                    format!(
+                        "{} {}B seen {} ago, expiry: {}s",
+                        data.topic, data.size, age, data.expiry
+                    )
+                } else {
+                    format!("{} {}B seen {} ago", data.topic, data.size, age)
+                }
+            }))
+            .with_child(
+                Label::dynamic(|data: &RetainMsg, _: &Env| data.preview.to_string())
+                    .with_text_color(SILVER),
+            )
+            .align_left()
+    });
+    let msgs = Scroll::<Vector<RetainMsg>, List<RetainMsg>>::new(list)
+        .vertical()
+        .lens(BrokerIndexLensRetainScan(id).then(RetainScan::msgs))
+        .align_vertical(UnitPoint::TOP)
+        .expand_width();
+    let connected = move |data: &AppData| {
+        data.tab_statuses
+            .get(&id)
+            .map(|x| x.connected)
+            .unwrap_or_default()
+    };
+    let scan = move |data: &AppData| data.retain_scans.get(&id).cloned().unwrap_or_default();
+    Flex::column()
+        .with_child(
+            Flex::row()
+                .with_child(
+                    TextBox::new()
+                        .with_placeholder("filter, e.g. #")
+                        .lens(BrokerIndexLensRetainScan(id).then(RetainScan::filter))
+                        .fix_width(120.),
+                )
+                .with_child(
+                    Button::new(LocalizedString::new("Scan"))
+                        .on_click(move |_ctx, data: &mut AppData, _env| {
+                            let filter = match data.retain_scan_filter(id) {
+                                Ok(filter) => filter,
+                                Err(e) => {
+                                    error!("{:?}", e);
+                                    return;
+                                }
+                            };
+                            if filter.topic.is_empty() {
+                                debug!("filter is empty");
+                                return;
+                            }
+                            if let Err(e) = data.tx.send(AppEvent::StartRetainScan(id, filter)) {
+                                error!("{:?}", e);
+                            }
+                        })
+                        .disabled_if(move |data: &AppData, _env| {
+                            !connected(data) || scan(data).scanning
+                        })
+                        .padding(BUTTON_PADDING),
+                )
+                .align_left(),
+        )
+        .with_flex_child(msgs, 1.0)
+        .with_child(
+            Flex::row()
+                .with_child(
+                    Button::new(LocalizedString::new("Clear"))
+                        .on_click(move |_ctx, data: &mut AppData, _env| {
+                            if let Err(e) = data.tx.send(AppEvent::PreviewRetainClear(id)) {
+                                error!("{:?}", e);
+                            }
+                        })
+                        .disabled_if(move |data: &AppData, _env| {
+                            let scan = scan(data);
+                            !connected(data) || scan.scanning || scan.confirming
+                        })
+                        .padding(BUTTON_PADDING),
+                )
+                .with_child(
+                    Button::new(LocalizedString::new("Confirm"))
+                        .on_click(move |_ctx, data: &mut AppData, _env| {
+                            let Some(scan) = data.retain_scans.get(&id) else {
+                                error!("can't get the broker");
+                                return;
+                            };
+                            let topics = scan.selected_topics();
+                            if let Err(e) = data.tx.send(AppEvent::ClearRetain(id, topics)) {
+                                error!("{:?}", e);
+                            }
+                        })
+                        .disabled_if(move |data: &AppData, _env| {
+                            !connected(data) || !scan(data).confirming
+                        })
+                        .padding(BUTTON_PADDING),
+                )
+                .with_child(
+                    Button::new(LocalizedString::new("Cancel"))
+                        .on_click(move |_ctx, data: &mut AppData, _env| {
+                            if let Err(e) = data.tx.send(AppEvent::CancelRetainClear(id)) {
+                                error!("{:?}", e);
+                            }
+                        })
+                        .disabled_if(move |data: &AppData, _env| !scan(data).confirming)
+                        .padding(BUTTON_PADDING),
+                )
+                .align_left(),
+        )
+}
+
+fn init_bridge(id: usize) -> impl Widget<AppData> {
+    let status = Label::dynamic(|data: &Bridge, _: &Env| {
+        format!(
+            "forwarded: {} back: {} dropped: {}",
+            data.forwarded, data.backwarded, data.dropped
+        )
+    })
+    .with_text_size(12.)
+    .lens(BrokerIndexLensBridge(id));
+    let input = Flex::column()
+        .with_child(
+            Flex::row()
+                .with_child(
+                    TextBox::new()
+                        .with_placeholder("targets, e.g. a,b")
+                        .lens(Bridge::target)
+                        .fix_width(90.),
+                )
+                .with_child(
+                    TextBox::new()
+                        .with_placeholder("filters, e.g. a/#,b/+")
+                        .lens(Bridge::filters)
                         .fix_width(150.),
                 )
-                .with_child(error_display_widget(ID_SUBSCRIBE_TOPIC))
                 .align_left(),
         )
         .with_child(
             Flex::row()
-                .with_child(label_static("qos", UnitPoint::RIGHT))
                 .with_child(
                     TextBox::new()
-                        .with_placeholder("0/1/2")
+                        .with_placeholder("strip prefix")
+                        .lens(Bridge::strip_prefix)
+                        .fix_width(90.),
+                )
+                .with_child(
+                    TextBox::new()
+                        .with_placeholder("add prefix")
+                        .lens(Bridge::add_prefix)
+                        .fix_width(90.),
+                )
+                .with_child(
+                    TextBox::new()
+                        .with_placeholder("max qos")
                         .with_formatter(MustInput)
                         .update_data_while_editing(true)
-                        .validate_while_editing(true)
-                        .delegate(
-                            TextBoxErrorDelegate::new(ID_SUBSCRIBE_QOS, check_qos)
-                                .sends_partial_errors(true),
-                        )
-                        .lens(BrokerIndexLensSubscribeInput(id).then(SubscribeInput::qos))
-                        .fix_width(150.),
+                        .lens(Bridge::max_qos)
+                        .fix_width(30.),
                 )
-                .with_child(error_display_widget(ID_SUBSCRIBE_QOS))
+                .with_child(Checkbox::new("both").lens(Bridge::both))
                 .align_left(),
         )
+        .disabled_if(|data: &Bridge, _env| data.running)
+        .lens(BrokerIndexLensBridge(id));
+    let running =
+        move |data: &AppData| data.bridges.get(&id).map(|x| x.running).unwrap_or_default();
+    Flex::column()
+        .with_child(input)
         .with_child(
-            Flex::row().with_child(
-                Button::new(LocalizedString::new("Subscribe"))
-                    .on_click(move |_ctx, data: &mut DbIndex, _env| {
-                        debug!("{:?}", data.data);
-                        if let Some(input) = data.data.subscribe_input.get(&data.id) {
-                            if input.topic.is_empty() {
-                                debug!("topic is empty");
+            Flex::row()
+                .with_child(
+                    Button::new(LocalizedString::new("Bridge"))
+                        .on_click(move |_ctx, data: &mut AppData, _env| {
+                            let routes = match data.bridge_routes(id) {
+                                Ok(routes) => routes,
+                                Err(e) => {
+                                    warn!("{}", e.to_string());
+                                    return;
+                                }
+                            };
+                            if let Err(e) = data.tx.send(AppEvent::StartBridge(routes)) {
+                                error!("{:?}", e);
+                            }
+                        })
+                        .disabled_if(move |data: &AppData, _env| running(data))
+                        .padding(BUTTON_PADDING),
+                )
+                .with_child(
+                    Button::new(LocalizedString::new("Stop"))
+                        .on_click(move |_ctx, data: &mut AppData, _env| {
+                            if let Err(e) = data.tx.send(AppEvent::StopBridge(id)) {
+                                error!("{:?}", e);
+                            }
+                        })
+                        .disabled_if(move |data: &AppData, _env| !running(data))
+                        .padding(BUTTON_PADDING),
+                )
+                .with_child(status)
+                .align_left(),
+        )
+        .align_vertical(UnitPoint::TOP)
+}
+
+fn init_rules(id: usize, tx: Sender<AppEvent>) -> impl Widget<AppData> {
+    let list: List<Rule> = List::new(move || {
+        let tx_toggle = tx.clone();
+        let tx_remove = tx.clone();
+        Flex::row()
+            .with_child(
+                svg(removed_icon()).on_click(move |_ctx, data: &mut Rule, _env| {
+                    if let Err(_) = tx_remove.send(AppEvent::RemoveRule(id, data.id)) {
+                        error!("fail to send event")
+                    }
+                }),
+            )
+            .with_child(
+                Label::dynamic(|data: &Rule, _: &Env| {
+                    let state = if data.enabled { "[on]" } else { "[off]" };
+                    state.to_string()
+                })
+                .on_click(move |_ctx, data: &mut Rule, _env| {
+                    if let Err(_) = tx_toggle.send(AppEvent::ToggleRule(id, data.id)) {
+                        error!("fail to send event")
+                    }
+                }),
+            )
+            .with_child(Label::dynamic(|data: &Rule, _: &Env| {
+                let mut actions = Vec::new();
+                if data.highlight {
+                    actions.push("highlight".to_string());
+                }
+                if data.notify {
+                    actions.push("notify".to_string());
+                }
+                if !data.topic.is_empty() {
+                    actions.push(format!("publish {}", data.topic));
+                }
+                format!(
+                    "{} {} => {} fired: {}",
+                    data.filter,
+                    data.condition,
+                    actions.join(", "),
+                    data.fired
+                )
+            }))
+            .align_left()
+    });
+    let rules = Scroll::<Vector<Rule>, List<Rule>>::new(list)
+        .vertical()
+        .lens(BrokerIndexLensVecRule(id))
+        .align_vertical(UnitPoint::TOP)
+        .expand_width();
+    let input = Flex::column()
+        .with_child(
+            Flex::row()
+                .with_child(
+                    TextBox::new()
+                        .with_placeholder("filter")
+                        .lens(Rule::filter)
+                        .fix_width(80.),
+                )
+                .with_child(
+                    TextBox::new()
+                        .with_placeholder("condition: $.path or regex")
+                        .lens(Rule::condition)
+                        .fix_width(160.),
+                )
+                .align_left(),
+        )
+        .with_child(
+            Flex::row()
+                .with_child(Checkbox::new("highlight").lens(Rule::highlight))
+                .with_child(Checkbox::new("notify").lens(Rule::notify))
+                .with_child(Checkbox::new("retain").lens(Rule::retain))
+                .align_left(),
+        )
+        .with_child(
+            Flex::row()
+                .with_child(
+                    TextBox::new()
+                        .with_placeholder("publish topic")
+                        .lens(Rule::topic)
+                        .fix_width(80.),
+                )
+                .with_child(
+                    TextBox::new()
+                        .with_placeholder("msg")
+                        .lens(Rule::msg)
+                        .fix_width(110.),
+                )
+                .with_child(
+                    TextBox::new()
+                        .with_placeholder("qos")
+                        .with_formatter(MustInput)
+                        .update_data_while_editing(true)
+                        .lens(Rule::qos)
+                        .fix_width(30.),
+                )
+                .align_left(),
+        )
+        .lens(BrokerIndexLensRuleInput(id));
+    Flex::column()
+        .with_flex_child(rules, 1.0)
+        .with_child(input)
+        .with_child(
+            Button::new(LocalizedString::new("AddRule"))
+                .on_click(move |_ctx, data: &mut AppData, _env| {
+                    if let Err(e) = data.tx.send(AppEvent::AddRule(id)) {
+                        error!("{:?}", e);
+                    }
+                })
+                .padding(BUTTON_PADDING)
+                .align_left(),
+        )
+}
+
+fn init_script(id: usize) -> impl Widget<AppData> {
+    let console = Scroll::new(List::new(|| {
+        Label::dynamic(|data: &AString, _: &Env| data.to_string())
+            .with_text_size(12.)
+            .align_left()
+    }))
+    .vertical()
+    .lens(BrokerIndexLensScript(id).then(Script::console))
+    .align_vertical(UnitPoint::TOP)
+    .expand_width();
+    let connected = move |data: &AppData| {
+        data.tab_statuses
+            .get(&id)
+            .map(|x| x.connected)
+            .unwrap_or_default()
+    };
+    let running =
+        move |data: &AppData| data.scripts.get(&id).map(|x| x.running).unwrap_or_default();
+    Flex::column()
+        .with_flex_child(
+            TextBox::multiline()
+                .with_placeholder("fn on_message(topic, payload, props) { }")
+                .lens(BrokerIndexLensScript(id).then(Script::code))
+                .expand(),
+            1.0,
+        )
+        .with_child(
+            Flex::row()
+                .with_child(
+                    Button::new(LocalizedString::new("Run"))
+                        .on_click(move |_ctx, data: &mut AppData, _env| {
+                            let Some(script) = data.scripts.get(&id) else {
+                                error!("can't get the broker");
                                 return;
+                            };
+                            if let Err(e) =
+                                data.tx.send(AppEvent::LoadScript(id, script.code.clone()))
+                            {
+                                error!("{:?}", e);
+                            }
+                        })
+                        .disabled_if(move |data: &AppData, _env| !connected(data))
+                        .padding(BUTTON_PADDING),
+                )
+                .with_child(
+                    Button::new(LocalizedString::new("Stop"))
+                        .on_click(move |_ctx, data: &mut AppData, _env| {
+                            if let Err(e) = data.tx.send(AppEvent::StopScript(id)) {
+                                error!("{:?}", e);
                             }
+                        })
+                        .disabled_if(move |data: &AppData, _env| !running(data))
+                        .padding(BUTTON_PADDING),
+                )
+                .align_left(),
+        )
+        .with_flex_child(console, 1.0)
+}
+
+/// the packets and the errors of the connection, see `mqtt::init_connect`
+fn init_timeline(id: usize) -> impl Widget<AppData> {
+    let list = Scroll::new(List::new(|| {
+        Label::dynamic(|data: &TimelineEvent, _: &Env| data.to_string())
+            .with_line_break_mode(LineBreaking::WordWrap)
+            .with_text_size(12.)
+            .align_left()
+    }))
+    .vertical()
+    .lens(BrokerIndexLensVecTimeline(id))
+    .align_vertical(UnitPoint::TOP)
+    .expand_width();
+    Flex::column()
+        .with_child(
+            Flex::row()
+                .with_child(
+                    Button::new(LocalizedString::new("Copy"))
+                        .on_click(move |_ctx, data: &mut AppData, _env| {
+                            Application::global()
+                                .clipboard()
+                                .put_string(data.timeline_text(id));
+                        })
+                        .padding(BUTTON_PADDING),
+                )
+                .with_child(
+                    Button::new(LocalizedString::new("Clear"))
+                        .on_click(move |_ctx, data: &mut AppData, _env| {
+                            if let Err(e) = data.tx.send(AppEvent::ClearTimeline(id)) {
+                                error!("{:?}", e);
+                            }
+                        })
+                        .padding(BUTTON_PADDING),
+                )
+                .align_left(),
+        )
+        .with_flex_child(list, 1.0)
+}
+
+fn init_probe(id: usize) -> impl Widget<AppData> {
+    let status = Label::dynamic(|data: &Probe, _: &Env| {
+        format!(
+            "{}/{} last: {:.0}ms avg: {:.1}ms jitter: {:.1}ms",
+            data.received,
+            data.sent,
+            data.last(),
+            data.avg(),
+            data.jitter()
+        )
+    })
+    .with_text_size(12.)
+    .lens(BrokerIndexLensProbe(id));
+    Flex::column()
+        .with_child(
+            Flex::row()
+                .with_child(
+                    TextBox::new()
+                        .with_placeholder("interval(ms)")
+                        .with_formatter(ParseFormatter::new())
+                        .update_data_while_editing(true)
+                        .lens(BrokerIndexLensProbe(id).then(Probe::interval))
+                        .fix_width(100.),
+                )
+                .with_child(
+                    Button::new(LocalizedString::new("Probe"))
+                        .on_click(move |_ctx, data: &mut DbIndex, _env| {
+                            let Some(probe) = data.data.probes.get(&data.id) else {
+                                error!("can't get the broker");
+                                return;
+                            };
                             if let Err(e) = data
                                 .data
-                                .db
                                 .tx
-                                .send(AppEvent::Subscribe(input.clone(), data.id))
+                                .send(AppEvent::StartProbe(data.id, probe.interval))
                             {
                                 error!("{:?}", e);
                             }
-                        } else {
-                            error!("can't get the broker");
+                        })
+                        .disabled_if(|data: &DbIndex, _env| {
+                            let connected = data
+                                .data
+                                .tab_statuses
+                                .get(&data.id)
+                                .map(|x| x.connected)
+                                .unwrap_or_default();
+                            let running = data
+                                .data
+                                .probes
+                                .get(&data.id)
+                                .map(|x| x.running)
+                                .unwrap_or_default();
+                            !connected || running
+                        })
+                        .padding(BUTTON_PADDING)
+                        .lens(Index(id)),
+                )
+                .with_child(
+                    Button::new(LocalizedString::new("Stop"))
+                        .on_click(move |_ctx, data: &mut AppData, _env| {
+                            if let Err(e) = data.tx.send(AppEvent::StopProbe(id)) {
+                                error!("{:?}", e);
+                            }
+                        })
+                        .disabled_if(move |data: &AppData, _env| {
+                            !data.probes.get(&id).map(|x| x.running).unwrap_or_default()
+                        })
+                        .padding(BUTTON_PADDING),
+                )
+                .align_left(),
+        )
+        .with_child(status.align_left())
+        .with_flex_child(
+            LatencyChart::new(80.)
+                .lens(BrokerIndexLensProbe(id).then(Probe::samples))
+                .padding(LABLE_PADDING),
+            1.0,
+        )
+}
+
+fn init_public_template_list(id: usize, tx: Sender<AppEvent>) -> impl Widget<AppData> {
+    let list: List<PublicTemplate> = List::new(move || {
+        let tx_select = tx.clone();
+        let tx_remove = tx.clone();
+        Flex::row()
+            .with_child(
+                Label::dynamic(|data: &PublicTemplate, _: &Env| format!("{}", data.name))
+                    .padding(LABLE_PADDING)
+                    .on_click(move |_ctx, data: &mut PublicTemplate, _env| {
+                        if let Err(_) = tx_select.send(AppEvent::SelectPublicTemplate(id, data.id))
+                        {
+                            error!("fail to send event")
                         }
-                    })
-                    .disabled_if(|data: &DbIndex, _env| {
-                        if let Some(broker) = data.data.tab_statuses.get(&data.id) {
-                            !broker.connected
-                        } else {
-                            true
+                    }),
+            )
+            .with_child(svg(removed_icon()).on_click(
+                move |_ctx, data: &mut PublicTemplate, _env| {
+                    if let Err(_) = tx_remove.send(AppEvent::RemovePublicTemplate(id, data.id)) {
+                        error!("fail to send event")
+                    }
+                },
+            ))
+            .border(BORDER_LIGHT, TEXTBOX_BORDER_WIDTH)
+    })
+    .horizontal();
+    Scroll::<Vector<PublicTemplate>, List<PublicTemplate>>::new(list)
+        .horizontal()
+        .lens(BrokerIndexLensVecPublicTemplate(id))
+        .fix_width(380.)
+}
+
+/// click to fill the public input, or publish again
+fn init_public_his_list(id: usize, tx: Sender<AppEvent>) -> impl Widget<AppData> {
+    let list: List<PublicHis> = List::new(move || {
+        let tx_favourite = tx.clone();
+        let tx_select = tx.clone();
+        let tx_publish = tx.clone();
+        let tx_remove = tx.clone();
+        Flex::row()
+            .with_child(
+                Label::dynamic(|data: &PublicHis, _: &Env| {
+                    if data.favourite { "★" } else { "☆" }.to_string()
+                })
+                .on_click(move |_ctx, data: &mut PublicHis, _env| {
+                    if let Err(_) = tx_favourite.send(AppEvent::FavouritePublicHis(id, data.id)) {
+                        error!("fail to send event")
+                    }
+                }),
+            )
+            .with_child(QOS().lens(PublicHis::qos))
+            .with_flex_child(
+                Label::dynamic(|data: &PublicHis, _: &Env| format!("{}: {}", data.topic, data.msg))
+                    .with_line_break_mode(LineBreaking::Clip)
+                    .expand_width()
+                    .on_click(move |_ctx, data: &mut PublicHis, _env| {
+                        if let Err(_) = tx_select.send(AppEvent::SelectPublicHis(id, data.id)) {
+                            error!("fail to send event")
                         }
-                    })
-                    .padding(BUTTON_PADDING)
-                    .lens(Index(id)),
-            ),
-        );
-    connection
+                    }),
+                1.0,
+            )
+            .with_child(
+                svg(connect_icon()).on_click(move |_ctx, data: &mut PublicHis, _env| {
+                    if let Err(_) = tx_publish.send(AppEvent::Public(data.to_input(), id)) {
+                        error!("fail to send event")
+                    }
+                }),
+            )
+            .with_child(
+                svg(removed_icon()).on_click(move |_ctx, data: &mut PublicHis, _env| {
+                    if let Err(_) = tx_remove.send(AppEvent::RemovePublicHis(id, data.id)) {
+                        error!("fail to send event")
+                    }
+                }),
+            )
+    });
+    Scroll::<Vector<PublicHis>, List<PublicHis>>::new(list)
+        .vertical()
+        .lens(BrokerIndexLensVecPublicHis(id))
+        .fix_width(380.)
+        .fix_height(100.)
 }
 
-pub fn init_public_input(id: usize) -> impl Widget<AppData> {
+fn init_public_schedule_list(id: usize, tx: Sender<AppEvent>) -> impl Widget<AppData> {
+    let list: List<PublicSchedule> = List::new(move || {
+        let tx = tx.clone();
+        Flex::row()
+            .with_child(svg(removed_icon()).on_click(
+                move |_ctx, data: &mut PublicSchedule, _env| {
+                    if let Err(_) = tx.send(AppEvent::StopSchedule(id, data.id)) {
+                        error!("fail to send event")
+                    }
+                },
+            ))
+            .with_child(Label::dynamic(|data: &PublicSchedule, _: &Env| {
+                if data.times > 0 {
+                    format!(
+                        "{} every {}ms: {}/{}",
+                        data.topic, data.interval, data.sent, data.times
+                    )
+                } else {
+                    format!("{} every {}ms: {}", data.topic, data.interval, data.sent)
+                }
+            }))
+            .align_left()
+    });
+    Scroll::<Vector<PublicSchedule>, List<PublicSchedule>>::new(list)
+        .vertical()
+        .lens(BrokerIndexLensVecPublicSchedule(id))
+        .fix_width(380.)
+}
+
+pub fn init_public_input(id: usize, tx: Sender<AppEvent>) -> impl Widget<AppData> {
     let connection = Flex::column()
         .with_child(
             Flex::row()
@@ -304,36 +1106,111 @@ pub fn init_public_input(id: usize) -> impl Widget<AppData> {
                 .align_left(),
         )
         .with_child(
-            Flex::row().with_child(
-                Button::new(LocalizedString::new("Publish"))
-                    .on_click(move |_ctx, data: &mut DbIndex, _env| {
-                        if let Some(broker) = data.data.public_input.get(&data.id) {
-                            if broker.topic.is_empty() || broker.msg.is_empty() {
-                                debug!("topic or msg is empty");
-                                return;
+            Flex::row()
+                .with_child(label_static("template", UnitPoint::RIGHT))
+                .with_child(
+                    TextBox::new()
+                        .with_placeholder("name of template")
+                        .lens(BrokerIndexLensPublicInput(id).then(PublicInput::template))
+                        .fix_width(300.),
+                )
+                .with_child(
+                    Checkbox::new("{{placeholder}}")
+                        .lens(BrokerIndexLensPublicInput(id).then(PublicInput::expand)),
+                )
+                .align_left(),
+        )
+        .with_child(init_public_template_list(id, tx.clone()))
+        .with_child(
+            Flex::row()
+                .with_child(label_static("repeat", UnitPoint::RIGHT))
+                .with_child(
+                    TextBox::new()
+                        .with_placeholder("interval(ms)")
+                        .with_formatter(ParseFormatter::new())
+                        .update_data_while_editing(true)
+                        .lens(BrokerIndexLensPublicInput(id).then(PublicInput::interval))
+                        .fix_width(100.),
+                )
+                .with_child(
+                    TextBox::new()
+                        .with_placeholder("times(0: unlimited)")
+                        .with_formatter(ParseFormatter::new())
+                        .update_data_while_editing(true)
+                        .lens(BrokerIndexLensPublicInput(id).then(PublicInput::times))
+                        .fix_width(100.),
+                )
+                .with_child(
+                    Button::new(LocalizedString::new("Repeat"))
+                        .on_click(move |_ctx, data: &mut DbIndex, _env| {
+                            if let Some(input) = data.data.public_input.get(&data.id) {
+                                if input.topic.is_empty() || input.msg.is_empty() {
+                                    debug!("topic or msg is empty");
+                                    return;
+                                }
+                                if let Err(e) = data
+                                    .data
+                                    .tx
+                                    .send(AppEvent::StartSchedule(input.clone(), data.id))
+                                {
+                                    error!("{:?}", e);
+                                }
+                            } else {
+                                error!("can't get the broker");
                             }
-                            if let Err(e) = data
-                                .data
-                                .db
-                                .tx
-                                .send(AppEvent::Public(broker.clone(), data.id))
-                            {
+                        })
+                        .disabled_if(|data: &DbIndex, _env| {
+                            if let Some(broker) = data.data.tab_statuses.get(&data.id) {
+                                !broker.connected
+                            } else {
+                                true
+                            }
+                        })
+                        .padding(BUTTON_PADDING)
+                        .lens(Index(id)),
+                )
+                .align_left(),
+        )
+        .with_child(init_public_schedule_list(id, tx.clone()))
+        .with_child(init_public_his_list(id, tx))
+        .with_child(
+            Flex::row()
+                .with_child(
+                    Button::new(LocalizedString::new("SaveTemplate"))
+                        .on_click(move |_ctx, data: &mut AppData, _env| {
+                            if let Err(e) = data.tx.send(AppEvent::SavePublicTemplate(id)) {
                                 error!("{:?}", e);
                             }
-                        } else {
-                            error!("can't get the broker");
-                        }
-                    })
-                    .disabled_if(|data: &DbIndex, _env| {
-                        if let Some(broker) = data.data.tab_statuses.get(&data.id) {
-                            !broker.connected
-                        } else {
-                            true
-                        }
-                    })
-                    .padding(BUTTON_PADDING)
-                    .lens(Index(id)),
-            ),
+                        })
+                        .padding(BUTTON_PADDING),
+                )
+                .with_child(
+                    Button::new(LocalizedString::new("Publish"))
+                        .on_click(move |_ctx, data: &mut DbIndex, _env| {
+                            if let Some(broker) = data.data.public_input.get(&data.id) {
+                                if broker.topic.is_empty() || broker.msg.is_empty() {
+                                    debug!("topic or msg is empty");
+                                    return;
+                                }
+                                if let Err(e) =
+                                    data.data.tx.send(AppEvent::Public(broker.clone(), data.id))
+                                {
+                                    error!("{:?}", e);
+                                }
+                            } else {
+                                error!("can't get the broker");
+                            }
+                        })
+                        .disabled_if(|data: &DbIndex, _env| {
+                            if let Some(broker) = data.data.tab_statuses.get(&data.id) {
+                                !broker.connected
+                            } else {
+                                true
+                            }
+                        })
+                        .padding(BUTTON_PADDING)
+                        .lens(Index(id)),
+                ),
         );
     connection
 }
@@ -0,0 +1,81 @@
+use crate::data::common::Environment;
+use crate::data::hierarchy::AppData;
+use crate::data::AppEvent;
+use crate::ui::common::{svg, title};
+use crate::ui::icons::removed_icon;
+use crossbeam_channel::Sender;
+use druid::im::Vector;
+use druid::theme::{BORDER_LIGHT, TEXTBOX_BORDER_WIDTH};
+use druid::widget::{Button, CrossAxisAlignment, Flex, List, Scroll, TextBox};
+use druid::{LensExt, LocalizedString, UnitPoint, Widget, WidgetExt};
+use log::error;
+
+/// the named variables, e.g. `HOST=staging.example.com`, for `${HOST}` of the brokers
+pub fn init_environments(tx: Sender<AppEvent>) -> impl Widget<AppData> {
+    let list = Scroll::<Vector<Environment>, List<Environment>>::new(List::new(move || {
+        let tx = tx.clone();
+        Flex::row()
+            .cross_axis_alignment(CrossAxisAlignment::Start)
+            .with_child(
+                TextBox::new()
+                    .lens(Environment::name)
+                    .fix_width(80.)
+                    .disabled_if(|_, _| true),
+            )
+            .with_flex_child(
+                TextBox::multiline().lens(Environment::vars).expand_width(),
+                1.0,
+            )
+            .with_child(
+                svg(removed_icon()).on_click(move |_ctx, data: &mut Environment, _env| {
+                    if let Err(_) = tx.send(AppEvent::RemoveEnvironment(data.id)) {
+                        error!("fail to send event")
+                    }
+                }),
+            )
+    }))
+    .vertical()
+    .lens(AppData::environments);
+    let buttons = Flex::row()
+        .with_flex_child(title("Environments", UnitPoint::LEFT).expand_width(), 1.0)
+        .with_child(Button::new(LocalizedString::new("Save")).on_click(
+            move |_ctx, data: &mut AppData, _env| {
+                if let Err(e) = data.tx.send(AppEvent::SaveEnvironments) {
+                    error!("{:?}", e);
+                }
+            },
+        ));
+    let input = Flex::row()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(
+            TextBox::new()
+                .with_placeholder("name")
+                .lens(AppData::env_input.then(Environment::name))
+                .fix_width(80.),
+        )
+        .with_flex_child(
+            TextBox::multiline()
+                .with_placeholder("HOST=127.0.0.1")
+                .lens(AppData::env_input.then(Environment::vars))
+                .expand_width(),
+            1.0,
+        )
+        .with_child(
+            Button::new(LocalizedString::new("AddEnvironment")).on_click(
+                move |_ctx, data: &mut AppData, _env| {
+                    if let Err(e) = data.tx.send(AppEvent::AddEnvironment) {
+                        error!("{:?}", e);
+                    }
+                },
+            ),
+        );
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(
+            buttons
+                .expand_width()
+                .border(BORDER_LIGHT, TEXTBOX_BORDER_WIDTH),
+        )
+        .with_flex_child(list.expand_width(), 1.0)
+        .with_child(input.expand_width())
+}
@@ -1,4 +1,4 @@
-use crate::data::common::QoS;
+use crate::data::common::{QoS, RetainHandling};
 use crate::data::AString;
 use crate::ui::ids::{ID_ADDR, SHOW_ERROR};
 use crate::ForError;
@@ -57,6 +57,18 @@ impl Formatter<QoS> for MustInput {
         parse_to_qos(input).to_validation_error()
     }
 }
+impl Formatter<RetainHandling> for MustInput {
+    fn format(&self, value: &RetainHandling) -> String {
+        value.to_string()
+    }
+
+    fn validate_partial_input(&self, input: &str, _sel: &Selection) -> Validation {
+        parse_to_retain_handling(input).to_validation()
+    }
+    fn value(&self, input: &str) -> Result<RetainHandling, ValidationError> {
+        parse_to_retain_handling(input).to_validation_error()
+    }
+}
 pub trait Portable<T> {
     fn to_validation(self) -> Validation;
     fn to_validation_error(self) -> Result<T, ValidationError>;
@@ -99,6 +111,17 @@ pub fn parse_to_qos(input: &str) -> Result<QoS, ForError> {
         _ => Err(ForError::InvalidQos),
     }
 }
+pub fn parse_to_retain_handling(input: &str) -> Result<RetainHandling, ForError> {
+    if input.is_empty() {
+        return Err(ForError::NotEmpty);
+    }
+    match input {
+        "0" => Ok(RetainHandling::SendAtSubscribe),
+        "1" => Ok(RetainHandling::SendAtNewSubscribe),
+        "2" => Ok(RetainHandling::NotSend),
+        _ => Err(ForError::InvalidRetainHandling),
+    }
+}
 pub fn check_no_empty(input: &str) -> bool {
     if parse_to_no_empty(input).is_err() {
         return false;
@@ -111,6 +134,12 @@ pub fn check_qos(input: &str) -> bool {
     }
     true
 }
+pub fn check_retain_handling(input: &str) -> bool {
+    if parse_to_retain_handling(input).is_err() {
+        return false;
+    }
+    true
+}
 pub fn check_addr(input: &str) -> bool {
     if parse_to_no_empty(input).is_err() {
         return false;
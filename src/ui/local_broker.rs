@@ -0,0 +1,65 @@
+use crate::data::common::{LocalBroker, RetainMsg};
+use crate::data::hierarchy::AppData;
+use crate::data::{AString, AppEvent};
+use crate::ui::common::{label_dy_expand_width, title};
+use crate::ui::formatter::MustInput;
+use druid::im::Vector;
+use druid::theme::{BORDER_LIGHT, TEXTBOX_BORDER_WIDTH};
+use druid::widget::{Button, Checkbox, CrossAxisAlignment, Flex, Label, List, Scroll, TextBox};
+use druid::{Env, LensExt, LocalizedString, UnitPoint, Widget, WidgetExt};
+use log::error;
+
+pub fn init_local_broker() -> impl Widget<AppData> {
+    let clients = Scroll::<Vector<AString>, List<AString>>::new(List::new(|| {
+        Label::dynamic(|data: &AString, _: &Env| format!("{}", data)).align_left()
+    }))
+    .vertical()
+    .lens(AppData::local_broker.then(LocalBroker::clients));
+    let retains = Scroll::<Vector<RetainMsg>, List<RetainMsg>>::new(List::new(|| {
+        Label::dynamic(|data: &RetainMsg, _: &Env| format!("{} {}B", data.topic, data.size))
+            .align_left()
+    }))
+    .vertical()
+    .lens(AppData::local_broker.then(LocalBroker::retains));
+    let buttons = Flex::row()
+        .with_flex_child(title("Local Broker", UnitPoint::LEFT).expand_width(), 1.0)
+        .with_child(
+            TextBox::new()
+                .with_placeholder("port")
+                .with_formatter(MustInput)
+                .update_data_while_editing(true)
+                .lens(AppData::local_broker.then(LocalBroker::port))
+                .fix_width(60.)
+                .disabled_if(|data: &AppData, _env| data.local_broker.running),
+        )
+        .with_child(
+            Checkbox::new("LAN")
+                .lens(AppData::local_broker.then(LocalBroker::lan))
+                .disabled_if(|data: &AppData, _env| data.local_broker.running),
+        )
+        .with_child(
+            Button::new(LocalizedString::new("Start"))
+                .on_click(move |_ctx, data: &mut AppData, _env| {
+                    let LocalBroker { port, lan, .. } = data.local_broker;
+                    if let Err(e) = data.tx.send(AppEvent::StartLocalBroker(port, lan)) {
+                        error!("{:?}", e);
+                    }
+                })
+                .disabled_if(|data: &AppData, _env| data.local_broker.running),
+        );
+    Flex::column()
+        .cross_axis_alignment(CrossAxisAlignment::Start)
+        .with_child(
+            buttons
+                .expand_width()
+                .border(BORDER_LIGHT, TEXTBOX_BORDER_WIDTH),
+        )
+        .with_child(label_dy_expand_width(|data: &AppData, _: &Env| {
+            format!("clients: {}", data.local_broker.clients.len())
+        }))
+        .with_flex_child(clients.expand_width(), 1.0)
+        .with_child(label_dy_expand_width(|data: &AppData, _: &Env| {
+            format!("retained: {}", data.local_broker.retains.len())
+        }))
+        .with_flex_child(retains.expand_width(), 1.0)
+}
@@ -10,13 +10,18 @@ use druid::{Env, UnitPoint, Widget, WidgetExt};
 
 mod broker_info;
 mod broker_list;
+mod chart;
 pub mod common;
 mod connection;
 mod debug;
+mod environment;
 pub mod formatter;
 pub mod icons;
 pub mod ids;
+mod local_broker;
+mod profile;
 pub mod tabs;
+mod vault;
 
 pub fn init_layout(tx: Sender<AppEvent>) -> impl Widget<AppData> {
     let hint = Label::dynamic(|data: &AppData, _: &Env| format!("{}", data.hint))
@@ -0,0 +1,52 @@
+use crate::data::common::ProfileInput;
+use crate::data::hierarchy::AppData;
+use crate::data::AppEvent;
+use crate::ui::common::title;
+use crate::util::hint::PROFILE_NO_PATH;
+use druid::theme::{BORDER_LIGHT, TEXTBOX_BORDER_WIDTH};
+use druid::widget::{Button, Checkbox, Flex, TextBox};
+use druid::{LensExt, LocalizedString, UnitPoint, Widget, WidgetExt};
+use log::{error, warn};
+
+/// export the selected broker or all, import the bundle, MQTTX or MQTT Explorer's connections
+pub fn init_profile() -> impl Widget<AppData> {
+    Flex::row()
+        .with_flex_child(title("Profiles", UnitPoint::LEFT).expand_width(), 1.0)
+        .with_child(
+            TextBox::new()
+                .with_placeholder("file path")
+                .lens(AppData::profile_input.then(ProfileInput::path))
+                .fix_width(120.),
+        )
+        .with_child(
+            Checkbox::new("secrets").lens(AppData::profile_input.then(ProfileInput::secrets)),
+        )
+        .with_child(Button::new(LocalizedString::new("Export")).on_click(
+            move |_ctx, data: &mut AppData, _env| {
+                send(
+                    data,
+                    AppEvent::ExportProfiles(data.profile_input.path.clone()),
+                );
+            },
+        ))
+        .with_child(Button::new(LocalizedString::new("Import")).on_click(
+            move |_ctx, data: &mut AppData, _env| {
+                send(
+                    data,
+                    AppEvent::ImportProfiles(data.profile_input.path.clone()),
+                );
+            },
+        ))
+        .expand_width()
+        .border(BORDER_LIGHT, TEXTBOX_BORDER_WIDTH)
+}
+
+fn send(data: &AppData, event: AppEvent) {
+    if data.profile_input.path.is_empty() {
+        warn!("{}", PROFILE_NO_PATH);
+        return;
+    }
+    if let Err(e) = data.tx.send(event) {
+        error!("{:?}", e);
+    }
+}
@@ -67,7 +67,7 @@ impl TabsPolicy for BrokerTabPolicy {
     }
 
     fn close_tab(&self, _key: Self::Key, _data: &mut AppData) {
-        if let Err(_) = _data.db.tx.send(AppEvent::CloseConnectionTab(self.0)) {
+        if let Err(_) = _data.tx.send(AppEvent::CloseConnectionTab(self.0)) {
             error!("fail to send event")
         }
     }
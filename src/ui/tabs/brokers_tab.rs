@@ -52,7 +52,7 @@ impl TabsPolicy for BrokersTabs {
     }
 
     fn close_tab(&self, key: Self::Key, data: &mut Self::Input) {
-        if let Err(_) = data.db.tx.send(AppEvent::CloseBrokerTab(key)) {
+        if let Err(_) = data.tx.send(AppEvent::CloseBrokerTab(key)) {
             error!("fail to send event");
         }
     }
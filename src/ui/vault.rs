@@ -0,0 +1,48 @@
+use crate::data::common::VaultInput;
+use crate::data::hierarchy::AppData;
+use crate::data::lens::Masked;
+use crate::data::AppEvent;
+use crate::ui::common::title;
+use druid::theme::{BORDER_LIGHT, TEXTBOX_BORDER_WIDTH};
+use druid::widget::{Button, Either, Flex, TextBox};
+use druid::{Env, LensExt, LocalizedString, UnitPoint, Widget, WidgetExt};
+use log::error;
+
+/// set the master password, or unlock the saved credentials at startup
+pub fn init_vault() -> impl Widget<AppData> {
+    let unlock = Button::new(LocalizedString::new("Unlock")).on_click(
+        move |_ctx, data: &mut AppData, _env| {
+            let Some(meta) = data.db.vault_meta() else {
+                error!("the master password isn't set");
+                return;
+            };
+            let master = data.vault.master.clone();
+            if let Err(e) = data.tx.send(AppEvent::UnlockVault(meta, master)) {
+                error!("{:?}", e);
+            }
+        },
+    );
+    let set = Button::new(LocalizedString::new("SetMasterPassword")).on_click(
+        move |_ctx, data: &mut AppData, _env| {
+            let master = data.vault.master.clone();
+            if let Err(e) = data.tx.send(AppEvent::SetMasterPassword(master)) {
+                error!("{:?}", e);
+            }
+        },
+    );
+    Flex::row()
+        .with_flex_child(title("Credentials", UnitPoint::LEFT).expand_width(), 1.0)
+        .with_child(
+            TextBox::new()
+                .with_placeholder("master password")
+                .lens(AppData::vault.then(VaultInput::master).then(Masked))
+                .fix_width(120.),
+        )
+        .with_child(Either::new(
+            |data: &AppData, _: &Env| data.vault.locked,
+            unlock,
+            set,
+        ))
+        .expand_width()
+        .border(BORDER_LIGHT, TEXTBOX_BORDER_WIDTH)
+}
@@ -0,0 +1,161 @@
+//! Where the db lives, in order:
+//! 1. `--data-dir <dir>`, or the env `FOR_MQTT_DATA_DIR`;
+//! 2. portable: `--portable`, or a file named `portable` next to the executable,
+//!    keeps the data in `data` next to the executable;
+//! 3. the data dir of the platform, e.g. `~/.local/share/for-mqtt` on linux.
+//!
+//! The db in `resources/db` of the old versions, in the working directory or next to the
+//! executable, is copied to the data dir once and left for the old versions.
+use anyhow::{bail, Result};
+use directories::ProjectDirs;
+use log::info;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const DATA_DIR_ENV: &str = "FOR_MQTT_DATA_DIR";
+pub const DATA_DIR_ARG: &str = "--data-dir";
+pub const PORTABLE_ARG: &str = "--portable";
+/// the db relative to the working directory or the executable before the data dir
+pub const LEGACY_DB_PATH: &str = "resources/db";
+const PORTABLE_MARKER: &str = "portable";
+const PORTABLE_DIR: &str = "data";
+
+pub fn resolve(data_dir: Option<&str>, portable: bool) -> Result<PathBuf> {
+    if let Some(dir) = data_dir {
+        return Ok(PathBuf::from(dir));
+    }
+    if let Ok(dir) = std::env::var(DATA_DIR_ENV) {
+        if !dir.is_empty() {
+            return Ok(PathBuf::from(dir));
+        }
+    }
+    let exe_dir = std::env::current_exe()?
+        .parent()
+        .map(|x| x.to_path_buf())
+        .unwrap_or_default();
+    if portable || exe_dir.join(PORTABLE_MARKER).is_file() {
+        return Ok(exe_dir.join(PORTABLE_DIR));
+    }
+    let Some(dirs) = ProjectDirs::from("", "", "for-mqtt") else {
+        bail!(
+            "can't find the data dir of the platform, try {}",
+            DATA_DIR_ARG
+        );
+    };
+    Ok(dirs.data_dir().to_path_buf())
+}
+
+/// where the old versions may have left the db: the working directory, then next to the executable
+pub fn legacy_db_paths() -> Vec<PathBuf> {
+    let mut paths = vec![PathBuf::from(LEGACY_DB_PATH)];
+    if let Some(exe_dir) = std::env::current_exe()
+        .ok()
+        .and_then(|x| x.parent().map(|x| x.join(LEGACY_DB_PATH)))
+    {
+        paths.push(exe_dir);
+    }
+    paths
+}
+
+/// `--data-dir <dir>` and `--portable` of the gui, the others are ignored
+pub fn resolve_args(mut args: impl Iterator<Item = String>) -> Result<PathBuf> {
+    let mut data_dir = None;
+    let mut portable = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            DATA_DIR_ARG => {
+                let Some(dir) = args.next() else {
+                    bail!("lack of the value of {}", DATA_DIR_ARG);
+                };
+                data_dir = Some(dir);
+            }
+            PORTABLE_ARG => portable = true,
+            _ => {}
+        }
+    }
+    resolve(data_dir.as_deref(), portable)
+}
+
+pub fn db_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("db")
+}
+
+/// copy the legacy db if the db isn't created yet. Return true if copied.
+///
+/// It is copied to a temporary dir beside the db and renamed into place, so that an
+/// interrupted copy is never taken as the db. The legacy db is kept.
+pub fn migrate_legacy(legacy: &Path, db: &Path) -> Result<bool> {
+    if db.exists() || !legacy.is_dir() {
+        return Ok(false);
+    }
+    let Some(name) = db.file_name() else {
+        bail!("invalid db path: {:?}", db);
+    };
+    let copying = db.with_file_name(format!("{}.migrating", name.to_string_lossy()));
+    if copying.exists() {
+        fs::remove_dir_all(&copying)?;
+    }
+    if let Err(e) = copy_dir(legacy, &copying) {
+        let _ = fs::remove_dir_all(&copying);
+        return Err(e);
+    }
+    fs::rename(&copying, db)?;
+    info!(
+        "copied the db from {:?} to {:?}, the old one can be removed",
+        legacy, db
+    );
+    Ok(true)
+}
+
+pub fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::util::data_dir::{copy_dir, db_path, migrate_legacy, resolve_args, DATA_DIR_ENV};
+    use crate::util::general_id;
+    use std::fs;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_data_dir() {
+        std::env::remove_var(DATA_DIR_ENV);
+        let args = ["--data-dir", "/tmp/for-mqtt", "--portable"].map(|x| x.to_string());
+        let dir = resolve_args(args.into_iter()).unwrap();
+        assert_eq!(db_path(&dir), PathBuf::from("/tmp/for-mqtt/db"));
+        let portable = resolve_args(["--portable".to_string()].into_iter()).unwrap();
+        assert!(portable.ends_with("data"));
+        assert!(resolve_args(["--data-dir".to_string()].into_iter()).is_err());
+
+        let root = std::env::temp_dir().join(format!("for-mqtt-{}", general_id()));
+        let legacy = root.join("resources/db");
+        fs::create_dir_all(legacy.join("blobs")).unwrap();
+        fs::write(legacy.join("conf"), "conf").unwrap();
+        fs::write(legacy.join("blobs/1"), "blob").unwrap();
+        let db = root.join("data/db");
+        // left by an interrupted copy
+        fs::create_dir_all(root.join("data/db.migrating/blobs")).unwrap();
+        assert!(migrate_legacy(&legacy, &db).unwrap());
+        assert_eq!(fs::read_to_string(legacy.join("conf")).unwrap(), "conf");
+        assert_eq!(fs::read_to_string(db.join("blobs/1")).unwrap(), "blob");
+        assert!(!root.join("data/db.migrating").exists());
+        // once
+        assert!(!migrate_legacy(&legacy, &db).unwrap());
+
+        let copied = root.join("copied");
+        copy_dir(&db, &copied).unwrap();
+        assert_eq!(fs::read_to_string(copied.join("conf")).unwrap(), "conf");
+        fs::remove_dir_all(root).unwrap();
+    }
+}
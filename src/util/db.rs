@@ -1,182 +1,358 @@
-use anyhow::Result;
-use crossbeam_channel::Sender;
-use sled::{Config, Db};
-use std::sync::Arc;
-
-use crate::data::common::{Broker, SubscribeHis};
-use crate::data::db::{BrokerDB, DbKey};
-use crate::data::hierarchy::AppData;
-use crate::data::AppEvent;
-use druid::im::{HashMap, Vector};
+mod key;
+mod migrate;
+
+pub use key::DbKey;
+
+use anyhow::{bail, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sled::{Batch, Config, Db};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use uuid::Uuid;
+
+use crate::util::data_dir;
+use crate::util::general_id;
+use crate::util::hint::VAULT_LOCKED;
+use crate::util::vault::{Vault, VaultKey, VaultMeta};
 use log::{debug, warn};
 
+/// The saved brokers and their lists, without any ui: the values are any serde types,
+/// e.g. `data::db::BrokerDB` of the druid frontend. The clones share the same state,
+/// so the frontend and the session see the same brokers and vault.
 #[derive(Clone, Debug)]
 pub struct ArcDb {
-    pub index: usize,
     pub db: Db,
-    pub tx: Sender<AppEvent>,
-    pub ids: Vector<usize>,
+    state: Arc<Mutex<DbState>>,
+    /// none if temporary
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+struct DbState {
+    index: usize,
+    /// the saved brokers in order: the index in the session and the key in the db
+    ids: Vec<(usize, Uuid)>,
+    /// the passwords are encrypted by the master password if enabled
+    vault: Vault,
+}
+
+/// the fields of a saved broker the db deals with, the others are kept as they are
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredBroker {
+    /// nil in the legacy layout, see `LegacyDbKey`
+    #[serde(default)]
+    uuid: Uuid,
+    /// encrypted if the vault is enabled, see `Vault::seal`
+    password: String,
+    #[serde(default = "save_password_default")]
+    save_password: bool,
+    #[serde(flatten)]
+    others: serde_json::Map<String, serde_json::Value>,
 }
 
+fn save_password_default() -> bool {
+    true
+}
+
+/// the ids of the legacy layout, see `LegacyDbKey`
 const BROKERS: &[u8; 7] = b"brokers";
+const BROKER_UUIDS: &[u8; 12] = b"broker_uuids";
+const VAULT: &[u8; 5] = b"vault";
+const ENVIRONMENTS: &[u8; 12] = b"environments";
 impl ArcDb {
-    pub fn init_db(tx: Sender<AppEvent>) -> Result<Self> {
-        let config = Config::new().path("./resources/db");
+    /// the db in the data dir, see `data_dir`
+    pub fn init_db(data_dir: &Path) -> Result<Self> {
+        let path = data_dir::db_path(data_dir);
+        for legacy in data_dir::legacy_db_paths() {
+            if data_dir::migrate_legacy(&legacy, &path)? {
+                break;
+            }
+        }
+        Self::open_path(&path)
+    }
+
+    /// the db at the path, without looking for the legacy db
+    pub fn open_path(path: &Path) -> Result<Self> {
+        let db = Config::new().path(path).open()?;
+        Self::open(db, Some(path))
+    }
+
+    /// a copy of the db in the data dir, removed when dropped, so that the db isn't changed
+    /// by reading it, e.g. by the migrations
+    pub fn open_snapshot(data_dir: &Path) -> Result<Self> {
+        let path = data_dir::db_path(data_dir);
+        if !path.is_dir() {
+            bail!("can't find the db: {:?}", path);
+        }
+        let snapshot = std::env::temp_dir().join(format!("for-mqtt-snapshot-{}", general_id()));
+        data_dir::copy_dir(&path, &snapshot)?;
+        let db = Config::new().path(&snapshot).temporary(true).open()?;
+        Self::open(db, None)
+    }
+
+    /// the db removed when dropped, for the tests
+    pub fn init_temporary_db() -> Result<Self> {
+        let db = Config::new().temporary(true).open()?;
+        Self::open(db, None)
+    }
+
+    /// migrate the db to the current version, see `migrate`
+    pub fn open(db: Db, path: Option<&Path>) -> Result<Self> {
+        migrate::migrate(&db, path)?;
+        let meta = match db.get(VAULT)? {
+            Some(val) => Some(serde_json::from_slice(&val)?),
+            None => None,
+        };
         Ok(ArcDb {
-            index: 0,
-            db: config.open()?,
-            tx,
-            ids: Default::default(),
+            db,
+            state: Arc::new(Mutex::new(DbState {
+                index: 0,
+                ids: Vec::new(),
+                vault: Vault::new(meta),
+            })),
+            path: path.map(|x| x.to_path_buf()),
         })
     }
 
-    pub fn read_app_data(&mut self) -> Result<AppData> {
-        let (db_brokers, subscribe_hises) = if let Some(val) = self.db.remove(BROKERS)? {
-            let db_brokers_ids: Vector<usize> = serde_json::from_slice(&val)?;
-            let mut brokers = Vector::new();
-            let mut subscribe_hises = HashMap::new();
-            self.index = db_brokers_ids.len();
-            debug!("{:?}", db_brokers_ids);
-            for (index, id) in db_brokers_ids.into_iter().enumerate() {
-                if let Some(val) = self.db.remove(DbKey::broker_key(id).as_bytes()?)? {
-                    let mut broker: BrokerDB = serde_json::from_slice(&val)?;
-                    broker.id = index;
-                    let hises = if let Some(val) =
-                        self.db.remove(DbKey::subscribe_his_key(id).as_bytes()?)?
-                    {
-                        let mut hises: Vector<SubscribeHis> = serde_json::from_slice(&val)?;
-                        hises = {
-                            let mut tmp = Vector::new();
-                            for mut his in hises {
-                                his.broker_id = index;
-                                tmp.push_back(his);
-                            }
-                            tmp
-                        };
-                        hises
-                    } else {
-                        Vector::new()
-                    };
-                    debug!("{:?} {:?}", broker, hises);
-                    brokers.push_back(broker);
-                    subscribe_hises.insert(index, hises);
-                    self.ids.push_back(index);
-                } else {
-                    warn!("can't find id: {}", id);
-                };
-            }
-            (brokers, subscribe_hises)
-        } else {
-            (Vector::new(), HashMap::new())
+    fn state(&self) -> MutexGuard<'_, DbState> {
+        self.state.lock().unwrap()
+    }
+
+    pub fn vault_meta(&self) -> Option<VaultMeta> {
+        self.state().vault.meta.clone()
+    }
+
+    pub fn is_vault_enabled(&self) -> bool {
+        self.state().vault.is_enabled()
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.state().vault.is_locked()
+    }
+
+    /// the passwords read while locked are empty, see `read_password` after unlocked
+    pub fn unlock(&self, master: &str) -> Result<()> {
+        let Some(meta) = self.vault_meta() else {
+            bail!("the master password isn't set");
         };
-        let mut brokers = Vector::new();
-        {
-            self.db.insert(BROKERS, serde_json::to_vec(&self.ids)?)?;
-            for (index, his_tmp) in subscribe_hises.iter() {
-                self.db.insert(
-                    DbKey::subscribe_his_key(*index).as_bytes()?,
-                    serde_json::to_vec(&his_tmp)?,
-                )?;
-            }
+        let key = meta.unlock(master)?;
+        self.unlocked(meta, key);
+        Ok(())
+    }
+
+    /// the key unlocked by the master password, see `VaultMeta::unlock`
+    pub fn unlocked(&self, meta: VaultMeta, key: VaultKey) {
+        self.state().vault.unlocked(meta, key);
+    }
 
-            for tmp_broker in db_brokers.into_iter() {
-                self.db.insert(
-                    DbKey::broker_key(tmp_broker.id).as_bytes()?,
-                    serde_json::to_vec(&tmp_broker)?,
-                )?;
-                brokers.push_back(tmp_broker.to_broker(self.tx.clone()));
+    /// set or change the master password, and encrypt the saved passwords again
+    pub fn set_vault(&self, meta: VaultMeta, key: VaultKey) -> Result<()> {
+        let mut state = self.state();
+        if state.vault.is_locked() {
+            bail!("unlock the credentials first");
+        }
+        let mut brokers = Vec::with_capacity(state.ids.len());
+        for (_, uuid) in state.ids.iter() {
+            let key = DbKey::broker_key(*uuid).as_bytes()?;
+            if let Some(mut broker) = self.read::<StoredBroker>(&key)? {
+                broker.password = state.vault.open(&broker.password)?;
+                brokers.push((key, broker));
             }
         }
-        Ok(AppData {
-            brokers,
-            broker_tabs: Default::default(),
-            tab_statuses: Default::default(),
-            subscribe_hises,
-            subscribe_topics: Default::default(),
-            msgs: Default::default(),
-            subscribe_input: Default::default(),
-            public_input: Default::default(),
-            unsubscribe_ing: Default::default(),
-            db: self.clone(),
-            hint: "".to_string().into(),
+        let mut vault = state.vault.clone();
+        vault.unlocked(meta.clone(), key);
+        let mut batch = Batch::default();
+        for (key, mut broker) in brokers {
+            broker.password = vault.seal(&broker.password)?;
+            batch.insert(key, serde_json::to_vec(&broker)?);
+        }
+        batch.insert(&VAULT[..], serde_json::to_vec(&meta)?);
+        self.db.apply_batch(batch)?;
+        state.vault = vault;
+        Ok(())
+    }
+
+    /// the backups made before the migrations, see `migrate`
+    pub fn backups(&self) -> Result<Vec<PathBuf>> {
+        match &self.path {
+            Some(path) => migrate::backups(path),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// the saved password of the broker, empty if not saved or locked
+    pub fn read_password(&self, id: usize) -> Result<String> {
+        let key = DbKey::broker_key(self.uuid(id)?);
+        Ok(match self.read::<StoredBroker>(key.as_bytes()?)? {
+            Some(broker) => self.state().vault.open(&broker.password)?,
+            None => String::new(),
         })
     }
 
-    pub fn new_broker(&mut self) -> Broker {
-        let id = self.index;
-        self.index += 1;
-        Broker {
-            id,
-            client_id: Arc::new("".to_string()),
-            name: Arc::new("".to_string()),
-            addr: Arc::new("broker-cn.emqx.io".to_string()),
-            port: 1883,
-            params: Arc::new(OPTION.to_string()),
-            use_credentials: false,
-            user_name: Arc::new("".to_string()),
-            password: Arc::new("".to_string()),
-            stored: false,
-            tx: self.tx.clone(),
-            selected: false,
+    /// the saved brokers in order with their indexes, the passwords are decrypted if unlocked
+    /// numbered again from 0 if loaded again, so the indexes given before are stale
+    pub fn load_brokers<T: DeserializeOwned>(&self) -> Result<Vec<(usize, Uuid, T)>> {
+        let uuids: Vec<Uuid> = self.read(BROKER_UUIDS)?.unwrap_or_default();
+        debug!("{:?}", uuids);
+        let mut state = self.state();
+        state.index = 0;
+        state.ids.clear();
+        let mut brokers = Vec::with_capacity(uuids.len());
+        for uuid in uuids {
+            if state.ids.iter().any(|x| x.1 == uuid) {
+                warn!("duplicate broker: {}", uuid);
+                continue;
+            }
+            let Some(mut broker) =
+                self.read::<StoredBroker>(DbKey::broker_key(uuid).as_bytes()?)?
+            else {
+                warn!("can't find broker: {}", uuid);
+                continue;
+            };
+            let index = state.index;
+            state.index += 1;
+            broker.password = state.vault.open(&broker.password)?;
+            brokers.push((
+                index,
+                uuid,
+                serde_json::from_value(serde_json::to_value(broker)?)?,
+            ));
+            state.ids.push((index, uuid));
         }
+        // drop the brokers not found or duplicate
+        self.update_ids(&state)?;
+        Ok(brokers)
+    }
+
+    /// the value saved under the key of a broker, e.g. `DbKey::subscribe_his_key`
+    pub fn read_of<T: DeserializeOwned + Default>(&self, key: DbKey) -> Result<T> {
+        Ok(self.read(key.as_bytes()?)?.unwrap_or_default())
+    }
+
+    pub fn read_environments<T: DeserializeOwned + Default>(&self) -> Result<T> {
+        Ok(self.read(ENVIRONMENTS)?.unwrap_or_default())
+    }
+
+    fn read<T: DeserializeOwned>(&self, key: impl AsRef<[u8]>) -> Result<Option<T>> {
+        Ok(match self.db.get(key)? {
+            Some(val) => Some(serde_json::from_slice(&val)?),
+            None => None,
+        })
+    }
+
+    fn uuid(&self, id: usize) -> Result<Uuid> {
+        let Some((_, uuid)) = self.state().ids.iter().find(|x| x.0 == id).copied() else {
+            bail!("the broker isn't saved: {}", id);
+        };
+        Ok(uuid)
     }
 
-    pub fn save_broker(&mut self, id: usize, broker: &Broker) -> Result<()> {
-        debug!("save broker: {:?}", broker);
-        if self.ids.iter().find(|x| **x == id).is_none() {
-            self.ids.push_back(id);
-            self.db.insert(BROKERS, serde_json::to_vec(&self.ids)?)?;
+    /// the index in the session of a new broker
+    pub fn next_index(&self) -> usize {
+        let mut state = self.state();
+        state.index += 1;
+        state.index - 1
+    }
+
+    /// the broker is any serde type with the fields of `StoredBroker`, the password in plain
+    pub fn save_broker(&self, id: usize, broker: &impl Serialize) -> Result<()> {
+        let mut broker: StoredBroker = serde_json::from_value(serde_json::to_value(broker)?)?;
+        debug!("save broker: {:?}", broker.others);
+        let mut state = self.state();
+        // the password edited can't be encrypted until unlocked
+        if state.vault.is_locked() && broker.save_password && !broker.password.is_empty() {
+            bail!("{}", VAULT_LOCKED);
+        }
+        // the uuid is listed once, with the index of the last load
+        if let Some(saved) = state.ids.iter_mut().find(|x| x.1 == broker.uuid) {
+            saved.0 = id;
+        } else if state.ids.iter().find(|x| x.0 == id).is_none() {
+            state.ids.push((id, broker.uuid));
+            self.update_ids(&state)?;
         }
-        self.db.insert(
-            DbKey::broker_key(id).as_bytes()?,
-            serde_json::to_vec(&broker.clone_to_db())?,
-        )?;
+        let key = DbKey::broker_key(broker.uuid).as_bytes()?;
+        broker.password = if !broker.save_password {
+            "".to_string()
+        } else if state.vault.is_locked() {
+            // keep the saved secret
+            match self.read::<StoredBroker>(&key)? {
+                Some(saved) => saved.password,
+                None => "".to_string(),
+            }
+        } else {
+            state.vault.seal(&broker.password)?
+        };
+        self.db.insert(key, serde_json::to_vec(&broker)?)?;
         Ok(())
     }
-    pub fn delete_broker(&mut self, id: usize) -> Result<()> {
-        let mut selected_index = None;
-        for (index, broker) in self.ids.iter().enumerate() {
-            if *broker == id {
-                selected_index = Some(index);
-                break;
+    pub fn delete_broker(&self, id: usize) -> Result<()> {
+        let mut state = self.state();
+        if let Some(index) = state.ids.iter().position(|x| x.0 == id) {
+            let (_, uuid) = state.ids.remove(index);
+            self.update_ids(&state)?;
+            for key in DbKey::broker_keys(uuid) {
+                self.db.remove(key.as_bytes()?)?;
             }
-        }
-        if let Some(index) = selected_index {
-            self.ids.remove(index);
-            self.update_ids()?;
-            self.db.remove(DbKey::broker_key(id).as_bytes()?)?;
         } else {
             warn!("not selected broker to delete");
         }
         Ok(())
     }
+    /// move the broker to the position of the other, see `AppData::move_broker`
+    pub fn move_broker(&self, from: usize, to: usize) -> Result<()> {
+        let mut state = self.state();
+        let (Some(from_index), Some(to_index)) = (
+            state.ids.iter().position(|x| x.0 == from),
+            state.ids.iter().position(|x| x.0 == to),
+        ) else {
+            bail!("the broker isn't saved: {} {}", from, to);
+        };
+        let id = state.ids.remove(from_index);
+        state.ids.insert(to_index, id);
+        self.update_ids(&state)
+    }
     #[inline]
-    fn update_ids(&self) -> Result<()> {
-        self.db.insert(BROKERS, serde_json::to_vec(&self.ids)?)?;
+    fn update_ids(&self, state: &DbState) -> Result<()> {
+        let uuids: Vec<Uuid> = state.ids.iter().map(|x| x.1).collect();
+        self.db.insert(BROKER_UUIDS, serde_json::to_vec(&uuids)?)?;
+        Ok(())
+    }
+    fn update_of(&self, key: DbKey, val: &impl Serialize) -> Result<()> {
+        self.db.insert(key.as_bytes()?, serde_json::to_vec(val)?)?;
         Ok(())
     }
-    pub fn update_subscribe_his(&self, id: usize, hises: &Vector<SubscribeHis>) -> Result<()> {
-        let key = DbKey::subscribe_his_key(id);
+    pub fn update_subscribe_his(&self, id: usize, hises: &impl Serialize) -> Result<()> {
+        self.update_of(DbKey::subscribe_his_key(self.uuid(id)?), hises)
+    }
+    pub fn update_public_templates(&self, id: usize, templates: &impl Serialize) -> Result<()> {
+        self.update_of(DbKey::public_template_key(self.uuid(id)?), templates)
+    }
+    pub fn update_public_his(&self, id: usize, hises: &impl Serialize) -> Result<()> {
+        self.update_of(DbKey::public_his_key(self.uuid(id)?), hises)
+    }
+    pub fn update_script(&self, id: usize, script: &impl Serialize) -> Result<()> {
+        self.update_of(DbKey::script_key(self.uuid(id)?), script)
+    }
+    pub fn update_environments(&self, environments: &impl Serialize) -> Result<()> {
         self.db
-            .insert(key.as_bytes()?, serde_json::to_vec(hises)?)?;
+            .insert(ENVIRONMENTS, serde_json::to_vec(environments)?)?;
         Ok(())
     }
+    pub fn update_rules(&self, id: usize, rules: &impl Serialize) -> Result<()> {
+        self.update_of(DbKey::rules_key(self.uuid(id)?), rules)
+    }
 }
 
-const OPTION: &str = r#"{
-	"keep_alive": 60,
-	"clean_session": true,
-	"max_incoming_packet_size": 10240,
-	"max_outgoing_packet_size": 10240,
-	"inflight": 100,
-	"conn_timeout": 5
-}
-        "#;
-
 #[cfg(test)]
 mod test {
     use crate::data::common::Broker;
-    use crate::util::db::BROKERS;
+    use crate::data::db::BrokerDB;
+    use crate::data::hierarchy::AppData;
+    use crate::util::data_dir;
+    use crate::util::db::{ArcDb, DbKey, BROKERS, BROKER_UUIDS};
+    use crate::util::general_id;
+    use crate::util::vault::{is_encrypted, VaultMeta};
     use druid::im::vector;
     use sled::Config;
     use std::sync::Arc;
@@ -195,4 +371,134 @@ mod test {
         // let broker = serde_json::to_vec(&broker).unwrap();
         // db.insert(BROKERS, broker).unwrap();
     }
+
+    #[test]
+    fn test_open_snapshot() {
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let dir = std::env::temp_dir().join(format!("for-mqtt-{}", general_id()));
+        {
+            let db = ArcDb::open_path(&data_dir::db_path(&dir)).unwrap();
+            let mut data = AppData::load(db.clone(), tx.clone()).unwrap();
+            data.add_broker();
+            let id = data.brokers[0].id;
+            data.save_broker(id).unwrap();
+            data.db.db.flush().unwrap();
+        }
+        let snapshot = ArcDb::open_snapshot(&dir).unwrap();
+        let mut data = AppData::load(snapshot.clone(), tx.clone()).unwrap();
+        assert_eq!(data.brokers.len(), 1);
+        data.add_broker();
+        let id = data.brokers[1].id;
+        data.save_broker(id).unwrap();
+        drop(data);
+        drop(snapshot);
+
+        let db = ArcDb::open_path(&data_dir::db_path(&dir)).unwrap();
+        assert_eq!(
+            AppData::load(db.clone(), tx.clone()).unwrap().brokers.len(),
+            1
+        );
+        drop(db);
+        assert!(ArcDb::open_snapshot(&dir.join("none")).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_stable_uuid() {
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let db = ArcDb::init_temporary_db().unwrap();
+        let mut data = AppData::load(db.clone(), tx.clone()).unwrap();
+        data.add_broker();
+        data.add_broker();
+        data.save_broker(0).unwrap();
+        data.save_broker(1).unwrap();
+        let uuids: Vec<_> = data.brokers.iter().map(|x| x.uuid).collect();
+        data.db.delete_broker(0).unwrap();
+        for key in DbKey::broker_keys(uuids[0]) {
+            assert!(db.db.get(key.as_bytes().unwrap()).unwrap().is_none());
+        }
+
+        // the next start keeps the uuid, not the index
+        let restarted = ArcDb::open(db.db.clone(), None).unwrap();
+        let mut data = AppData::load(restarted.clone(), tx.clone()).unwrap();
+        assert_eq!(data.brokers.len(), 1);
+        assert_eq!((data.brokers[0].id, data.brokers[0].uuid), (0, uuids[1]));
+        assert!(!uuids.contains(&data.new_broker().uuid));
+        data.db.delete_broker(0).unwrap();
+        assert_eq!(db.db.get(BROKER_UUIDS).unwrap().unwrap().as_ref(), b"[]");
+    }
+
+    #[test]
+    fn test_load_again() {
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let db = ArcDb::init_temporary_db().unwrap();
+        let mut data = AppData::load(db.clone(), tx.clone()).unwrap();
+        data.add_broker();
+        data.add_broker();
+        data.save_broker(0).unwrap();
+        data.save_broker(1).unwrap();
+        let uuids: Vec<_> = data.brokers.iter().map(|x| x.uuid).collect();
+        let saved = || -> Vec<uuid::Uuid> {
+            serde_json::from_slice(&db.db.get(BROKER_UUIDS).unwrap().unwrap()).unwrap()
+        };
+
+        // the clones share the state, which is loaded again from the start
+        let mut data = AppData::load(db.clone(), tx.clone()).unwrap();
+        let ids: Vec<_> = data.brokers.iter().map(|x| (x.id, x.uuid)).collect();
+        assert_eq!(ids, vec![(0, uuids[0]), (1, uuids[1])]);
+        data.save_broker(1).unwrap();
+        assert_eq!(saved(), uuids);
+        assert_eq!(data.new_broker().id, 2);
+    }
+
+    #[test]
+    fn test_vault() {
+        let (tx, _rx) = crossbeam_channel::unbounded();
+        let db = ArcDb::init_temporary_db().unwrap();
+        let mut data = AppData::load(db.clone(), tx.clone()).unwrap();
+        data.add_broker();
+        data.add_broker();
+        for broker in data.brokers.iter_mut() {
+            broker.use_credentials = true;
+            broker.password = "public".to_string().into();
+        }
+        data.brokers[1].save_password = false;
+        data.save_broker(0).unwrap();
+        data.save_broker(1).unwrap();
+        let uuids: Vec<_> = data.brokers.iter().map(|x| x.uuid).collect();
+        let saved = |db: &ArcDb, id: usize| -> BrokerDB {
+            let key = DbKey::broker_key(uuids[id]);
+            let val = db.db.get(key.as_bytes().unwrap()).unwrap().unwrap();
+            serde_json::from_slice(&val).unwrap()
+        };
+        assert_eq!(saved(&data.db, 0).password.as_str(), "public");
+        assert_eq!(saved(&data.db, 1).password.as_str(), "");
+
+        let (meta, key) = VaultMeta::create("master").unwrap();
+        data.vault_created(meta, key).unwrap();
+        assert!(is_encrypted(&saved(&data.db, 0).password));
+        assert_eq!(data.db.read_password(0).unwrap(), "public");
+
+        // locked at the next start
+        let restarted = ArcDb::open(db.db.clone(), None).unwrap();
+        let mut locked = AppData::load(restarted.clone(), tx.clone()).unwrap();
+        assert!(locked.vault.locked);
+        assert_eq!(locked.brokers[0].password.as_str(), "");
+        assert!(!locked.brokers[1].save_password);
+        // saving while locked keeps the secret
+        locked.save_broker(0).unwrap();
+        assert!(is_encrypted(&saved(&locked.db, 0).password));
+        // the password edited while locked can't be encrypted
+        locked.brokers[0].password = "edited".to_string().into();
+        assert!(locked.save_broker(0).is_err());
+        locked.brokers[0].password = Default::default();
+
+        assert!(locked.db.unlock("wrong").is_err());
+        let meta = locked.db.vault_meta().unwrap();
+        let key = meta.unlock("master").unwrap();
+        locked.unlock_vault(meta, key).unwrap();
+        assert!(!locked.vault.locked);
+        assert_eq!(locked.brokers[0].password.as_str(), "public");
+        assert_eq!(locked.brokers[1].password.as_str(), "");
+    }
 }
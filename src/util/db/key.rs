@@ -0,0 +1,75 @@
+//! The keys of the db: everything of a broker is saved under its uuid.
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum DbKey {
+    Broker(Uuid),
+    SubscribeHis(Uuid),
+    PublicTemplate(Uuid),
+    Script(Uuid),
+    Rules(Uuid),
+    PublicHis(Uuid),
+}
+
+impl DbKey {
+    pub fn broker_key(id: Uuid) -> Self {
+        Self::Broker(id)
+    }
+    pub fn subscribe_his_key(id: Uuid) -> Self {
+        Self::SubscribeHis(id)
+    }
+    pub fn public_template_key(id: Uuid) -> Self {
+        Self::PublicTemplate(id)
+    }
+    pub fn script_key(id: Uuid) -> Self {
+        Self::Script(id)
+    }
+    pub fn rules_key(id: Uuid) -> Self {
+        Self::Rules(id)
+    }
+    pub fn public_his_key(id: Uuid) -> Self {
+        Self::PublicHis(id)
+    }
+    /// the keys of everything of the broker
+    pub fn broker_keys(id: Uuid) -> [Self; 6] {
+        [
+            Self::Broker(id),
+            Self::SubscribeHis(id),
+            Self::PublicTemplate(id),
+            Self::Script(id),
+            Self::Rules(id),
+            Self::PublicHis(id),
+        ]
+    }
+    pub fn as_bytes(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(self)?)
+    }
+}
+
+/// the keys before the brokers got uuids: the index of the broker, renumbered at every start
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum LegacyDbKey {
+    Broker(usize),
+    SubscribeHis(usize),
+    PublicTemplate(usize),
+    Script(usize),
+    Rules(usize),
+}
+
+impl LegacyDbKey {
+    /// the keys of everything of the broker, in the same order as `DbKey::broker_keys`
+    pub fn broker_keys(id: usize) -> [Self; 5] {
+        [
+            Self::Broker(id),
+            Self::SubscribeHis(id),
+            Self::PublicTemplate(id),
+            Self::Script(id),
+            Self::Rules(id),
+        ]
+    }
+    pub fn as_bytes(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(self)?)
+    }
+}
@@ -0,0 +1,218 @@
+//! The versions of the layout of the db and the migrations between them:
+//!
+//! - 0: `brokers` lists the indexes of the brokers, the keys are `LegacyDbKey`
+//! - 1: `broker_uuids` lists the uuids of the brokers, the keys are `DbKey`
+//!
+//! The version is kept under `schema_version`, the dbs before it are recognized by their keys.
+//! Before migrating, the db is copied to `<path>.backup-v<version>-<millis>`,
+//! then every migration is applied in one batch with its version.
+use super::key::{DbKey, LegacyDbKey};
+use super::{StoredBroker, BROKERS, BROKER_UUIDS};
+use crate::util::now_millis;
+use anyhow::{bail, Result};
+use log::{info, warn};
+use sled::{Batch, Config, Db};
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+pub const SCHEMA_VERSION: u32 = 1;
+const SCHEMA_VERSION_KEY: &[u8; 14] = b"schema_version";
+
+/// the changes from the layout of a version to the next one
+type Migration = fn(&Db) -> Result<Batch>;
+
+/// the migration of the index is from the version `index` to `index + 1`
+const MIGRATIONS: [Migration; SCHEMA_VERSION as usize] = [broker_uuids];
+
+/// bring the db to `SCHEMA_VERSION`, backup it to the directory of `path` before if any
+pub fn migrate(db: &Db, path: Option<&Path>) -> Result<()> {
+    let Some(version) = version(db)? else {
+        db.insert(SCHEMA_VERSION_KEY, serde_json::to_vec(&SCHEMA_VERSION)?)?;
+        return Ok(());
+    };
+    if version > SCHEMA_VERSION {
+        bail!(
+            "the version {} of the db is newer than the version {} of the app",
+            version,
+            SCHEMA_VERSION
+        );
+    }
+    if version == SCHEMA_VERSION {
+        return Ok(());
+    }
+    if let Some(path) = path {
+        backup(db, path, version)?;
+    }
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let to = from as u32 + 1;
+        let mut batch = migration(db)?;
+        batch.insert(SCHEMA_VERSION_KEY.as_slice(), serde_json::to_vec(&to)?);
+        db.apply_batch(batch)?;
+        info!("migrated the db from version {} to {}", from, to);
+    }
+    db.flush()?;
+    Ok(())
+}
+
+/// none if the db is new
+pub fn version(db: &Db) -> Result<Option<u32>> {
+    if let Some(val) = db.get(SCHEMA_VERSION_KEY)? {
+        return Ok(Some(serde_json::from_slice(&val)?));
+    }
+    Ok(if db.is_empty() {
+        None
+    } else if db.contains_key(BROKER_UUIDS)? {
+        Some(1)
+    } else {
+        Some(0)
+    })
+}
+
+/// the backups beside the db at the path
+pub fn backups(path: &Path) -> Result<Vec<PathBuf>> {
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        return Ok(Vec::new());
+    };
+    let prefix = format!("{}.backup-v", name.to_string_lossy());
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_name().to_string_lossy().starts_with(&prefix) {
+            backups.push(entry.path());
+        }
+    }
+    Ok(backups)
+}
+
+fn backup(db: &Db, path: &Path, version: u32) -> Result<()> {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".backup-v{}-{}", version, now_millis()));
+    let backup_path = path.with_file_name(name);
+    let backup = Config::new().path(&backup_path).open()?;
+    for kv in db.iter() {
+        let (key, val) = kv?;
+        backup.insert(key, val)?;
+    }
+    backup.flush()?;
+    info!("backup the db of version {} to {:?}", version, backup_path);
+    Ok(())
+}
+
+/// 0 -> 1: move the brokers to the keys of new uuids
+fn broker_uuids(db: &Db) -> Result<Batch> {
+    let legacy_ids: Vec<usize> = match db.get(BROKERS)? {
+        Some(val) => serde_json::from_slice(&val)?,
+        None => Vec::new(),
+    };
+    let mut uuids = Vec::new();
+    let mut batch = Batch::default();
+    for id in legacy_ids {
+        let legacy_keys = LegacyDbKey::broker_keys(id);
+        let Some(val) = db.get(legacy_keys[0].as_bytes()?)? else {
+            warn!("can't find legacy broker: {}", id);
+            continue;
+        };
+        let mut broker: StoredBroker = serde_json::from_slice(&val)?;
+        let uuid = Uuid::new_v4();
+        broker.uuid = uuid;
+        batch.insert(
+            DbKey::broker_key(uuid).as_bytes()?,
+            serde_json::to_vec(&broker)?,
+        );
+        for (legacy_key, key) in legacy_keys.iter().zip(DbKey::broker_keys(uuid)).skip(1) {
+            if let Some(val) = db.get(legacy_key.as_bytes()?)? {
+                batch.insert(key.as_bytes()?, val);
+            }
+        }
+        for legacy_key in legacy_keys.iter() {
+            batch.remove(legacy_key.as_bytes()?);
+        }
+        uuids.push(uuid);
+    }
+    batch.insert(BROKER_UUIDS.as_slice(), serde_json::to_vec(&uuids)?);
+    batch.remove(BROKERS.as_slice());
+    Ok(batch)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::data::hierarchy::AppData;
+    use crate::util::db::key::LegacyDbKey;
+    use crate::util::db::migrate::{backups, migrate, version, SCHEMA_VERSION, SCHEMA_VERSION_KEY};
+    use crate::util::db::{ArcDb, BROKERS};
+    use crate::util::now_millis;
+    use sled::{Config, Db};
+    use std::fs;
+
+    /// fixtures of every version: the keys and the json values
+    const FIXTURES: [&str; SCHEMA_VERSION as usize + 1] = [
+        include_str!("../../../resources/fixtures/db-v0.json"),
+        include_str!("../../../resources/fixtures/db-v1.json"),
+    ];
+
+    fn load_fixture(fixture: &str) -> Db {
+        let db = Config::new().temporary(true).open().unwrap();
+        let entries: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_str(fixture).unwrap();
+        for (key, val) in entries {
+            db.insert(key.as_bytes(), serde_json::to_vec(&val).unwrap())
+                .unwrap();
+        }
+        db
+    }
+
+    #[test]
+    fn test_fixtures() {
+        for (fixture_version, fixture) in FIXTURES.iter().enumerate() {
+            let db = load_fixture(fixture);
+            assert_eq!(version(&db).unwrap(), Some(fixture_version as u32));
+            let (tx, _rx) = crossbeam_channel::unbounded();
+            let data = AppData::load(ArcDb::open(db.clone(), None).unwrap(), tx).unwrap();
+            assert_eq!(version(&db).unwrap(), Some(SCHEMA_VERSION));
+            let names: Vec<&str> = data.brokers.iter().map(|x| x.name.as_str()).collect();
+            assert_eq!(names, ["emq", "local"], "fixture {}", fixture_version);
+            assert!(data.brokers.iter().all(|x| !x.uuid.is_nil()));
+            assert!(data.brokers[1].use_credentials);
+            assert_eq!(data.subscribe_hises[&0].len(), 2);
+            assert_eq!(data.public_templates[&0][0].name.as_str(), "ping");
+            assert!(data.scripts[&0].enabled);
+            assert_eq!(data.rules[&0].len(), 1);
+            assert!(data.subscribe_hises[&1].is_empty());
+            assert!(db.get(BROKERS).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn test_backup_and_newer() {
+        let path = std::env::temp_dir().join(format!("for-mqtt-test-{}", now_millis()));
+        let db = Config::new().path(&path).open().unwrap();
+        for (key, val) in load_fixture(FIXTURES[0]).iter().map(|x| x.unwrap()) {
+            db.insert(key, val).unwrap();
+        }
+        migrate(&db, Some(&path)).unwrap();
+        let backups = backups(&path).unwrap();
+        assert_eq!(backups.len(), 1);
+        let backup_path = backups[0].clone();
+        let backup_name = backup_path.file_name().unwrap().to_string_lossy();
+        assert!(backup_name.contains(".backup-v0-"));
+        let backup = Config::new().path(&backup_path).open().unwrap();
+        assert!(backup.contains_key(BROKERS).unwrap());
+        assert!(backup
+            .contains_key(LegacyDbKey::Broker(0).as_bytes().unwrap())
+            .unwrap());
+        drop((db, backup));
+        fs::remove_dir_all(&path).unwrap();
+        fs::remove_dir_all(&backup_path).unwrap();
+
+        let db = Config::new().temporary(true).open().unwrap();
+        migrate(&db, None).unwrap();
+        assert_eq!(version(&db).unwrap(), Some(SCHEMA_VERSION));
+        db.insert(
+            SCHEMA_VERSION_KEY,
+            serde_json::to_vec(&(SCHEMA_VERSION + 1)).unwrap(),
+        )
+        .unwrap();
+        assert!(migrate(&db, None).is_err());
+    }
+}
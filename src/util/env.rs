@@ -0,0 +1,85 @@
+use crate::data::common::{Broker, Environment};
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+
+/// The variables of the environment: `NAME=value` per line, `#` starts a comment line.
+pub fn parse_vars(vars: &str) -> Result<HashMap<String, String>> {
+    let mut rs = HashMap::new();
+    for line in vars.lines().map(|x| x.trim()) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((name, value)) = line.split_once('=') else {
+            bail!("the variable should be NAME=value: {}", line);
+        };
+        rs.insert(name.trim().to_string(), value.trim().to_string());
+    }
+    Ok(rs)
+}
+
+/// replace `${NAME}` with the variable, the unknown and not closed ones are kept as they are,
+/// e.g. a password containing `${`
+pub fn substitute(text: &str, vars: &HashMap<String, String>) -> String {
+    let mut rs = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        rs.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        let var = &rest[start..start + end + 1];
+        match vars.get(var[2..var.len() - 1].trim()) {
+            Some(value) => rs.push_str(value),
+            None => rs.push_str(var),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    rs.push_str(rest);
+    rs
+}
+
+/// whether the text refers to a variable
+pub fn has_var(text: &str) -> bool {
+    text.find("${")
+        .map_or(false, |start| text[start..].contains('}'))
+}
+
+/// the broker to connect: the variables of its environment substituted.
+/// Nothing is substituted without the environment.
+pub fn resolve(broker: &Broker, env: Option<&Environment>) -> Result<Broker> {
+    let Some(env) = env else {
+        return Ok(broker.clone());
+    };
+    let vars = parse_vars(&env.vars)?;
+    let mut broker = broker.clone();
+    broker.client_id = substitute(&broker.client_id, &vars).into();
+    broker.addr = substitute(&broker.addr, &vars).into();
+    broker.user_name = substitute(&broker.user_name, &vars).into();
+    broker.password = substitute(&broker.password, &vars).into();
+    broker.params = substitute(&broker.params, &vars).into();
+    Ok(broker)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::util::env::{has_var, parse_vars, substitute};
+
+    #[test]
+    fn test_substitute() {
+        let vars = parse_vars("# staging\nHOST = staging.example.com\n\nTENANT=t1\n").unwrap();
+        assert_eq!(vars.len(), 2);
+        assert_eq!(
+            substitute("${HOST}:${ TENANT }/a", &vars),
+            "staging.example.com:t1/a"
+        );
+        assert_eq!(substitute("{{seq}} $HOST", &vars), "{{seq}} $HOST");
+        assert_eq!(
+            substitute("${PORT}-${HOST}", &vars),
+            "${PORT}-staging.example.com"
+        );
+        assert_eq!(substitute("pa${ss", &vars), "pa${ss");
+        assert!(parse_vars("HOST").is_err());
+        assert!(has_var("dev-${TENANT}"));
+        assert!(!has_var("dev-${TENANT"));
+    }
+}
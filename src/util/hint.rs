@@ -6,7 +6,42 @@ pub const DELETE_SUBSCRIBE_NO_SELECTED: &str = "there is no selected history!";
 pub const SAVE_BROKER_SUCCESS: &str = "save success!";
 pub const CONNECT_SUCCESS: &str = "connect success!";
 pub const DISCONNECT_SUCCESS: &str = "disconnect success!";
+pub const CONNECTION_LOST: &str = "the connection is lost!";
 pub const SUBSCRIBE_SUCCESS: &str = "subscribe success!";
 pub const PUBLISH_SUCCESS: &str = "publish success!";
 
 pub const UNSUBSCRIBE_SUCCESS: &str = "unsubscribe success!";
+
+pub const SAVE_TEMPLATE_SUCCESS: &str = "save template success!";
+pub const SAVE_TEMPLATE_NO_NAME: &str = "please input the name of template!";
+pub const DELETE_TEMPLATE_SUCCESS: &str = "delete template success!";
+
+pub const SCHEDULE_NO_INTERVAL: &str = "the interval of repeat should be more than 0!";
+pub const RETAIN_SCAN_END: &str = "retained messages collected";
+pub const RETAIN_NONE_SELECTED: &str = "no retained message is selected!";
+pub const RETAIN_CLEAR_PREVIEW: &str = "confirm to clear the retained messages";
+pub const RETAIN_CLEAR_SUCCESS: &str = "retained messages cleared";
+pub const REQUEST_TIMEOUT: &str = "the request is not replied in time!";
+pub const PROBE_NO_INTERVAL: &str = "the interval of probe should be more than 0!";
+pub const LOCAL_BROKER_STARTED: &str = "local broker started!";
+pub const BRIDGE_NOT_CONNECTED: &str = "both brokers of the bridge should be connected!";
+pub const ADD_RULE_SUCCESS: &str = "add rule success!";
+pub const DELETE_RULE_SUCCESS: &str = "delete rule success!";
+pub const RULE_FIRED: &str = "rule fired";
+pub const SCRIPT_LOADED: &str = "script loaded!";
+pub const SCRIPT_ERROR: &str = "script error, see the console!";
+pub const VAULT_SET: &str = "master password set, the saved passwords are encrypted! \
+    The old ones in clear may stay in the files of the db until sled reclaims the space.";
+pub const VAULT_UNLOCKED: &str = "saved credentials unlocked!";
+pub const VAULT_WRONG_PASSWORD: &str = "wrong master password!";
+pub const VAULT_LOCKED: &str = "unlock the saved credentials first!";
+pub const PASSWORD_REQUIRED: &str = "the password isn't saved, please input it!";
+pub const VAULT_PLAINTEXT_BACKUPS: &str =
+    "the backups of the db keep the passwords in clear, remove them if not needed:";
+pub const EXPORT_PROFILES_SUCCESS: &str = "export success!";
+pub const IMPORT_PROFILES_SUCCESS: &str = "import success:";
+pub const PROFILE_NO_PATH: &str = "please input the path of the file!";
+pub const DUPLICATE_BROKER_SUCCESS: &str = "duplicate success!";
+pub const ENVIRONMENT_NO_NAME: &str = "please input the name of environment!";
+pub const SAVE_ENVIRONMENTS_SUCCESS: &str = "save environments success!";
+pub const SAVE_SUBSCRIBE_HIS_SUCCESS: &str = "save the note of history success!";
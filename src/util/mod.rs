@@ -1,9 +1,15 @@
+use chrono::{Local, TimeZone};
 use rand::prelude::SliceRandom;
 use rand::thread_rng;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub mod custom_logger;
+pub mod data_dir;
 pub mod db;
+pub mod env;
 pub mod hint;
+pub mod template;
+pub mod vault;
 
 pub const ID_CHARS: [char; 62] = [
     'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r', 's',
@@ -21,9 +27,24 @@ pub fn general_id() -> String {
     id
 }
 
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// `YYYY-MM-DD HH:MM:SS.mmm` in the local time
+pub fn format_millis(millis: u64) -> String {
+    match Local.timestamp_millis_opt(millis as i64).single() {
+        Some(time) => time.format("%Y-%m-%d %H:%M:%S%.3f").to_string(),
+        None => millis.to_string(),
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use crate::util::general_id;
+    use crate::util::{format_millis, general_id};
 
     #[test]
     fn test() {
@@ -32,4 +53,13 @@ mod test {
         println!("{}", general_id());
         println!("{}", general_id());
     }
+
+    #[test]
+    fn test_format_millis() {
+        // 2023-01-02 03:04:05.678 in UTC
+        let text = format_millis(1672628645678);
+        assert_eq!(text.len(), "2023-01-02 03:04:05.678".len());
+        assert!(text.starts_with("2023-01-0"));
+        assert!(text.ends_with(":05.678"));
+    }
 }
@@ -0,0 +1,100 @@
+use crate::mqtt::data::MqttPublicInput;
+use crate::util::now_millis;
+use anyhow::{bail, Result};
+use rand::{thread_rng, Rng};
+
+/// Placeholders supported in the topic and payload of a publish which enables `expand`:
+/// `{{timestamp}}`, `{{uuid}}`, `{{seq}}`, `{{random_int(min,max)}}`, `{{client_id}}`
+#[derive(Debug, Clone, Default)]
+pub struct TemplateContext {
+    pub client_id: String,
+    pub seq: u64,
+}
+
+impl TemplateContext {
+    pub fn new(client_id: String) -> Self {
+        Self { client_id, seq: 0 }
+    }
+
+    /// expand topic and msg with the same `seq`
+    pub fn expand_input(&mut self, input: MqttPublicInput) -> Result<MqttPublicInput> {
+        self.seq += 1;
+        let topic = self.expand(input.topic.as_str())?;
+        let msg = self.expand(input.msg.as_str())?;
+        Ok(MqttPublicInput {
+            topic,
+            msg,
+            ..input
+        })
+    }
+
+    pub fn expand(&self, template: &str) -> Result<String> {
+        let mut rs = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find("{{") {
+            rs.push_str(&rest[..start]);
+            let Some(end) = rest[start..].find("}}") else {
+                bail!("not closed placeholder: {}", &rest[start..]);
+            };
+            let placeholder = rest[start + 2..start + end].trim();
+            rs.push_str(&self.value_of(placeholder)?);
+            rest = &rest[start + end + 2..];
+        }
+        rs.push_str(rest);
+        Ok(rs)
+    }
+
+    fn value_of(&self, placeholder: &str) -> Result<String> {
+        Ok(match placeholder {
+            "timestamp" => now_millis().to_string(),
+            "uuid" => uuid::Uuid::new_v4().to_string(),
+            "seq" => self.seq.to_string(),
+            "client_id" => self.client_id.clone(),
+            _ => {
+                if let Some(args) = placeholder
+                    .strip_prefix("random_int(")
+                    .and_then(|x| x.strip_suffix(')'))
+                {
+                    let Some((min, max)) = args.split_once(',') else {
+                        bail!("random_int need 2 args: {}", placeholder);
+                    };
+                    let (min, max): (i64, i64) = (min.trim().parse()?, max.trim().parse()?);
+                    if min > max {
+                        bail!("random_int min > max: {}", placeholder);
+                    }
+                    thread_rng().gen_range(min..=max).to_string()
+                } else {
+                    bail!("unknown placeholder: {}", placeholder);
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::util::template::TemplateContext;
+
+    #[test]
+    fn test_expand() {
+        let mut ctx = TemplateContext::new("id_1234".to_string());
+        ctx.seq = 7;
+        assert_eq!(
+            ctx.expand("device/{{client_id}}/{{ seq }}").unwrap(),
+            "device/id_1234/7"
+        );
+        let val: i64 = ctx
+            .expand("{{random_int(0,100)}}")
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((0..=100).contains(&val));
+        assert_eq!(ctx.expand("{{uuid}}").unwrap().len(), 36);
+        assert!(ctx
+            .expand(r#"{"ts": {{timestamp}}}"#)
+            .unwrap()
+            .starts_with(r#"{"ts": 1"#));
+        assert!(ctx.expand("{{unknown}}").is_err());
+        assert!(ctx.expand("{{seq").is_err());
+    }
+}
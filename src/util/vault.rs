@@ -0,0 +1,172 @@
+//! Encryption of the credentials at rest with a master password.
+//!
+//! The key is derived from the master password by argon2 with a random salt, and a known text
+//! encrypted by the key tells whether a master password is right. A secret is stored as
+//! `enc:` and the base64 of the random nonce and the ciphertext of chacha20poly1305.
+//! Without a master password the secrets are stored as they are.
+use anyhow::{anyhow, bail, Result};
+use argon2::Argon2;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chacha20poly1305::aead::Aead;
+use chacha20poly1305::{ChaCha20Poly1305, Key, KeyInit, Nonce};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+const SECRET_PREFIX: &str = "enc:";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const CHECK: &str = "for-mqtt";
+
+/// stored in the db, see `ArcDb::set_vault`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VaultMeta {
+    salt: String,
+    check: String,
+}
+
+#[derive(Clone)]
+pub struct VaultKey(Arc<ChaCha20Poly1305>);
+
+#[derive(Debug, Clone, Default)]
+pub struct Vault {
+    pub meta: Option<VaultMeta>,
+    key: Option<VaultKey>,
+}
+
+impl VaultMeta {
+    pub fn create(master: &str) -> Result<(Self, VaultKey)> {
+        if master.is_empty() {
+            bail!("the master password is empty");
+        }
+        let salt: [u8; SALT_LEN] = rand::random();
+        let key = VaultKey::derive(master, &salt)?;
+        let meta = Self {
+            salt: STANDARD.encode(salt),
+            check: key.encrypt(CHECK)?,
+        };
+        Ok((meta, key))
+    }
+
+    pub fn unlock(&self, master: &str) -> Result<VaultKey> {
+        let salt = STANDARD.decode(self.salt.as_str())?;
+        let key = VaultKey::derive(master, &salt)?;
+        match key.decrypt(self.check.as_str()) {
+            Ok(check) if check == CHECK => Ok(key),
+            _ => bail!("wrong master password"),
+        }
+    }
+}
+
+impl VaultKey {
+    fn derive(master: &str, salt: &[u8]) -> Result<Self> {
+        let mut key = Key::default();
+        Argon2::default()
+            .hash_password_into(master.as_bytes(), salt, key.as_mut_slice())
+            .map_err(|e| anyhow!("{}", e))?;
+        Ok(Self(Arc::new(ChaCha20Poly1305::new(&key))))
+    }
+
+    pub fn encrypt(&self, plain: &str) -> Result<String> {
+        let nonce: [u8; NONCE_LEN] = rand::random();
+        let mut data = self
+            .0
+            .encrypt(Nonce::from_slice(&nonce), plain.as_bytes())
+            .map_err(|e| anyhow!("{}", e))?;
+        data.splice(0..0, nonce);
+        Ok(format!("{}{}", SECRET_PREFIX, STANDARD.encode(data)))
+    }
+
+    pub fn decrypt(&self, secret: &str) -> Result<String> {
+        let Some(data) = secret.strip_prefix(SECRET_PREFIX) else {
+            bail!("the secret isn't encrypted");
+        };
+        let data = STANDARD.decode(data)?;
+        if data.len() < NONCE_LEN {
+            bail!("the secret is too short");
+        }
+        let (nonce, data) = data.split_at(NONCE_LEN);
+        let plain = self
+            .0
+            .decrypt(Nonce::from_slice(nonce), data)
+            .map_err(|e| anyhow!("{}", e))?;
+        Ok(String::from_utf8(plain)?)
+    }
+}
+
+impl Debug for VaultKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("VaultKey(..)")
+    }
+}
+
+impl Vault {
+    pub fn new(meta: Option<VaultMeta>) -> Self {
+        Self { meta, key: None }
+    }
+    pub fn is_enabled(&self) -> bool {
+        self.meta.is_some()
+    }
+    pub fn is_locked(&self) -> bool {
+        self.meta.is_some() && self.key.is_none()
+    }
+    pub fn unlocked(&mut self, meta: VaultMeta, key: VaultKey) {
+        self.meta = Some(meta);
+        self.key = Some(key);
+    }
+    /// the secret to store
+    pub fn seal(&self, plain: &str) -> Result<String> {
+        if plain.is_empty() {
+            return Ok(String::new());
+        }
+        match &self.key {
+            Some(key) => key.encrypt(plain),
+            None if self.is_enabled() => bail!("unlock the credentials first"),
+            None => Ok(plain.to_string()),
+        }
+    }
+    /// the plain text of the stored secret, empty while locked
+    pub fn open(&self, secret: &str) -> Result<String> {
+        if !is_encrypted(secret) {
+            return Ok(secret.to_string());
+        }
+        match &self.key {
+            Some(key) => key.decrypt(secret),
+            None => Ok(String::new()),
+        }
+    }
+}
+
+pub fn is_encrypted(secret: &str) -> bool {
+    secret.starts_with(SECRET_PREFIX)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::util::vault::{is_encrypted, Vault, VaultMeta};
+
+    #[test]
+    fn test_vault() {
+        let (meta, _) = VaultMeta::create("master").unwrap();
+        assert!(meta.unlock("wrong").is_err());
+        let key = meta.unlock("master").unwrap();
+
+        let mut vault = Vault::new(Some(meta.clone()));
+        assert!(vault.is_locked());
+        assert!(vault.seal("public").is_err());
+        vault.unlocked(meta.clone(), key);
+        let secret = vault.seal("public").unwrap();
+        assert!(is_encrypted(&secret));
+        assert_ne!(secret, vault.seal("public").unwrap());
+        assert_eq!(vault.open(&secret).unwrap(), "public");
+        assert_eq!(vault.seal("").unwrap(), "");
+        // the legacy plain text
+        assert_eq!(vault.open("plain").unwrap(), "plain");
+        assert_eq!(Vault::new(Some(meta)).open(&secret).unwrap(), "");
+
+        let (_, other) = VaultMeta::create("master").unwrap();
+        assert!(other.decrypt(&secret).is_err());
+        assert_eq!(Vault::default().seal("public").unwrap(), "public");
+    }
+}